@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::sync::Mutex;
+use crate::log_forward;
+use crate::trace::RequestRecord;
+
+// Same fields and order as the nginx formats of the same name
+pub const COMMON_TEMPLATE: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#;
+pub const COMBINED_TEMPLATE: &str =
+    r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+// Open log and the settings it was opened with, None while the server is stopped
+static ACCESS_LOG: Mutex<Option<AccessLog>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    Common,
+    Combined,
+    // Uses the template from the configuration
+    Custom,
+    Json,
+}
+
+impl fmt::Display for AccessLogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessLogFormat::Common => write!(f, "Common"),
+            AccessLogFormat::Combined => write!(f, "Combined"),
+            AccessLogFormat::Custom => write!(f, "Custom"),
+            AccessLogFormat::Json => write!(f, "JSON lines"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessLogConfig {
+    pub enabled: bool,
+    pub format: AccessLogFormat,
+    // Only used by the custom format, e.g. "$remote_addr $request_id $status"
+    pub template: String,
+    // File the log is appended to, empty writes to standard output
+    pub path: String,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            format: AccessLogFormat::Combined,
+            template: COMBINED_TEMPLATE.to_string(),
+            path: String::new(),
+        }
+    }
+}
+
+impl AccessLogConfig {
+    // Template the text formats are written with, None for JSON lines
+    fn template(&self) -> Option<&str> {
+        match self.format {
+            AccessLogFormat::Common => Some(COMMON_TEMPLATE),
+            AccessLogFormat::Combined => Some(COMBINED_TEMPLATE),
+            AccessLogFormat::Custom => Some(&self.template),
+            AccessLogFormat::Json => None,
+        }
+    }
+}
+
+// Values a template can refer to, named after their nginx counterparts
+#[derive(Clone, Debug, PartialEq)]
+enum Variable {
+    RemoteAddr,
+    RemoteUser,
+    TimeLocal,
+    TimeIso8601,
+    Request,
+    Method,
+    Uri,
+    Protocol,
+    Status,
+    BodyBytesSent,
+    // Seconds with millisecond precision
+    RequestTime,
+    RequestId,
+    TraceId,
+    Listener,
+    Route,
+    // Any request header, "$http_user_agent" reads User-Agent
+    Header(String),
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Variable> {
+        let variable = match name {
+            "remote_addr" => Variable::RemoteAddr,
+            "remote_user" => Variable::RemoteUser,
+            "time_local" => Variable::TimeLocal,
+            "time_iso8601" => Variable::TimeIso8601,
+            "request" => Variable::Request,
+            "request_method" => Variable::Method,
+            "request_uri" => Variable::Uri,
+            "server_protocol" => Variable::Protocol,
+            "status" => Variable::Status,
+            "body_bytes_sent" => Variable::BodyBytesSent,
+            "request_time" => Variable::RequestTime,
+            "request_id" => Variable::RequestId,
+            "trace_id" => Variable::TraceId,
+            "listener" => Variable::Listener,
+            "route" => Variable::Route,
+            _ => match name.strip_prefix("http_") {
+                Some(header) if !header.is_empty() => Variable::Header(header.replace('_', "-")),
+                _ => return None,
+            },
+        };
+        Some(variable)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(Variable),
+}
+
+// Split a template into text and variables. Variables are written "$name" or
+// "${name}" when followed by a letter, "$$" is a literal dollar sign.
+fn parse_template(template: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if c != '$' {
+            text.push(c);
+            continue;
+        }
+        let name = match chars.peek() {
+            Some((_, '$')) => {
+                chars.next();
+                text.push('$');
+                continue;
+            }
+            Some((_, '{')) => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => name.push(c),
+                        None => return Err(format!("'${{{}' is missing its closing brace", name)),
+                    }
+                }
+                name
+            }
+            _ => {
+                let mut name = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                name
+            }
+        };
+        if name.is_empty() {
+            return Err("'$' must be followed by a variable name, use '$$' for a dollar sign".to_string());
+        }
+        let variable = Variable::parse(&name.to_ascii_lowercase()).ok_or_else(|| format!("unknown variable '${}'", name))?;
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        segments.push(Segment::Variable(variable));
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+// Check a custom template, for configuration validation
+pub fn check_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("template is empty".to_string());
+    }
+    parse_template(template).map(|_| ())
+}
+
+struct AccessLog {
+    config: AccessLogConfig,
+    // None for JSON lines
+    segments: Option<Vec<Segment>>,
+    // None when only forwarding
+    output: Option<Box<dyn Write + Send>>,
+}
+
+// Open, reopen or close the access log. Reopening after a reload also picks up a rotated file.
+pub fn apply_access_log(config: &AccessLogConfig) {
+    let mut log = ACCESS_LOG.lock().unwrap();
+    if log.as_ref().is_some_and(|log| &log.config == config) {
+        return;
+    }
+    *log = None;
+
+    // Validation rejects bad templates, fall back to Combined if one slips through
+    let segments = config.template().map(|template| {
+        parse_template(template).unwrap_or_else(|e| {
+            eprintln!("Invalid access log template, using the Combined format: {}", e);
+            parse_template(COMBINED_TEMPLATE).unwrap()
+        })
+    });
+    let output: Option<Box<dyn Write + Send>> = if !config.enabled {
+        None
+    } else if config.path.is_empty() {
+        Some(Box::new(io::stdout()))
+    } else {
+        match OpenOptions::new().create(true).append(true).open(&config.path) {
+            Ok(file) => Some(Box::new(LineWriter::new(file))),
+            Err(e) => {
+                eprintln!("Failed to open access log {}, writing to standard output: {}", config.path, e);
+                Some(Box::new(io::stdout()))
+            }
+        }
+    };
+    *log = Some(AccessLog { config: config.clone(), segments, output });
+}
+
+pub fn clear_access_log() {
+    *ACCESS_LOG.lock().unwrap() = None;
+}
+
+// Write the line for a finished request, and forward it when forwarding is set up
+pub fn log_request(record: &RequestRecord) {
+    let mut log = ACCESS_LOG.lock().unwrap();
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+    let line = match &log.segments {
+        Some(segments) => format_line(segments, record),
+        None => json_line(record),
+    };
+    if let Some(output) = log.output.as_mut() {
+        if let Err(e) = writeln!(output, "{}", line) {
+            eprintln!("Failed to write access log: {}", e);
+        }
+    }
+    log_forward::access_message(record, &line);
+}
+
+fn format_line(segments: &[Segment], record: &RequestRecord) -> String {
+    let mut line = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => line.push_str(text),
+            Segment::Variable(variable) => write_variable(&mut line, variable, record),
+        }
+    }
+    line
+}
+
+fn write_variable(line: &mut String, variable: &Variable, record: &RequestRecord) {
+    let time = chrono::DateTime::<chrono::Local>::from(record.started);
+    let _ = match variable {
+        Variable::RemoteAddr => write!(line, "{}", record.remote.map_or("-".to_string(), |remote| remote.ip().to_string())),
+        Variable::RemoteUser => write!(line, "{}", record.user.as_deref().map_or("-".to_string(), escape)),
+        Variable::TimeLocal => write!(line, "{}", time.format("%d/%b/%Y:%H:%M:%S %z")),
+        Variable::TimeIso8601 => write!(line, "{}", time.format("%Y-%m-%dT%H:%M:%S%:z")),
+        Variable::Request => write!(line, "{} {} {}", record.method, escape(&record.uri), PROTOCOL),
+        Variable::Method => write!(line, "{}", record.method),
+        Variable::Uri => write!(line, "{}", escape(&record.uri)),
+        Variable::Protocol => write!(line, "{}", PROTOCOL),
+        Variable::Status => write!(line, "{}", record.status),
+        Variable::BodyBytesSent => write!(line, "{}", record.response_bytes),
+        Variable::RequestTime => write!(line, "{:.3}", record.timing.total.as_secs_f64()),
+        Variable::RequestId => write!(line, "{}", record.request_id),
+        Variable::TraceId => write!(line, "{}", record.trace_id),
+        Variable::Listener => write!(line, "{}", escape(&record.listener)),
+        Variable::Route => write!(line, "{}", record.route.as_deref().map_or("-".to_string(), escape)),
+        Variable::Header(name) => write!(line, "{}", request_header(record, name).map_or("-".to_string(), escape)),
+    };
+}
+
+// Rocket does not tell which HTTP version a request used
+const PROTOCOL: &str = "HTTP/1.1";
+
+fn json_line(record: &RequestRecord) -> String {
+    let time = chrono::DateTime::<chrono::Local>::from(record.started);
+    json!({
+        "time": time.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        "remote_addr": record.remote.map(|remote| remote.ip().to_string()),
+        "remote_user": record.user,
+        "method": record.method,
+        "uri": record.uri,
+        "protocol": PROTOCOL,
+        "status": record.status,
+        "body_bytes_sent": record.response_bytes,
+        "referer": request_header(record, "referer"),
+        "user_agent": request_header(record, "user-agent"),
+        "request_time": record.timing.total.as_secs_f64(),
+        "request_id": record.request_id,
+        "trace_id": record.trace_id,
+        "listener": record.listener,
+        "route": record.route,
+        "aborted": record.aborted,
+    })
+    .to_string()
+}
+
+fn request_header<'a>(record: &'a RequestRecord, name: &str) -> Option<&'a str> {
+    record
+        .request_headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// Client supplied text must not break the quoting or add lines, as in Apache's logs
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
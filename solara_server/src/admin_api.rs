@@ -0,0 +1,514 @@
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Responder, Response};
+use rocket::route::Route;
+use rocket::{delete, get, patch, post, put, routes, Request};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::RwLock;
+use crate::auth::{self, Identity};
+use crate::certs;
+use crate::config;
+use crate::connections;
+use crate::control::ServerController;
+use crate::jwt;
+use crate::logs;
+use crate::metrics;
+use crate::notifications::Severity;
+use crate::rbac::{self, AccessRule};
+use crate::server::{self, ListenerSummary, Protocol, ServerConfig};
+use crate::sessions;
+use crate::trace::{self, RequestRecord};
+use crate::users::{self, UserError};
+
+// Controller of the server in this process, set by the GUI once it has created it
+static CONTROLLER: RwLock<Option<ServerController>> = RwLock::new(None);
+
+const MAX_BODY_MIB: u32 = 1;
+pub const DEFAULT_LOG_LIMIT: usize = 100;
+
+// JSON API for remote control, served under /api on admin listeners
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminApiConfig {
+    pub enabled: bool,
+    // Roles allowed to use the API, any one of them is enough
+    pub roles: Vec<String>,
+}
+
+impl Default for AdminApiConfig {
+    fn default() -> Self {
+        Self { enabled: false, roles: vec!["admin".to_string()] }
+    }
+}
+
+pub fn attach(controller: ServerController) {
+    *CONTROLLER.write().unwrap() = Some(controller);
+}
+
+fn controller() -> Result<ServerController, ApiError> {
+    CONTROLLER
+        .read()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "The server is not under control of this process"))
+}
+
+fn running_config() -> Result<ServerConfig, ApiError> {
+    controller()?.running_config().ok_or_else(|| ApiError::new(Status::ServiceUnavailable, "The server is not running"))
+}
+
+// Error answer with a JSON body
+#[derive(Debug)]
+pub struct ApiError {
+    status: Status,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: Status, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(Status::BadRequest, message)
+    }
+
+    fn conflict(message: impl Into<String>) -> Self {
+        Self::new(Status::Conflict, message)
+    }
+}
+
+impl From<UserError> for ApiError {
+    fn from(e: UserError) -> Self {
+        let status = match e {
+            UserError::UnknownUser(_) => Status::NotFound,
+            UserError::UserExists(_) => Status::Conflict,
+            UserError::InvalidUsername | UserError::InvalidRole(_) | UserError::PasswordTooShort => Status::BadRequest,
+            UserError::NoStore => Status::ServiceUnavailable,
+            UserError::Io(_) | UserError::Parse(_) | UserError::Serialize(_) => Status::InternalServerError,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let body = json!({ "error": self.message });
+        Response::build_from((ContentType::JSON, body.to_string()).respond_to(req)?).status(self.status).ok()
+    }
+}
+
+type ApiResult = Result<(Status, (ContentType, String)), ApiError>;
+
+fn reply(status: Status, value: Value) -> ApiResult {
+    Ok((status, (ContentType::JSON, value.to_string())))
+}
+
+fn ok(value: Value) -> ApiResult {
+    reply(Status::Ok, value)
+}
+
+async fn read_json<T: DeserializeOwned>(data: Data<'_>) -> Result<T, ApiError> {
+    let body = match metrics::read_body(data, MAX_BODY_MIB.mebibytes()).await {
+        Ok(body) if body.is_complete() => body.into_inner(),
+        Ok(_) => return Err(ApiError::new(Status::PayloadTooLarge, format!("Bodies are limited to {} MiB", MAX_BODY_MIB))),
+        Err(e) => return Err(ApiError::bad_request(format!("Failed to read the body: {}", e))),
+    };
+    serde_json::from_slice(&body).map_err(|e| ApiError::bad_request(format!("Invalid JSON: {}", e)))
+}
+
+// Caller holding one of the admin roles. Credentials are whatever the protected routes
+// accept: API keys, bearer tokens, Basic auth or a session.
+pub struct Admin(Identity);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ApiError> {
+        let roles = match running_config() {
+            Ok(config) => config.admin_api.roles,
+            Err(e) => return request::Outcome::Error((e.status, e)),
+        };
+        let Some(identity) = auth::current_user(req).await else {
+            let e = ApiError::new(Status::Unauthorized, "Authentication required");
+            return request::Outcome::Error((e.status, e));
+        };
+        if !identity.roles.iter().any(|role| roles.contains(role)) {
+            let e = ApiError::new(Status::Forbidden, format!("'{}' may not use the admin API", identity.username));
+            return request::Outcome::Error((e.status, e));
+        }
+        request::Outcome::Success(Admin(identity))
+    }
+}
+
+impl Admin {
+    // Changes made through the API show up in the server log with the caller
+    fn log(&self, action: &str) {
+        if let Ok(controller) = controller() {
+            controller.log(format!("Admin API: {} ({})", action, self.0.username));
+        }
+    }
+}
+
+// There are no virtual host endpoints: Solara has no virtual hosts yet, every listener
+// serves the same site. They belong here once the Virtual Hosting items in task.md land.
+pub fn api_routes() -> Vec<Route> {
+    routes![
+        status, stats, start, stop, reload, get_config, put_config, validate, query_logs,
+        list_connections, close_connection, recent_requests, list_users, add_user, change_user, delete_user, get_rules, put_rules,
+        list_certificates, install_certificate,
+    ]
+}
+
+#[get("/api/status")]
+fn status(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    ok(status_json(&controller()?))
+}
+
+// Overall status and each listener with its own, also answered on the control socket
+pub fn status_json(controller: &ServerController) -> Value {
+    let listeners: Vec<Value> = controller
+        .listeners()
+        .into_iter()
+        .map(|state| {
+            let mut listener = serde_json::to_value(ListenerSummary::from(&state.config)).unwrap_or_default();
+            listener["status"] = json!(state.status.to_string());
+            listener
+        })
+        .collect();
+    json!({ "status": controller.status().to_string(), "listeners": listeners })
+}
+
+#[get("/api/stats")]
+fn stats(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    ok(serde_json::to_value(server::get_server_stats()).unwrap_or_default())
+}
+
+// Admin listeners keep running so the site can be started again from here
+#[post("/api/server/start")]
+fn start(admin: Result<Admin, ApiError>) -> ApiResult {
+    let admin = admin?;
+    controller()?.start_site().map_err(ApiError::conflict)?;
+    admin.log("started site listeners");
+    reply(Status::Accepted, json!({ "status": "starting" }))
+}
+
+#[post("/api/server/stop")]
+fn stop(admin: Result<Admin, ApiError>) -> ApiResult {
+    let admin = admin?;
+    controller()?.stop_site().map_err(ApiError::conflict)?;
+    admin.log("stopped site listeners");
+    reply(Status::Accepted, json!({ "status": "stopping" }))
+}
+
+// Apply the running configuration again, re-reading the users, keys and other files it refers to
+#[post("/api/server/reload")]
+fn reload(admin: Result<Admin, ApiError>) -> ApiResult {
+    let admin = admin?;
+    let kind = controller()?.reload(running_config()?).map_err(ApiError::conflict)?;
+    admin.log("reloaded the configuration");
+    ok(json!({ "reload": kind.to_string() }))
+}
+
+#[get("/api/config")]
+fn get_config(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    ok(serde_json::to_value(running_config()?).unwrap_or_default())
+}
+
+// Replace the running configuration, missing fields take their defaults as in a config file
+#[put("/api/config", data = "<data>")]
+async fn put_config(admin: Result<Admin, ApiError>, data: Data<'_>) -> ApiResult {
+    let admin = admin?;
+    let config: ServerConfig = read_json(data).await?;
+    apply(&admin, config, "replaced the configuration")
+}
+
+fn apply(admin: &Admin, config: ServerConfig, action: &str) -> ApiResult {
+    let report = config::validate_config(&config);
+    if !report.is_valid() {
+        return reply(Status::UnprocessableEntity, report_json(&report));
+    }
+    let kind = controller()?.reload_remote(config).map_err(ApiError::conflict)?;
+    admin.log(action);
+    let warnings: Vec<String> = report.warnings.iter().map(|w| w.to_string()).collect();
+    ok(json!({ "reload": kind.to_string(), "warnings": warnings }))
+}
+
+pub fn report_json(report: &config::ValidationReport) -> Value {
+    json!({
+        "valid": report.is_valid(),
+        "errors": report.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        "warnings": report.warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+#[post("/api/config/validate", data = "<data>")]
+async fn validate(admin: Result<Admin, ApiError>, data: Data<'_>) -> ApiResult {
+    admin?;
+    let config: ServerConfig = read_json(data).await?;
+    ok(report_json(&config::validate_config(&config)))
+}
+
+// Latest server messages, e.g. /api/logs?level=warning&filter=listener&limit=50.
+// Passing the last "seq" seen as `after` only returns newer messages.
+#[get("/api/logs?<level>&<filter>&<after>&<limit>")]
+fn query_logs(
+    admin: Result<Admin, ApiError>,
+    level: Option<&str>,
+    filter: Option<&str>,
+    after: Option<u64>,
+    limit: Option<usize>,
+) -> ApiResult {
+    admin?;
+    let level = parse_level(level).map_err(ApiError::bad_request)?;
+    ok(log_entries(filter.unwrap_or_default(), level, after.unwrap_or(0), limit.unwrap_or(DEFAULT_LOG_LIMIT)))
+}
+
+pub fn parse_level(level: Option<&str>) -> Result<Option<Severity>, String> {
+    match level.map(str::to_ascii_lowercase).as_deref() {
+        None | Some("") => Ok(None),
+        Some("info") => Ok(Some(Severity::Info)),
+        Some("warning") => Ok(Some(Severity::Warning)),
+        Some("error") => Ok(Some(Severity::Error)),
+        Some(other) => Err(format!("Unknown level '{}', use info, warning or error", other)),
+    }
+}
+
+pub fn log_entries(filter: &str, level: Option<Severity>, after: u64, limit: usize) -> Value {
+    let entries: Vec<Value> = logs::recent(filter, level, after, limit)
+        .into_iter()
+        .map(|(seq, entry)| {
+            let mut value = entry.to_json();
+            value["seq"] = json!(seq);
+            value
+        })
+        .collect();
+    json!({ "entries": entries })
+}
+
+#[get("/api/connections")]
+fn list_connections(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    ok(json!({ "connections": connections::open_connections() }))
+}
+
+#[post("/api/connections/<id>/close")]
+fn close_connection(admin: Result<Admin, ApiError>, id: u64) -> ApiResult {
+    let admin = admin?;
+    if !connections::close_connection(id) {
+        return Err(ApiError::new(Status::NotFound, format!("Connection {} is not open", id)));
+    }
+    admin.log(&format!("closed connection {}", id));
+    reply(Status::Accepted, json!({ "id": id }))
+}
+
+// Finished requests with their headers and timing, oldest first
+#[get("/api/requests")]
+fn recent_requests(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    let recent = trace::recent_requests();
+    let requests: Vec<&RequestRecord> = recent.iter().map(|record| record.as_ref()).collect();
+    ok(json!({ "requests": requests }))
+}
+
+#[get("/api/users")]
+fn list_users(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    let users: Vec<Value> = users::users()
+        .into_iter()
+        .map(|user| json!({
+            "username": user.username,
+            "roles": user.roles,
+            "disabled": user.disabled,
+            "failed_logins": users::failed_logins(&user.username),
+            "locked_secs": users::locked_for(&user.username).map(|remaining| remaining.as_secs()),
+        }))
+        .collect();
+    ok(json!({ "users": users }))
+}
+
+#[derive(Deserialize)]
+struct NewUser {
+    username: String,
+    password: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+#[post("/api/users", data = "<data>")]
+async fn add_user(admin: Result<Admin, ApiError>, data: Data<'_>) -> ApiResult {
+    let admin = admin?;
+    let NewUser { username, password, roles } = read_json(data).await?;
+    // Hashing the password takes a while
+    let name = username.clone();
+    tokio::task::spawn_blocking(move || {
+        users::add_user(&name, &password)?;
+        if roles.is_empty() { Ok(()) } else { users::set_roles(&name, roles) }
+    })
+    .await
+    .map_err(|e| ApiError::new(Status::InternalServerError, e.to_string()))??;
+    admin.log(&format!("added user '{}'", username));
+    reply(Status::Created, json!({ "username": username }))
+}
+
+// Fields left out stay as they are
+#[derive(Deserialize)]
+struct UserChange {
+    password: Option<String>,
+    roles: Option<Vec<String>>,
+    disabled: Option<bool>,
+    #[serde(default)]
+    unlock: bool,
+}
+
+#[patch("/api/users/<username>", data = "<data>")]
+async fn change_user(admin: Result<Admin, ApiError>, username: &str, data: Data<'_>) -> ApiResult {
+    let admin = admin?;
+    let change: UserChange = read_json(data).await?;
+    if !users::users().iter().any(|user| user.username == username) {
+        return Err(UserError::UnknownUser(username.to_string()).into());
+    }
+    if let Some(password) = change.password {
+        let name = username.to_string();
+        tokio::task::spawn_blocking(move || users::set_password(&name, &password))
+            .await
+            .map_err(|e| ApiError::new(Status::InternalServerError, e.to_string()))??;
+        // Like a password change in the browser, other logins have to start over
+        sessions::revoke_user(username, None);
+    }
+    if let Some(roles) = change.roles {
+        users::set_roles(username, roles)?;
+    }
+    if let Some(disabled) = change.disabled {
+        if disabled {
+            sessions::revoke_user(username, None);
+        }
+        users::set_disabled(username, disabled)?;
+    }
+    if change.unlock {
+        users::unlock(username);
+    }
+    admin.log(&format!("changed user '{}'", username));
+    ok(json!({ "username": username }))
+}
+
+#[delete("/api/users/<username>")]
+fn delete_user(admin: Result<Admin, ApiError>, username: &str) -> ApiResult {
+    let admin = admin?;
+    sessions::revoke_user(username, None);
+    users::remove_user(username)?;
+    admin.log(&format!("deleted user '{}'", username));
+    Ok((Status::NoContent, (ContentType::JSON, String::new())))
+}
+
+#[get("/api/rules")]
+fn get_rules(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    ok(json!({ "rules": running_config()?.auth.rules }))
+}
+
+// Replace the access rules, first match wins as in the configuration file
+#[put("/api/rules", data = "<data>")]
+async fn put_rules(admin: Result<Admin, ApiError>, data: Data<'_>) -> ApiResult {
+    let admin = admin?;
+    let rules: Vec<AccessRule> = read_json(data).await?;
+    for (index, rule) in rules.iter().enumerate() {
+        rbac::check_rule(rule).map_err(|reason| ApiError::bad_request(format!("Rule #{} is invalid: {}", index + 1, reason)))?;
+    }
+    let mut config = running_config()?;
+    config.auth.rules = rules;
+    apply(&admin, config, "replaced the access rules")
+}
+
+#[get("/api/certificates")]
+fn list_certificates(admin: Result<Admin, ApiError>) -> ApiResult {
+    admin?;
+    let certificates: Vec<Value> = running_config()?
+        .listeners
+        .iter()
+        .filter(|listener| listener.protocol == Protocol::Https)
+        .map(|listener| {
+            let mut info = match certs::certificate_info(Path::new(&listener.tls.cert_path)) {
+                Ok(info) => info,
+                Err(e) => json!({ "error": e }),
+            };
+            info["listener"] = json!(listener.name);
+            info["cert_path"] = json!(listener.tls.cert_path);
+            info["key_path"] = json!(listener.tls.key_path);
+            info
+        })
+        .collect();
+    ok(json!({ "certificates": certificates }))
+}
+
+// PEM texts of a renewed certificate chain and its private key
+#[derive(Deserialize)]
+struct CertificateUpload {
+    certificate: String,
+    private_key: String,
+}
+
+// Install a certificate for an HTTPS listener and restart the listener with it. The
+// previous files are kept next to the new ones with a ".bak" extension.
+#[put("/api/certificates/<listener>", data = "<data>")]
+async fn install_certificate(admin: Result<Admin, ApiError>, listener: &str, data: Data<'_>) -> ApiResult {
+    let admin = admin?;
+    let upload: CertificateUpload = read_json(data).await?;
+    let config = running_config()?;
+    let Some(target) = config.listeners.iter().find(|l| l.name == listener) else {
+        return Err(ApiError::new(Status::NotFound, format!("Listener '{}' does not exist", listener)));
+    };
+    if target.protocol != Protocol::Https {
+        return Err(ApiError::conflict(format!("Listener '{}' does not use HTTPS", listener)));
+    }
+    let info = check_certificate(upload.certificate.as_bytes(), upload.private_key.as_bytes()).map_err(ApiError::bad_request)?;
+
+    for (path, contents, private) in [
+        (&target.tls.cert_path, &upload.certificate, false),
+        (&target.tls.key_path, &upload.private_key, true),
+    ] {
+        replace_keeping_backup(Path::new(path), contents.as_bytes(), private)
+            .map_err(|e| ApiError::new(Status::InternalServerError, format!("Failed to write {}: {}", path, e)))?;
+    }
+    controller()?.restart_listener(listener).map_err(ApiError::conflict)?;
+    admin.log(&format!("installed a certificate for listener '{}'", listener));
+    reply(Status::Accepted, info)
+}
+
+// Parse the chain and make sure the key belongs to its first certificate, which has to be current
+fn check_certificate(chain: &[u8], key: &[u8]) -> Result<Value, String> {
+    let info = certs::check_key_pair(chain, key)?;
+    if info["not_after"].as_i64().is_some_and(|not_after| not_after < jwt::unix_now() as i64) {
+        return Err("the certificate has expired".to_string());
+    }
+    Ok(info)
+}
+
+// Private keys are only readable by the owner
+fn replace_keeping_backup(path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    if path.exists() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(path, backup)?;
+    }
+    let temp = path.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&temp)?.write_all(contents)?;
+    fs::rename(&temp, path)
+}
@@ -0,0 +1,476 @@
+use rocket::http::uri::Origin;
+use rocket::http::RawStr;
+use rocket::Request;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use crate::auth::{self, Identity};
+use crate::file_writer::{self, FileWriter};
+use crate::jwt;
+use crate::password;
+use crate::users;
+
+// Keys loaded from the keys file, shared by the GUI and the server
+static KEY_STORE: Mutex<Option<KeyStore>> = Mutex::new(None);
+
+// Settings of the running server, None while it is stopped
+static KEY_CONFIG: RwLock<Option<Arc<ApiKeyConfig>>> = RwLock::new(None);
+
+// Keys look like "sk_<id>_<secret>", the ID is not secret and names the key in listings
+pub const KEY_PREFIX: &str = "sk_";
+
+// Last use is written to the keys file at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeyConfig {
+    pub enabled: bool,
+    // TOML file with the key hashes and scopes, relative to the working directory
+    pub keys_file: String,
+    // Request header carrying the key
+    pub header: String,
+    // Query parameter carrying the key, empty to only accept the header. Keys in
+    // URLs end up in access logs and browser histories.
+    pub query_param: String,
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys_file: "api_keys.toml".to_string(),
+            header: "X-API-Key".to_string(),
+            query_param: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    // SHA-256 of the secret part. The secret is random, so a slow hash adds nothing.
+    pub secret_hash: String,
+    // Paths the key may be used on, empty for all
+    pub path_prefixes: Vec<String>,
+    // Methods the key may be used with, empty for all
+    pub methods: Vec<String>,
+    // Checked against the access rules
+    pub roles: Vec<String>,
+    // Unix seconds
+    pub created: u64,
+    pub expires: Option<u64>,
+    pub last_used: Option<u64>,
+    // Requests made with the key since the server started, filled in for listings
+    #[serde(skip)]
+    pub requests: u64,
+}
+
+impl ApiKey {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    pub fn covers(&self, method: &str, path: &str) -> bool {
+        let method_matches = self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method));
+        let path_matches = self.path_prefixes.is_empty() || self.path_prefixes.iter().any(|prefix| auth::path_has_prefix(path, prefix));
+        method_matches && path_matches
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct KeysFile {
+    keys: Vec<ApiKey>,
+}
+
+struct KeyStore {
+    path: PathBuf,
+    keys: Vec<ApiKey>,
+    // Requests per key ID
+    requests: HashMap<String, u64>,
+    // Last use not written to the keys file yet
+    dirty: bool,
+    last_save: Instant,
+    writer: FileWriter,
+}
+
+impl KeyStore {
+    fn contents(&mut self) -> Result<Vec<u8>, ApiKeyError> {
+        self.dirty = false;
+        self.last_save = Instant::now();
+        let contents = toml::to_string_pretty(&KeysFile { keys: self.keys.clone() }).map_err(ApiKeyError::Serialize)?;
+        Ok(contents.into_bytes())
+    }
+
+    // Write the keys file, after any usage still queued
+    fn save(&mut self) -> Result<(), ApiKeyError> {
+        let contents = self.contents()?;
+        self.writer.write_and_wait(contents).map_err(ApiKeyError::Io)
+    }
+
+    // Queue the usage without waiting for the disk, for the request path
+    fn save_usage(&mut self) {
+        match self.contents() {
+            Ok(contents) => self.writer.write(contents),
+            Err(e) => eprintln!("Failed to save API key usage to {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ApiKeyError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    NoStore,
+    InvalidName,
+    NameExists(String),
+    UnknownKey(String),
+    InvalidPathPrefix(String),
+    InvalidMethod(String),
+    InvalidRole(String),
+}
+
+impl fmt::Display for ApiKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiKeyError::Io(e) => write!(f, "I/O error: {}", e),
+            ApiKeyError::Parse(e) => write!(f, "Invalid keys file: {}", e),
+            ApiKeyError::Serialize(e) => write!(f, "Failed to serialize API keys: {}", e),
+            ApiKeyError::NoStore => write!(f, "No keys file is loaded"),
+            ApiKeyError::InvalidName => write!(f, "Key names must be non-empty and can't contain ':' or whitespace"),
+            ApiKeyError::NameExists(name) => write!(f, "A key named '{}' already exists", name),
+            ApiKeyError::UnknownKey(id) => write!(f, "Key '{}' does not exist", id),
+            ApiKeyError::InvalidPathPrefix(prefix) => write!(f, "Path prefix '{}' must start with '/'", prefix),
+            ApiKeyError::InvalidMethod(method) => write!(f, "'{}' is not an HTTP method", method),
+            ApiKeyError::InvalidRole(role) => write!(f, "Role '{}' is invalid, roles can't contain ',' or whitespace", role),
+        }
+    }
+}
+
+impl std::error::Error for ApiKeyError {}
+
+// Why a presented key was turned away
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyRejection {
+    Invalid,
+    Expired,
+    // The key is valid but not for this path or method
+    OutOfScope,
+}
+
+impl fmt::Display for KeyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRejection::Invalid => write!(f, "The API key is not valid"),
+            KeyRejection::Expired => write!(f, "The API key has expired"),
+            KeyRejection::OutOfScope => write!(f, "The API key does not cover this request"),
+        }
+    }
+}
+
+// Read the keys file. A missing file is an empty store, created on the first change.
+pub fn read_keys(path: &Path) -> Result<Vec<ApiKey>, ApiKeyError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str::<KeysFile>(&contents).map(|file| file.keys).map_err(ApiKeyError::Parse),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(ApiKeyError::Io(e)),
+    }
+}
+
+// Load the keys file into the shared store. Usage of keys that stay is kept.
+pub fn load_keys(path: &Path) -> Result<(), ApiKeyError> {
+    let mut guard = KEY_STORE.lock().unwrap();
+    if let Some(store) = guard.as_mut().filter(|store| store.path == path) {
+        if store.dirty {
+            store.save()?;
+        }
+        store.keys = read_keys(path)?;
+        return Ok(());
+    }
+    let keys = read_keys(path)?;
+    let writer = FileWriter::start("API keys", path, file_writer::write_private).map_err(ApiKeyError::Io)?;
+    if let Some(previous) = guard.take() {
+        previous.writer.finish();
+    }
+    *guard = Some(KeyStore { path: path.to_path_buf(), keys, requests: HashMap::new(), dirty: false, last_save: Instant::now(), writer });
+    Ok(())
+}
+
+pub fn apply_api_keys(config: &ApiKeyConfig) {
+    if let Err(e) = load_keys(Path::new(&config.keys_file)) {
+        eprintln!("Failed to load API keys from {}: {}", config.keys_file, e);
+    }
+    *KEY_CONFIG.write().unwrap() = Some(Arc::new(config.clone()));
+}
+
+// Write pending last use times before the server stops
+pub fn clear_api_keys() {
+    *KEY_CONFIG.write().unwrap() = None;
+    if let Some(store) = KEY_STORE.lock().unwrap().as_mut().filter(|store| store.dirty) {
+        if let Err(e) = store.save() {
+            eprintln!("Failed to save API key usage to {}: {}", store.path.display(), e);
+        }
+    }
+}
+
+pub fn keys_path() -> Option<PathBuf> {
+    KEY_STORE.lock().unwrap().as_ref().map(|store| store.path.clone())
+}
+
+pub fn keys() -> Vec<ApiKey> {
+    let store = KEY_STORE.lock().unwrap();
+    let Some(store) = store.as_ref() else {
+        return Vec::new();
+    };
+    store
+        .keys
+        .iter()
+        .map(|key| ApiKey { requests: store.requests.get(&key.id).copied().unwrap_or(0), ..key.clone() })
+        .collect()
+}
+
+// The key a request presents in the header or query parameter, None while keys are off
+pub fn presented<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    let config = KEY_CONFIG.read().unwrap().clone().filter(|config| config.enabled)?;
+    req.headers()
+        .get_one(&config.header)
+        .or_else(|| Some(config.query_param.as_str()).filter(|param| !param.is_empty()).and_then(|param| req.query_value(param)?.ok()))
+        .map(str::trim)
+}
+
+// The request URI with the value of the key query parameter hidden, for the access
+// log, the Connection Monitor and traces
+pub fn redacted_uri(uri: &Origin<'_>) -> String {
+    rewrite_key_param(uri, |name| Some(format!("{}=[redacted]", name)))
+}
+
+// The request URI without the key query parameter, for requests passed on to a backend
+pub fn uri_without_key(uri: &Origin<'_>) -> String {
+    rewrite_key_param(uri, |_| None)
+}
+
+// Replace each key query parameter with what `rewrite` makes of its name, or drop it
+fn rewrite_key_param(uri: &Origin<'_>, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let param = KEY_CONFIG.read().unwrap().as_ref().map(|config| config.query_param.clone()).filter(|param| !param.is_empty());
+    let (Some(param), Some(query)) = (param, uri.query()) else {
+        return uri.to_string();
+    };
+    let query = query
+        .as_str()
+        .split('&')
+        .filter_map(|pair| {
+            let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+            if RawStr::new(name).url_decode_lossy() == param {
+                rewrite(name)
+            } else {
+                Some(pair.to_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    if query.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), query)
+    }
+}
+
+// Whether a header carries keys, so its value stays out of the GUI and traces
+pub fn is_key_header(name: &str) -> bool {
+    KEY_CONFIG.read().unwrap().as_ref().is_some_and(|config| config.header.eq_ignore_ascii_case(name))
+}
+
+// Check a presented key against the store and its scope, counting the use
+pub fn verify(presented: &str, method: &str, path: &str) -> Result<Identity, KeyRejection> {
+    let (id, secret) = presented.strip_prefix(KEY_PREFIX).and_then(|rest| rest.split_once('_')).ok_or(KeyRejection::Invalid)?;
+    let mut guard = KEY_STORE.lock().unwrap();
+    let store = guard.as_mut().ok_or(KeyRejection::Invalid)?;
+    let key = store.keys.iter_mut().find(|key| key.id == id).ok_or(KeyRejection::Invalid)?;
+    let hash = format!("{:x}", Sha256::digest(secret.as_bytes()));
+    if !password::constant_time_eq(hash.as_bytes(), key.secret_hash.as_bytes()) {
+        return Err(KeyRejection::Invalid);
+    }
+    let now = jwt::unix_now();
+    if key.is_expired(now) {
+        return Err(KeyRejection::Expired);
+    }
+    if !key.covers(method, path) {
+        return Err(KeyRejection::OutOfScope);
+    }
+
+    key.last_used = Some(now);
+    let identity = Identity { username: format!("key:{}", key.name), roles: key.roles.clone() };
+    *store.requests.entry(id.to_string()).or_insert(0) += 1;
+    store.dirty = true;
+    if store.last_save.elapsed() >= SAVE_INTERVAL {
+        store.save_usage();
+    }
+    Ok(identity)
+}
+
+// Apply a change to the store and write it back to the file
+fn modify<T>(change: impl FnOnce(&mut Vec<ApiKey>) -> Result<T, ApiKeyError>) -> Result<T, ApiKeyError> {
+    let mut guard = KEY_STORE.lock().unwrap();
+    let store = guard.as_mut().ok_or(ApiKeyError::NoStore)?;
+    let previous = store.keys.clone();
+    let result = change(&mut store.keys)?;
+    if let Err(e) = store.save() {
+        store.keys = previous;
+        return Err(e);
+    }
+    Ok(result)
+}
+
+// Create a key, returning it in full. Only its hash is kept, so it can't be shown again.
+pub fn generate(
+    name: &str,
+    path_prefixes: Vec<String>,
+    methods: Vec<String>,
+    roles: Vec<String>,
+    expires: Option<u64>,
+) -> Result<String, ApiKeyError> {
+    if !users::valid_username(name) {
+        return Err(ApiKeyError::InvalidName);
+    }
+    if let Some(prefix) = path_prefixes.iter().find(|prefix| !prefix.starts_with('/')) {
+        return Err(ApiKeyError::InvalidPathPrefix(prefix.clone()));
+    }
+    let methods: Vec<String> = methods.iter().map(|method| method.to_ascii_uppercase()).collect();
+    if let Some(method) = methods.iter().find(|method| method.parse::<rocket::http::Method>().is_err()) {
+        return Err(ApiKeyError::InvalidMethod(method.clone()));
+    }
+    if let Some(role) = roles.iter().find(|role| !users::valid_role(role)) {
+        return Err(ApiKeyError::InvalidRole(role.clone()));
+    }
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    let id = hex(&rand::random::<[u8; 6]>());
+    let secret = hex(&rand::random::<[u8; 32]>());
+    modify(|keys| {
+        if keys.iter().any(|key| key.name == name) {
+            return Err(ApiKeyError::NameExists(name.to_string()));
+        }
+        keys.push(ApiKey {
+            id: id.clone(),
+            name: name.to_string(),
+            secret_hash: format!("{:x}", Sha256::digest(secret.as_bytes())),
+            path_prefixes,
+            methods,
+            roles,
+            created: jwt::unix_now(),
+            expires,
+            last_used: None,
+            requests: 0,
+        });
+        Ok(())
+    })?;
+    Ok(format!("{}{}_{}", KEY_PREFIX, id, secret))
+}
+
+pub fn revoke(id: &str) -> Result<(), ApiKeyError> {
+    modify(|keys| {
+        let before = keys.len();
+        keys.retain(|key| key.id != id);
+        if keys.len() == before {
+            return Err(ApiKeyError::UnknownKey(id.to_string()));
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(path_prefixes: &[&str], methods: &[&str]) -> ApiKey {
+        ApiKey {
+            path_prefixes: path_prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+            methods: methods.iter().map(|method| method.to_string()).collect(),
+            ..ApiKey::default()
+        }
+    }
+
+    #[test]
+    fn scope_limits_paths_and_methods() {
+        assert!(key(&[], &[]).covers("DELETE", "/anything"));
+        let scoped = key(&["/api/reports"], &["GET", "HEAD"]);
+        assert!(scoped.covers("GET", "/api/reports"));
+        assert!(scoped.covers("head", "/api/reports/2024"));
+        assert!(!scoped.covers("POST", "/api/reports"));
+        assert!(!scoped.covers("GET", "/api/reportsx"));
+        assert!(!scoped.covers("GET", "/api"));
+        assert!(ApiKey { expires: Some(100), ..ApiKey::default() }.is_expired(100));
+        assert!(!ApiKey { expires: Some(101), ..ApiKey::default() }.is_expired(100));
+    }
+
+    #[test]
+    fn keys_are_stored_hashed_and_checked_against_their_scope() {
+        let dir = std::env::temp_dir().join(format!("solara-api-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api_keys.toml");
+        load_keys(&path).unwrap();
+
+        let full = generate("reports", vec!["/api/reports".to_string()], vec!["get".to_string()], vec!["viewer".to_string()], None).unwrap();
+        let expired = generate("old", Vec::new(), Vec::new(), Vec::new(), Some(1)).unwrap();
+        assert!(matches!(generate("reports", Vec::new(), Vec::new(), Vec::new(), None), Err(ApiKeyError::NameExists(_))));
+        assert!(matches!(generate("bad", vec!["api".to_string()], Vec::new(), Vec::new(), None), Err(ApiKeyError::InvalidPathPrefix(_))));
+
+        let (id, secret) = full.strip_prefix(KEY_PREFIX).unwrap().split_once('_').unwrap();
+        let stored = fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains(secret));
+        assert!(stored.contains(&format!("{:x}", Sha256::digest(secret.as_bytes()))));
+
+        let identity = verify(&full, "GET", "/api/reports/1").unwrap();
+        assert_eq!(identity.username, "key:reports");
+        assert_eq!(identity.roles, vec!["viewer"]);
+        assert_eq!(verify(&full, "POST", "/api/reports/1").unwrap_err(), KeyRejection::OutOfScope);
+        assert_eq!(verify(&full, "GET", "/admin").unwrap_err(), KeyRejection::OutOfScope);
+        assert_eq!(verify(&format!("{}{}_{}", KEY_PREFIX, id, "0".repeat(64)), "GET", "/api/reports").unwrap_err(), KeyRejection::Invalid);
+        assert_eq!(verify(&expired, "GET", "/").unwrap_err(), KeyRejection::Expired);
+        assert_eq!(verify("not-a-key", "GET", "/").unwrap_err(), KeyRejection::Invalid);
+
+        let listed = keys().into_iter().find(|key| key.id == id).unwrap();
+        assert_eq!(listed.requests, 1);
+        assert!(listed.last_used.is_some());
+
+        revoke(id).unwrap();
+        assert_eq!(verify(&full, "GET", "/api/reports").unwrap_err(), KeyRejection::Invalid);
+        *KEY_STORE.lock().unwrap() = None;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn key_query_parameter_is_redacted() {
+        *KEY_CONFIG.write().unwrap() = Some(Arc::new(ApiKeyConfig {
+            query_param: "api_key".to_string(),
+            header: "X-Token".to_string(),
+            ..ApiKeyConfig::default()
+        }));
+        let redacted = |uri: &str| redacted_uri(&Origin::parse(uri).unwrap());
+        assert_eq!(redacted("/reports?api_key=sk_1_secret&page=2"), "/reports?api_key=[redacted]&page=2");
+        assert_eq!(redacted("/reports?page=2&%61pi_key=sk_1_secret"), "/reports?page=2&%61pi_key=[redacted]");
+        assert_eq!(redacted("/reports?api_keys=public"), "/reports?api_keys=public");
+        assert_eq!(redacted("/reports"), "/reports");
+        let stripped = |uri: &str| uri_without_key(&Origin::parse(uri).unwrap());
+        assert_eq!(stripped("/reports?api_key=sk_1_secret&page=2"), "/reports?page=2");
+        assert_eq!(stripped("/reports?page=2&%61pi_key=sk_1_secret"), "/reports?page=2");
+        assert_eq!(stripped("/reports?api_key=sk_1_secret"), "/reports");
+        assert_eq!(stripped("/reports?api_keys=public"), "/reports?api_keys=public");
+        assert!(is_key_header("x-token"));
+        assert!(!is_key_header("X-API-Key"));
+        *KEY_CONFIG.write().unwrap() = None;
+    }
+}
@@ -0,0 +1,574 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rocket::data::Data;
+use rocket::form::{Form, FromForm};
+use rocket::http::uri::fmt::{Query, UriDisplay};
+use rocket::http::{ContentType, Cookie, CookieJar, Header, SameSite, Status};
+use rocket::time::{Duration as CookieDuration, OffsetDateTime};
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Redirect, Responder, Response};
+use rocket::route::{Handler, Outcome, Route};
+use rocket::{get, post, routes, Request};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use crate::api_keys::{self, ApiKeyConfig, KeyRejection};
+use crate::jwt::{self, JwtConfig, TokenError};
+use crate::oidc::{self, OidcConfig};
+use crate::rbac::{self, AccessRule};
+use crate::sessions::{self, SESSION_COOKIE};
+use crate::users::{self, LoginResult, Lockout, MIN_PASSWORD_LEN};
+
+// Settings of the running server, None while it is stopped
+static AUTH_CONFIG: RwLock<Option<Arc<AuthConfig>>> = RwLock::new(None);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    // TOML file with the users and their password hashes, relative to the working directory
+    pub users_file: String,
+    // Path prefixes that need a logged in user, e.g. "/private"
+    pub protected_paths: Vec<String>,
+    // Roles needed per path and method, checked before the protected paths
+    pub rules: Vec<AccessRule>,
+    // Bearer tokens from an identity provider, for API clients
+    pub jwt: JwtConfig,
+    // Browser logins through an OpenID Connect provider
+    pub oidc: OidcConfig,
+    // Long-lived keys for machine clients
+    pub api_keys: ApiKeyConfig,
+    // Shown by browsers in the Basic auth prompt
+    pub realm: String,
+    // Failed logins in a row before the account is locked, 0 disables the lockout
+    pub max_failed_logins: u32,
+    pub lockout_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            users_file: "users.toml".to_string(),
+            protected_paths: Vec::new(),
+            rules: Vec::new(),
+            jwt: JwtConfig::default(),
+            oidc: OidcConfig::default(),
+            api_keys: ApiKeyConfig::default(),
+            realm: "Solara".to_string(),
+            max_failed_logins: 5,
+            lockout_secs: 300,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn lockout(&self) -> Lockout {
+        Lockout { max_failures: self.max_failed_logins, duration: Duration::from_secs(self.lockout_secs) }
+    }
+
+    pub fn is_protected(&self, path: &str) -> bool {
+        self.protected_paths.iter().any(|prefix| path_has_prefix(path, prefix))
+    }
+}
+
+// "/private" covers "/private" and "/private/..." but not "/privateer"
+pub fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.is_empty(),
+        None => false,
+    }
+}
+
+// The path access checks see: percent-decoded, without empty and dot segments. Rocket routes
+// on decoded segments and backends behind the proxy resolve them, so "/%61dmin/x", "//admin/x"
+// and "/x/../admin/x" all have to be checked as "/admin/x".
+pub fn normalized_path(path: rocket::http::uri::Path<'_>) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    // A decoded segment may hold an encoded slash, which backends can take as a separator too
+    for part in path.segments().flat_map(|segment| segment.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            part => segments.push(part),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+pub fn apply_auth(config: &AuthConfig) {
+    if let Err(e) = users::load_users(Path::new(&config.users_file)) {
+        eprintln!("Failed to load users from {}: {}", config.users_file, e);
+    }
+    jwt::apply_jwt(&config.jwt);
+    oidc::apply_oidc(&config.oidc);
+    api_keys::apply_api_keys(&config.api_keys);
+    *AUTH_CONFIG.write().unwrap() = Some(Arc::new(config.clone()));
+}
+
+pub fn clear_auth() {
+    *AUTH_CONFIG.write().unwrap() = None;
+    jwt::clear_jwt();
+    oidc::clear_oidc();
+    api_keys::clear_api_keys();
+}
+
+fn current_config() -> Option<Arc<AuthConfig>> {
+    AUTH_CONFIG.read().unwrap().clone()
+}
+
+// Who a request was made by, and the roles the access rules see
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub username: String,
+    pub roles: Vec<String>,
+}
+
+// The logged in user, from a session cookie, Basic credentials or a bearer token,
+// worked out once per request. API keys are checked by the protected routes.
+pub async fn current_user(req: &Request<'_>) -> Option<Identity> {
+    req.local_cache_async(async { CurrentUser(identify(req).await) }).await.0.clone()
+}
+
+struct CurrentUser(Option<Identity>);
+
+// User a finished request was authenticated as, without checking credentials itself
+pub fn request_user(req: &Request<'_>) -> Option<String> {
+    req.local_cache(|| CurrentUser(None)).0.as_ref().map(|identity| identity.username.clone())
+}
+
+// Request guard for handlers that need a logged in user
+pub struct AuthUser(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        match current_user(req).await {
+            Some(identity) => request::Outcome::Success(AuthUser(identity.username)),
+            None => request::Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+async fn identify(req: &Request<'_>) -> Option<Identity> {
+    let config = current_config()?;
+    if let Some(session) = req.cookies().get_private(SESSION_COOKIE).and_then(|cookie| sessions::touch(cookie.value())) {
+        // Single sign-on sessions keep the provider's roles. Local accounts disabled
+        // or removed since the login are no longer let in.
+        if let Some(roles) = session.roles.or_else(|| users::roles(&session.username)) {
+            return Some(Identity { username: session.username, roles });
+        }
+    }
+
+    if let Some(token) = bearer_token(req) {
+        return jwt::verify(token).ok();
+    }
+    let encoded = req.headers().get_one("Authorization")?.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    let (username, password) = (username.to_string(), password.to_string());
+    let lockout = config.lockout();
+    let check = tokio::task::spawn_blocking(move || (users::authenticate(&username, &password, lockout), username));
+    match check.await.ok()? {
+        (LoginResult::Success, username) => Some(Identity { roles: users::roles(&username)?, username }),
+        _ => None,
+    }
+}
+
+fn bearer_token<'r>(req: &'r Request<'_>) -> Option<&'r str> {
+    let authorization = req.headers().get_one("Authorization")?;
+    let (scheme, token) = authorization.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("Bearer").then(|| token.trim())
+}
+
+// Client details kept with a new session
+pub struct Client {
+    ip: Option<IpAddr>,
+    user_agent: Option<String>,
+    // Arrived on an HTTPS listener, cookies set for it stay off plain HTTP
+    secure: bool,
+}
+
+impl Client {
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Client {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let user_agent = req.headers().get_one("User-Agent").map(str::to_string);
+        let secure = req.rocket().config().tls_enabled();
+        request::Outcome::Success(Client { ip: req.client_ip(), user_agent, secure })
+    }
+}
+
+// Apply the access rules and protected paths before the handler runs
+pub fn protect_routes(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(AuthHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct AuthHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for AuthHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let Some(config) = current_config() else {
+            return self.0.handle(req, data).await;
+        };
+        let path = normalized_path(req.uri().path());
+        let decision = rbac::decide(&config, req.method().as_str(), &path);
+
+        // A presented API key has to be valid and cover the request, wherever it is used
+        if let Some(key) = api_keys::presented(req) {
+            let identity = match api_keys::verify(key, req.method().as_str(), &path) {
+                Ok(identity) => identity,
+                Err(e) => return Outcome::from(req, KeyRejected(e)),
+            };
+            let allowed = rbac::allows(&config, decision, Some(&identity.roles));
+            req.local_cache(|| CurrentUser(Some(identity)));
+            if !allowed {
+                return Outcome::Error(Status::Forbidden);
+            }
+            return self.0.handle(req, data).await;
+        }
+
+        // Otherwise API prefixes take nothing but a valid bearer token, the rules then see its roles
+        if jwt::covers(&path) {
+            let identity = match bearer_token(req).ok_or(TokenError::Missing).and_then(jwt::verify) {
+                Ok(identity) => identity,
+                Err(e) => return Outcome::from(req, BearerChallenge(e)),
+            };
+            let allowed = rbac::allows(&config, decision, Some(&identity.roles));
+            req.local_cache(|| CurrentUser(Some(identity)));
+            if !allowed {
+                return Outcome::Error(Status::Forbidden);
+            }
+            return self.0.handle(req, data).await;
+        }
+
+        if rbac::allows(&config, decision, None) {
+            return self.0.handle(req, data).await;
+        }
+        let Some(identity) = current_user(req).await else {
+            return Outcome::from(req, Challenge);
+        };
+        if !rbac::allows(&config, decision, Some(&identity.roles)) {
+            return Outcome::Error(Status::Forbidden);
+        }
+        self.0.handle(req, data).await
+    }
+}
+
+// Browsers are sent to the login form, other clients get a Basic auth challenge
+struct Challenge;
+
+impl<'r> Responder<'r, 'static> for Challenge {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let browser = req.headers().get_one("Accept").is_some_and(|accept| accept.contains("text/html"))
+            && req.headers().get_one("Authorization").is_none();
+        if browser {
+            let next = req.uri().to_string();
+            return Redirect::to(format!("/login?next={}", &next as &dyn UriDisplay<Query>)).respond_to(req);
+        }
+        let realm = current_config().map_or_else(|| "Solara".to_string(), |config| config.realm.replace('"', ""));
+        Response::build_from("Authentication required".respond_to(req)?)
+            .status(Status::Unauthorized)
+            .header(Header::new("WWW-Authenticate", format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm)))
+            .ok()
+    }
+}
+
+// RFC 6750 answer for API clients, with a JSON body
+struct BearerChallenge(TokenError);
+
+impl<'r> Responder<'r, 'static> for BearerChallenge {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let realm = current_config().map_or_else(|| "Solara".to_string(), |config| config.realm.replace('"', ""));
+        // A missing token is not an error of the token itself
+        let challenge = match &self.0 {
+            TokenError::Missing => format!("Bearer realm=\"{}\"", realm),
+            e => format!("Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"", realm, e),
+        };
+        let body = serde_json::json!({
+            "error": if self.0 == TokenError::Missing { "unauthorized" } else { "invalid_token" },
+            "error_description": self.0.to_string(),
+        });
+        Response::build_from((ContentType::JSON, body.to_string()).respond_to(req)?)
+            .status(Status::Unauthorized)
+            .header(Header::new("WWW-Authenticate", challenge))
+            .ok()
+    }
+}
+
+// Refused API keys get a JSON answer like refused bearer tokens
+struct KeyRejected(KeyRejection);
+
+impl<'r> Responder<'r, 'static> for KeyRejected {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = if self.0 == KeyRejection::OutOfScope { Status::Forbidden } else { Status::Unauthorized };
+        let body = serde_json::json!({ "error": "invalid_api_key", "error_description": self.0.to_string() });
+        Response::build_from((ContentType::JSON, body.to_string()).respond_to(req)?).status(status).ok()
+    }
+}
+
+pub fn auth_routes() -> Vec<Route> {
+    routes![login_page, login, logout, password_page, change_password]
+}
+
+#[derive(FromForm)]
+struct LoginForm {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+#[derive(FromForm)]
+struct PasswordForm {
+    current_password: String,
+    new_password: String,
+    confirm_password: String,
+}
+
+#[get("/login?<next>")]
+fn login_page(next: Option<String>) -> (ContentType, String) {
+    login_form(next.as_deref(), None)
+}
+
+#[post("/login", data = "<form>")]
+async fn login(form: Form<LoginForm>, client: Client, cookies: &CookieJar<'_>) -> Result<Redirect, (Status, (ContentType, String))> {
+    let Some(config) = current_config() else {
+        return Err((Status::ServiceUnavailable, page("Login", "<p>Logins are not available right now.</p>")));
+    };
+    let LoginForm { username, password, next } = form.into_inner();
+    let lockout = config.lockout();
+    let check_name = username.clone();
+    let result = tokio::task::spawn_blocking(move || users::authenticate(&check_name, &password, lockout))
+        .await
+        .unwrap_or(LoginResult::Invalid);
+    match result {
+        LoginResult::Success => {
+            if !start_session(cookies, &username, None, &client) {
+                return Err((Status::ServiceUnavailable, page("Login", "<p>Logins are not available right now.</p>")));
+            }
+            Ok(Redirect::to(safe_redirect(next.as_deref()).to_string()))
+        }
+        LoginResult::Invalid => Err((Status::Unauthorized, login_form(next.as_deref(), Some("Invalid username or password.")))),
+        LoginResult::Locked(remaining) => {
+            let message = format!("Too many failed logins, try again in {} minute(s).", remaining.as_secs().div_ceil(60));
+            Err((Status::TooManyRequests, login_form(next.as_deref(), Some(&message))))
+        }
+    }
+}
+
+// Log the browser in, with `roles` for users not in the users file. False while the server is stopping.
+pub fn start_session(cookies: &CookieJar<'_>, username: &str, roles: Option<Vec<String>>, client: &Client) -> bool {
+    let (Some(token), Some((_, lifetime))) =
+        (sessions::create(username, roles, client.ip, client.user_agent.as_deref()), sessions::timeouts())
+    else {
+        return false;
+    };
+    // Lax so the session comes along when following a link to the server
+    let cookie = Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .secure(client.secure)
+        .same_site(SameSite::Lax)
+        .expires(OffsetDateTime::now_utc() + CookieDuration::seconds(lifetime.as_secs() as i64));
+    cookies.add_private(cookie);
+    true
+}
+
+#[post("/logout")]
+fn logout(cookies: &CookieJar<'_>) -> Redirect {
+    if let Some(cookie) = cookies.get_private(SESSION_COOKIE) {
+        sessions::revoke_token(cookie.value());
+    }
+    cookies.remove_private(Cookie::build(SESSION_COOKIE).path("/"));
+    Redirect::to("/login")
+}
+
+#[get("/account/password")]
+fn password_page(user: Option<AuthUser>) -> Result<(ContentType, String), Box<Redirect>> {
+    match user {
+        Some(AuthUser(username)) => Ok(password_form(&username, None)),
+        None => Err(Box::new(Redirect::to("/login?next=/account/password"))),
+    }
+}
+
+#[post("/account/password", data = "<form>")]
+async fn change_password(
+    user: Option<AuthUser>,
+    cookies: &CookieJar<'_>,
+    form: Form<PasswordForm>,
+) -> (Status, (ContentType, String)) {
+    let Some(AuthUser(username)) = user else {
+        return (Status::Unauthorized, page("Change Password", "<p>Please <a href=\"/login\">log in</a> first.</p>"));
+    };
+    let PasswordForm { current_password, new_password, confirm_password } = form.into_inner();
+    let fail = |message: &str| (Status::BadRequest, password_form(&username, Some(message)));
+    if new_password != confirm_password {
+        return fail("The new passwords don't match.");
+    }
+    if new_password.chars().count() < MIN_PASSWORD_LEN {
+        return fail(&format!("The new password needs at least {} characters.", MIN_PASSWORD_LEN));
+    }
+
+    let lockout = current_config().map_or(Lockout { max_failures: 0, duration: Duration::ZERO }, |config| config.lockout());
+    let name = username.clone();
+    let changed = tokio::task::spawn_blocking(move || match users::authenticate(&name, &current_password, lockout) {
+        LoginResult::Success => users::set_password(&name, &new_password).map(|_| LoginResult::Success).map_err(|e| e.to_string()),
+        result => Ok(result),
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    match changed {
+        Ok(LoginResult::Success) => {
+            // Other browsers logged in with the old password have to log in again
+            let keep = cookies.get_private(SESSION_COOKIE).map(|cookie| cookie.value().to_string());
+            sessions::revoke_user(&username, keep.as_deref());
+            (Status::Ok, page("Change Password", "<p>Your password was changed.</p>"))
+        }
+        Ok(LoginResult::Invalid) => fail("The current password is wrong."),
+        Ok(LoginResult::Locked(remaining)) => {
+            fail(&format!("Too many failed attempts, try again in {} minute(s).", remaining.as_secs().div_ceil(60)))
+        }
+        Err(e) => {
+            eprintln!("Failed to change password of '{}': {}", username, e);
+            (Status::InternalServerError, password_form(&username, Some("The password could not be saved.")))
+        }
+    }
+}
+
+// Only redirect to paths on this server after a login
+pub fn safe_redirect(next: Option<&str>) -> &str {
+    next.filter(|next| next.starts_with('/') && !next.starts_with("//") && !next.contains('\\')).unwrap_or("/")
+}
+
+fn login_form(next: Option<&str>, error: Option<&str>) -> (ContentType, String) {
+    page("Login", &format!(
+        "{error}<form method=\"post\" action=\"/login\">\n\
+         <p><label>Username <input name=\"username\" autocomplete=\"username\" autofocus></label></p>\n\
+         <p><label>Password <input name=\"password\" type=\"password\" autocomplete=\"current-password\"></label></p>\n\
+         <input type=\"hidden\" name=\"next\" value=\"{next}\">\n\
+         <p><button type=\"submit\">Log in</button></p>\n</form>{single_sign_on}",
+        single_sign_on = oidc::button_label().map_or(String::new(), |label| {
+            let next = safe_redirect(next);
+            let href = format!("/oidc/login?next={}", &next as &dyn UriDisplay<Query>);
+            format!("\n<p><a href=\"{}\">{}</a></p>", escape_html(&href), escape_html(&label))
+        }),
+        error = error.map_or(String::new(), |error| format!("<p><strong>{}</strong></p>\n", escape_html(error))),
+        next = escape_html(safe_redirect(next)),
+    ))
+}
+
+fn password_form(username: &str, error: Option<&str>) -> (ContentType, String) {
+    page("Change Password", &format!(
+        "<p>Logged in as {user}.</p>\n{error}<form method=\"post\" action=\"/account/password\">\n\
+         <p><label>Current password <input name=\"current_password\" type=\"password\" autocomplete=\"current-password\"></label></p>\n\
+         <p><label>New password <input name=\"new_password\" type=\"password\" autocomplete=\"new-password\"></label></p>\n\
+         <p><label>Repeat new password <input name=\"confirm_password\" type=\"password\" autocomplete=\"new-password\"></label></p>\n\
+         <p><button type=\"submit\">Change password</button></p>\n</form>\n\
+         <form method=\"post\" action=\"/logout\"><button type=\"submit\">Log out</button></form>",
+        user = escape_html(username),
+        error = error.map_or(String::new(), |error| format!("<p><strong>{}</strong></p>\n", escape_html(error))),
+    ))
+}
+
+// Same layout as the error pages
+pub fn page(title: &str, body: &str) -> (ContentType, String) {
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head><title>{title}</title></head>\n<body>\n\
+         <h1>{title}</h1>\n<hr>\n{body}\n</body>\n</html>\n",
+        title = title,
+        body = body,
+    );
+    (ContentType::HTML, page)
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::uri::Origin;
+
+    fn normalized(path: &str) -> String {
+        normalized_path(Origin::parse(path).unwrap().path())
+    }
+
+    #[test]
+    fn paths_are_normalized() {
+        assert_eq!(normalized("/"), "/");
+        assert_eq!(normalized("/admin/x"), "/admin/x");
+        assert_eq!(normalized("/%61dmin/x"), "/admin/x");
+        assert_eq!(normalized("/proxy//admin///x/"), "/proxy/admin/x");
+        assert_eq!(normalized("/proxy/./admin/x"), "/proxy/admin/x");
+        assert_eq!(normalized("/proxy/public/../admin/x"), "/proxy/admin/x");
+        assert_eq!(normalized("/proxy/public/%2e%2e/admin/x"), "/proxy/admin/x");
+        assert_eq!(normalized("/proxy%2Fadmin/x"), "/proxy/admin/x");
+        assert_eq!(normalized("/../../admin"), "/admin");
+        assert_eq!(normalized("/files/a%20b?x=1"), "/files/a b");
+    }
+
+    #[test]
+    fn prefixes() {
+        assert!(path_has_prefix("/private", "/private"));
+        assert!(path_has_prefix("/private/a", "/private/"));
+        assert!(!path_has_prefix("/privateer", "/private"));
+        assert!(path_has_prefix("/anything", "/"));
+    }
+
+    #[test]
+    fn encoded_and_doubled_slashes_are_still_protected() {
+        let config = AuthConfig {
+            users_file: String::new(),
+            protected_paths: vec!["/private".to_string()],
+            rules: vec![rbac::AccessRule {
+                path: "/proxy/admin/**".to_string(),
+                methods: Vec::new(),
+                roles: vec!["admin".to_string()],
+            }],
+            ..AuthConfig::default()
+        };
+        // As the handler sees a request without credentials
+        let anonymous = |uri: &str| {
+            let path = normalized(uri);
+            rbac::allows(&config, rbac::decide(&config, "GET", &path), None)
+        };
+
+        for path in [
+            "/proxy/admin/x",
+            "/proxy/%61dmin/x",
+            "/proxy//admin/x",
+            "/proxy/public/../admin/x",
+            "/proxy/public/%2E%2E/admin/x",
+            "/%70roxy/admin",
+            "/private/report",
+            "/%70rivate/report",
+            "//private//report",
+        ] {
+            assert!(!anonymous(path), "{}", path);
+        }
+        for path in ["/proxy/public/x", "/proxy/administrator", "/privateer"] {
+            assert!(anonymous(path), "{}", path);
+        }
+    }
+}
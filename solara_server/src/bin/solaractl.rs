@@ -0,0 +1,318 @@
+// Command line control of a running Solara server through its control socket
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "\
+Usage: solaractl [--socket PATH] COMMAND
+
+Commands:
+  status                  Server and listener status
+  start                   Start the site listeners
+  stop                    Stop the site listeners, admin listeners keep running
+  reload                  Re-read the configuration file and apply it
+  restart LISTENER        Restart a listener once its connections have drained
+  logs [-f] [-n COUNT] [--level LEVEL] [--filter TEXT]
+                          Latest server messages, -f keeps printing new ones
+  connections             Open connections
+  close ID                Close a connection after its current response
+  test-config [FILE]      Validate FILE, or the configuration file of the server
+
+The socket is the control_socket path of the server configuration, solara.sock
+in the working directory unless given with --socket or SOLARA_SOCKET.";
+
+const DEFAULT_SOCKET: &str = "solara.sock";
+const DEFAULT_LOG_LINES: usize = 20;
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+// Requests and answers are JSON, one per line
+struct Control {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Control {
+    fn connect(path: &str) -> Result<Self, String> {
+        let stream = UnixStream::connect(path).map_err(|e| format!("Can't connect to {}: {}", path, e))?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        Ok(Self { reader, writer: stream })
+    }
+
+    // Errors reported by the server come back as Err too
+    fn request(&mut self, request: Value) -> Result<Value, String> {
+        writeln!(self.writer, "{}", request).map_err(|e| format!("Failed to send the request: {}", e))?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(|e| format!("Failed to read the answer: {}", e))? == 0 {
+            return Err("The server closed the connection".to_string());
+        }
+        let answer: Value = serde_json::from_str(&line).map_err(|e| format!("Unexpected answer: {}", e))?;
+        match answer["error"].as_str() {
+            Some(error) => Err(error.to_string()),
+            None => Ok(answer),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let socket = std::env::var("SOLARA_SOCKET").unwrap_or_else(|_| DEFAULT_SOCKET.to_string());
+    let (socket, command) = match parse_args(std::env::args().skip(1).collect(), socket) {
+        Ok(parsed) => parsed,
+        Err(e) => return usage_error(&e),
+    };
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Command::Status => status(&socket),
+        Command::Action(request) => action(&socket, request),
+        Command::Logs(options) => logs(&socket, options),
+        Command::Connections => connections(&socket),
+        Command::TestConfig(file) => test_config(&socket, file.as_ref()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("solaractl: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Help,
+    Status,
+    // Sent as is, answered with a message
+    Action(Value),
+    Logs(LogOptions),
+    Connections,
+    TestConfig(Option<String>),
+}
+
+// The socket path and the command, `socket` is used unless --socket is given
+fn parse_args(mut args: Vec<String>, mut socket: String) -> Result<(String, Command), String> {
+    if let Some(index) = args.iter().position(|arg| arg == "--socket") {
+        if index + 1 >= args.len() {
+            return Err("--socket needs a path".to_string());
+        }
+        socket = args.remove(index + 1);
+        args.remove(index);
+    }
+    let Some((command, rest)) = args.split_first() else {
+        return Err("no command given".to_string());
+    };
+    let command = match (command.as_str(), rest) {
+        ("help" | "-h" | "--help", _) => Command::Help,
+        ("status", []) => Command::Status,
+        ("start" | "stop" | "reload", []) => Command::Action(json!({ "command": command })),
+        ("restart", [listener]) => Command::Action(json!({ "command": "restart", "listener": listener })),
+        ("logs", options) => Command::Logs(LogOptions::parse(options)?),
+        ("connections", []) => Command::Connections,
+        ("close", [id]) => match id.parse::<u64>() {
+            Ok(id) => Command::Action(json!({ "command": "close", "id": id })),
+            Err(_) => return Err(format!("'{}' is not a connection ID", id)),
+        },
+        ("test-config", []) => Command::TestConfig(None),
+        ("test-config", [file]) => Command::TestConfig(Some(file.clone())),
+        _ => return Err(format!("unknown command '{}'", args.join(" "))),
+    };
+    Ok((socket, command))
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("solaractl: {}\n\n{}", message, USAGE);
+    ExitCode::from(2)
+}
+
+fn status(socket: &str) -> Result<ExitCode, String> {
+    let answer = Control::connect(socket)?.request(json!({ "command": "status" }))?;
+    println!("Server: {}", answer["status"].as_str().unwrap_or_default());
+    let listeners = answer["listeners"].as_array().cloned().unwrap_or_default();
+    let width = listeners.iter().filter_map(|l| l["name"].as_str()).map(str::len).max().unwrap_or(0);
+    for listener in &listeners {
+        println!("  {:width$}  {:30}  {}{}",
+            listener["name"].as_str().unwrap_or_default(),
+            listener["address"].as_str().unwrap_or_default(),
+            listener["status"].as_str().unwrap_or_default(),
+            if listener["admin"].as_bool() == Some(true) { " (admin)" } else { "" },
+            width = width);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Commands answered with a message
+fn action(socket: &str, request: Value) -> Result<ExitCode, String> {
+    let answer = Control::connect(socket)?.request(request)?;
+    println!("{}", answer["message"].as_str().unwrap_or("Done"));
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Debug, PartialEq)]
+struct LogOptions {
+    follow: bool,
+    lines: usize,
+    level: Option<String>,
+    filter: String,
+}
+
+impl LogOptions {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = LogOptions { follow: false, lines: DEFAULT_LOG_LINES, level: None, filter: String::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-f" | "--follow" => options.follow = true,
+                "-n" => {
+                    let count = args.next().ok_or("-n needs a count")?;
+                    options.lines = count.parse().map_err(|_| format!("'{}' is not a count", count))?;
+                }
+                "--level" => options.level = Some(args.next().ok_or("--level needs info, warning or error")?.clone()),
+                "--filter" => options.filter = args.next().ok_or("--filter needs a text")?.clone(),
+                other => return Err(format!("unknown logs option '{}'", other)),
+            }
+        }
+        Ok(options)
+    }
+
+    // Messages after sequence number `after`, at most `limit` of them
+    fn request(&self, after: u64, limit: usize) -> Value {
+        json!({
+            "command": "logs",
+            "after": after,
+            "limit": limit,
+            "level": self.level,
+            "filter": self.filter,
+        })
+    }
+}
+
+fn logs(socket: &str, options: LogOptions) -> Result<ExitCode, String> {
+    let mut control = Control::connect(socket)?;
+    let mut after = 0;
+    let mut limit = options.lines;
+    loop {
+        let answer = control.request(options.request(after, limit))?;
+        for entry in answer["entries"].as_array().into_iter().flatten() {
+            println!("{} [{}] {}",
+                entry["timestamp"].as_str().unwrap_or_default(),
+                entry["level"].as_str().unwrap_or_default(),
+                entry["message"].as_str().unwrap_or_default());
+            after = after.max(entry["seq"].as_u64().unwrap_or(0));
+        }
+        if !options.follow {
+            return Ok(ExitCode::SUCCESS);
+        }
+        // Everything new from here on, the server keeps the latest thousand messages
+        limit = 1000;
+        std::thread::sleep(FOLLOW_INTERVAL);
+    }
+}
+
+fn connections(socket: &str) -> Result<ExitCode, String> {
+    let answer = Control::connect(socket)?.request(json!({ "command": "connections" }))?;
+    let connections = answer["connections"].as_array().cloned().unwrap_or_default();
+    if connections.is_empty() {
+        println!("No open connections");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    println!("{:>6}  {:22}  {:12}  {:>8}  {:>8}  CURRENT", "ID", "CLIENT", "LISTENER", "OPEN", "REQUESTS");
+    for connection in &connections {
+        let opened = connection["opened"]["secs_since_epoch"].as_u64().unwrap_or(now);
+        let current = if connection["closing"].as_bool() == Some(true) {
+            "closing"
+        } else {
+            connection["current_request"].as_str().unwrap_or("idle")
+        };
+        println!("{:>6}  {:22}  {:12}  {:>8}  {:>8}  {}",
+            connection["id"].as_u64().unwrap_or_default(),
+            connection["remote"].as_str().unwrap_or_default(),
+            connection["listener"].as_str().unwrap_or_default(),
+            format_duration(now.saturating_sub(opened)),
+            connection["requests"].as_u64().unwrap_or_default(),
+            current);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+// Files are read here, so relative paths are those of the caller
+fn test_config(socket: &str, file: Option<&String>) -> Result<ExitCode, String> {
+    let contents = match file {
+        Some(file) => Some(fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?),
+        None => None,
+    };
+    let answer = Control::connect(socket)?.request(json!({ "command": "test_config", "config": contents }))?;
+    for error in answer["errors"].as_array().into_iter().flatten() {
+        println!("error: {}", error.as_str().unwrap_or_default());
+    }
+    for warning in answer["warnings"].as_array().into_iter().flatten() {
+        println!("warning: {}", warning.as_str().unwrap_or_default());
+    }
+    if answer["valid"].as_bool() == Some(true) {
+        println!("Configuration is valid");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("Configuration is invalid");
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(String, Command), String> {
+        parse_args(args.iter().map(|arg| arg.to_string()).collect(), DEFAULT_SOCKET.to_string())
+    }
+
+    #[test]
+    fn commands_become_requests() {
+        assert_eq!(parse(&["status"]), Ok((DEFAULT_SOCKET.to_string(), Command::Status)));
+        assert_eq!(parse(&["reload", "--socket", "/run/solara.sock"]),
+            Ok(("/run/solara.sock".to_string(), Command::Action(json!({ "command": "reload" })))));
+        assert_eq!(parse(&["restart", "public"]).unwrap().1,
+            Command::Action(json!({ "command": "restart", "listener": "public" })));
+        assert_eq!(parse(&["close", "42"]).unwrap().1, Command::Action(json!({ "command": "close", "id": 42 })));
+        assert_eq!(parse(&["test-config", "new.toml"]).unwrap().1, Command::TestConfig(Some("new.toml".to_string())));
+        assert_eq!(parse(&["--help"]).unwrap().1, Command::Help);
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        assert_eq!(parse(&[]), Err("no command given".to_string()));
+        assert_eq!(parse(&["status", "--socket"]), Err("--socket needs a path".to_string()));
+        assert_eq!(parse(&["close", "first"]), Err("'first' is not a connection ID".to_string()));
+        assert_eq!(parse(&["restart"]), Err("unknown command 'restart'".to_string()));
+        assert_eq!(parse(&["logs", "-n", "many"]), Err("'many' is not a count".to_string()));
+        assert_eq!(parse(&["logs", "--tail"]), Err("unknown logs option '--tail'".to_string()));
+    }
+
+    #[test]
+    fn log_options_shape_the_request() {
+        let Command::Logs(options) = parse(&["logs", "-f", "-n", "5", "--level", "error", "--filter", "tls"]).unwrap().1 else {
+            panic!("not a logs command");
+        };
+        assert!(options.follow);
+        assert_eq!(options.request(7, options.lines), json!({
+            "command": "logs", "after": 7, "limit": 5, "level": "error", "filter": "tls",
+        }));
+        let Command::Logs(options) = parse(&["logs"]).unwrap().1 else {
+            panic!("not a logs command");
+        };
+        assert_eq!(options.request(0, options.lines), json!({
+            "command": "logs", "after": 0, "limit": DEFAULT_LOG_LINES, "level": null, "filter": "",
+        }));
+    }
+}
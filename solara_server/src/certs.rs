@@ -0,0 +1,77 @@
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::pem::Pem;
+use crate::jwt;
+
+// Subject, issuer and validity of the first certificate in a PEM file
+pub fn certificate_info(path: &Path) -> Result<Value, String> {
+    let contents = fs::read(path).map_err(|e| e.to_string())?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&contents).map_err(|e| e.to_string())?;
+    let certificate = pem.parse_x509().map_err(|e| e.to_string())?;
+    Ok(describe(&certificate))
+}
+
+fn describe(certificate: &X509Certificate) -> Value {
+    let validity = certificate.validity();
+    let not_after = validity.not_after.timestamp();
+    json!({
+        "subject": certificate.subject().to_string(),
+        "issuer": certificate.issuer().to_string(),
+        "not_before": validity.not_before.timestamp(),
+        "not_after": not_after,
+        "days_left": (not_after - jwt::unix_now() as i64) / 86_400,
+    })
+}
+
+// Parse the chain and make sure the key belongs to its first certificate, whatever its validity
+pub fn check_key_pair(chain: &[u8], key: &[u8]) -> Result<Value, String> {
+    let blocks: Vec<Pem> = Pem::iter_from_buffer(chain)
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("certificate is not PEM: {}", e))?;
+    let Some(first) = blocks.first() else {
+        return Err("no certificate was given".to_string());
+    };
+    for block in &blocks {
+        if block.label != "CERTIFICATE" {
+            return Err(format!("'{}' blocks don't belong in a certificate chain", block.label));
+        }
+        block.parse_x509().map_err(|e| format!("invalid certificate: {}", e))?;
+    }
+    let certificate = first.parse_x509().map_err(|e| format!("invalid certificate: {}", e))?;
+
+    let (_, key) = x509_parser::pem::parse_x509_pem(key).map_err(|e| format!("private key is not PEM: {}", e))?;
+    let public_key = match key.label.as_str() {
+        "PRIVATE KEY" => Some(pkcs8_public_key(&key.contents)?),
+        "RSA PRIVATE KEY" => Some(
+            RsaKeyPair::from_der(&key.contents).map_err(|e| format!("invalid RSA key: {}", e))?.public_key().as_ref().to_vec(),
+        ),
+        // Accepted by the TLS stack, but ring can't read SEC1 keys to compare them
+        "EC PRIVATE KEY" => None,
+        label => return Err(format!("'{}' PEM blocks are not private keys", label)),
+    };
+    if public_key.is_some_and(|public_key| public_key != certificate.public_key().subject_public_key.data.as_ref()) {
+        return Err("the private key does not belong to the certificate".to_string());
+    }
+    Ok(describe(&certificate))
+}
+
+// Public key of a PKCS#8 key in the form certificates carry it
+fn pkcs8_public_key(der: &[u8]) -> Result<Vec<u8>, String> {
+    if let Ok(pair) = RsaKeyPair::from_pkcs8(der) {
+        return Ok(pair.public_key().as_ref().to_vec());
+    }
+    let rng = SystemRandom::new();
+    for algorithm in [&signature::ECDSA_P256_SHA256_ASN1_SIGNING, &signature::ECDSA_P384_SHA384_ASN1_SIGNING] {
+        if let Ok(pair) = EcdsaKeyPair::from_pkcs8(algorithm, der, &rng) {
+            return Ok(pair.public_key().as_ref().to_vec());
+        }
+    }
+    if let Ok(pair) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der) {
+        return Ok(pair.public_key().as_ref().to_vec());
+    }
+    Err("unsupported private key, use RSA, P-256, P-384 or Ed25519".to_string())
+}
@@ -0,0 +1,130 @@
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Sense, Shape, Stroke, Ui};
+use crate::history::{Sample, MAX_WINDOW_SECS};
+
+// Narrowest zoom, a few samples at one second resolution
+const MIN_WINDOW_SECS: f64 = 10.0;
+const CHART_HEIGHT: f32 = 90.0;
+
+// A line in a chart, reading its value from each sample
+pub struct Series {
+    pub name: &'static str,
+    pub color: Color32,
+    pub value: fn(&Sample) -> f64,
+}
+
+// Draw a line chart over the last `window` seconds. Ctrl+scroll over the chart zooms
+// the time axis, hovering shows the values of the nearest sample.
+pub fn time_series(
+    ui: &mut Ui,
+    title: &str,
+    unit: &str,
+    series: &[Series],
+    samples: &[Sample],
+    now: f64,
+    window: &mut f64,
+) {
+    ui.horizontal(|ui| {
+        ui.strong(title);
+        for line in series {
+            ui.colored_label(line.color, format!("— {}", line.name));
+        }
+    });
+
+    let width = ui.available_width().max(100.0);
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(width, CHART_HEIGHT), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+    // Plain scrolling is left to the surrounding scroll area
+    if response.hovered() {
+        let zoom = ui.input(|input| input.zoom_delta());
+        if zoom != 1.0 {
+            *window = (*window / zoom as f64).clamp(MIN_WINDOW_SECS, MAX_WINDOW_SECS);
+        }
+    }
+
+    // Leave a little headroom and never collapse to a flat zero range
+    let max = samples
+        .iter()
+        .flat_map(|sample| series.iter().map(move |line| (line.value)(sample)))
+        .fold(0.0_f64, f64::max);
+    let max = if max > 0.0 { max * 1.15 } else { 1.0 };
+    let from = now - *window;
+
+    let to_screen = |time: f64, value: f64| {
+        Pos2::new(
+            rect.left() + ((time - from) / *window) as f32 * rect.width(),
+            rect.bottom() - (value / max) as f32 * rect.height(),
+        )
+    };
+
+    // Grid lines with the value they mark
+    let grid = Stroke::new(1.0, visuals.widgets.noninteractive.bg_stroke.color);
+    let text_color = visuals.weak_text_color();
+    for step in 1..4 {
+        let value = max * step as f64 / 4.0;
+        let y = to_screen(from, value).y;
+        painter.line_segment([Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)], grid);
+        painter.text(Pos2::new(rect.left() + 2.0, y), Align2::LEFT_BOTTOM,
+            format!("{} {}", format_value(value), unit), FontId::proportional(10.0), text_color);
+    }
+    painter.text(rect.left_bottom() + egui::vec2(2.0, -2.0), Align2::LEFT_BOTTOM,
+        format!("-{}", format_window(*window)), FontId::proportional(10.0), text_color);
+    painter.text(rect.right_bottom() + egui::vec2(-2.0, -2.0), Align2::RIGHT_BOTTOM,
+        "now", FontId::proportional(10.0), text_color);
+
+    for line in series {
+        let points: Vec<Pos2> = samples.iter().map(|sample| to_screen(sample.time, (line.value)(sample))).collect();
+        if points.len() > 1 {
+            painter.add(Shape::line(points, Stroke::new(1.5, line.color)));
+        }
+    }
+
+    if samples.is_empty() {
+        painter.text(rect.center(), Align2::CENTER_CENTER, "No data yet", FontId::proportional(12.0), text_color);
+        return;
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let time = from + ((pointer.x - rect.left()) / rect.width()) as f64 * *window;
+        let nearest = samples
+            .iter()
+            .min_by(|a, b| (a.time - time).abs().total_cmp(&(b.time - time).abs()))
+            .copied();
+        if let Some(sample) = nearest {
+            let x = to_screen(sample.time, 0.0).x;
+            painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())], Stroke::new(1.0, text_color));
+            for line in series {
+                painter.circle_filled(to_screen(sample.time, (line.value)(&sample)), 3.0, line.color);
+            }
+            response.on_hover_ui_at_pointer(|ui| {
+                ui.label(format!("{} ago", format_window(now - sample.time)));
+                for line in series {
+                    ui.colored_label(line.color, format!("{}: {} {}", line.name, format_value((line.value)(&sample)), unit));
+                }
+            });
+        }
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value >= 100.0 {
+        format!("{:.0}", value)
+    } else if value >= 10.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn format_window(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 3600 {
+        format!("{}h", seconds / 3600)
+    } else if seconds >= 60 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
@@ -0,0 +1,1000 @@
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, TcpListener, ToSocketAddrs};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::{ImDocument, TableLike};
+use crate::access_log::{self, AccessLogFormat};
+use crate::api_keys;
+use crate::certs;
+use crate::control_socket;
+use crate::jwt;
+use crate::log_forward::{self, ForwardTarget};
+use crate::oidc;
+use crate::password;
+use crate::rbac;
+use crate::server::{ListenerConfig, Protocol, ServerConfig};
+use crate::sessions;
+use crate::users;
+
+// Errors that can occur while reading or writing a configuration file
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "I/O error: {}", e),
+            ConfigError::Parse(e) => write!(f, "Invalid configuration: {}", e),
+            ConfigError::Serialize(e) => write!(f, "Failed to serialize configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Load a TOML configuration file, missing fields fall back to their defaults
+pub fn load_config(path: &Path) -> Result<ServerConfig, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    toml::from_str(&contents).map_err(ConfigError::Parse)
+}
+
+pub fn save_config(path: &Path, config: &ServerConfig) -> Result<(), ConfigError> {
+    let contents = toml::to_string_pretty(config).map_err(ConfigError::Serialize)?;
+    fs::write(path, contents).map_err(ConfigError::Io)
+}
+
+// Configuration field a validation issue refers to, used to highlight it in the GUI
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigField {
+    Listeners,
+    ListenerName(usize),
+    ListenerHost(usize),
+    ListenerPort(usize),
+    ListenerTls(usize),
+    MaxConnections,
+    Workers,
+    Metrics,
+    StatusAccess,
+    AccessLog,
+    LogForwarding,
+    Auth,
+    AccessRule(usize),
+    Sessions,
+    Jwt,
+    Oidc,
+    ApiKeys,
+    AdminApi,
+    ControlSocket,
+    Upstream(usize),
+}
+
+// Problems that prevent the server from starting with a configuration
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    NoListeners,
+    EmptyListenerName { index: usize },
+    DuplicateListenerName { index: usize, name: String },
+    InvalidHost { index: usize, host: String, reason: String },
+    PortConflict { index: usize, other: String, port: u16 },
+    PortUnavailable { index: usize, port: u16, reason: String },
+    MissingTlsFiles { index: usize },
+    TlsFileNotFound { index: usize, path: String },
+    InvalidCertificate { index: usize, reason: String },
+    InvalidMaxConnections,
+    InvalidTrustedIp { entry: String, reason: String },
+    InvalidWorkerCount { workers: usize },
+    InvalidAccessLogTemplate { reason: String },
+    AccessLogDirMissing { path: String },
+    InvalidSyslogAddress { address: String, reason: String },
+    InvalidProtectedPath { path: String },
+    InvalidUsersFile { path: String, reason: String },
+    InvalidPasswordHash { username: String },
+    InvalidAccessRule { index: usize, reason: String },
+    InvalidSessionTimeout,
+    SessionFileDirMissing { path: String },
+    InvalidSessionKey { path: String, reason: String },
+    InvalidJwtPrefix { prefix: String },
+    InvalidJwtKeys { reason: String },
+    InvalidOidcSetting { reason: String },
+    InvalidApiKeysFile { path: String, reason: String },
+    InvalidApiKeyHeader { name: String },
+    InvalidAdminApiRoles { reason: String },
+    InvalidControlSocket { path: String, reason: String },
+    InvalidPathPrefix { index: usize, prefix: String },
+    DuplicatePathPrefix { index: usize, prefix: String },
+    InvalidBackendAddress { index: usize, address: String },
+}
+
+impl ValidationError {
+    pub fn field(&self) -> ConfigField {
+        match self {
+            ValidationError::NoListeners => ConfigField::Listeners,
+            ValidationError::EmptyListenerName { index }
+            | ValidationError::DuplicateListenerName { index, .. } => ConfigField::ListenerName(*index),
+            ValidationError::InvalidHost { index, .. } => ConfigField::ListenerHost(*index),
+            ValidationError::PortConflict { index, .. }
+            | ValidationError::PortUnavailable { index, .. } => ConfigField::ListenerPort(*index),
+            ValidationError::MissingTlsFiles { index }
+            | ValidationError::TlsFileNotFound { index, .. }
+            | ValidationError::InvalidCertificate { index, .. } => ConfigField::ListenerTls(*index),
+            ValidationError::InvalidMaxConnections => ConfigField::MaxConnections,
+            ValidationError::InvalidTrustedIp { .. } => ConfigField::StatusAccess,
+            ValidationError::InvalidWorkerCount { .. } => ConfigField::Workers,
+            ValidationError::InvalidAccessLogTemplate { .. }
+            | ValidationError::AccessLogDirMissing { .. } => ConfigField::AccessLog,
+            ValidationError::InvalidSyslogAddress { .. } => ConfigField::LogForwarding,
+            ValidationError::InvalidProtectedPath { .. }
+            | ValidationError::InvalidUsersFile { .. }
+            | ValidationError::InvalidPasswordHash { .. } => ConfigField::Auth,
+            ValidationError::InvalidAccessRule { index, .. } => ConfigField::AccessRule(*index),
+            ValidationError::InvalidSessionTimeout
+            | ValidationError::SessionFileDirMissing { .. }
+            | ValidationError::InvalidSessionKey { .. } => ConfigField::Sessions,
+            ValidationError::InvalidJwtPrefix { .. } | ValidationError::InvalidJwtKeys { .. } => ConfigField::Jwt,
+            ValidationError::InvalidOidcSetting { .. } => ConfigField::Oidc,
+            ValidationError::InvalidApiKeysFile { .. } | ValidationError::InvalidApiKeyHeader { .. } => ConfigField::ApiKeys,
+            ValidationError::InvalidAdminApiRoles { .. } => ConfigField::AdminApi,
+            ValidationError::InvalidControlSocket { .. } => ConfigField::ControlSocket,
+            ValidationError::InvalidPathPrefix { index, .. }
+            | ValidationError::DuplicatePathPrefix { index, .. }
+            | ValidationError::InvalidBackendAddress { index, .. } => ConfigField::Upstream(*index),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::NoListeners => write!(f, "At least one listener is required"),
+            ValidationError::EmptyListenerName { index } => write!(f, "Listener #{} has no name", index + 1),
+            ValidationError::DuplicateListenerName { name, .. } => write!(f, "Listener name '{}' is used more than once", name),
+            ValidationError::InvalidHost { host, reason, .. } => write!(f, "Host '{}' is not a valid address: {}", host, reason),
+            ValidationError::PortConflict { other, port, .. } => write!(f, "Port {} is already used by listener '{}'", port, other),
+            ValidationError::PortUnavailable { port, reason, .. } => write!(f, "Port {} can't be bound: {}", port, reason),
+            ValidationError::MissingTlsFiles { .. } => write!(f, "HTTPS needs a certificate and a key file"),
+            ValidationError::TlsFileNotFound { path, .. } => write!(f, "TLS file '{}' does not exist", path),
+            ValidationError::InvalidCertificate { reason, .. } => write!(f, "TLS certificate can't be used: {}", reason),
+            ValidationError::InvalidMaxConnections => write!(f, "Max connections must be at least 1"),
+            ValidationError::InvalidTrustedIp { entry, reason } => write!(f, "Trusted address '{}' is invalid: {}", entry, reason),
+            ValidationError::InvalidWorkerCount { workers } => write!(f, "Worker count {} is invalid, at least 1 is required", workers),
+            ValidationError::InvalidAccessLogTemplate { reason } => write!(f, "Access log template is invalid: {}", reason),
+            ValidationError::AccessLogDirMissing { path } => write!(f, "Directory of access log '{}' does not exist", path),
+            ValidationError::InvalidSyslogAddress { address, reason } => write!(f, "Syslog address '{}' is invalid: {}", address, reason),
+            ValidationError::InvalidProtectedPath { path } => write!(f, "Protected path '{}' must start with '/'", path),
+            ValidationError::InvalidUsersFile { path, reason } => write!(f, "Users file '{}' can't be used: {}", path, reason),
+            ValidationError::InvalidPasswordHash { username } => write!(f, "User '{}' has no valid Argon2id password hash", username),
+            ValidationError::InvalidAccessRule { index, reason } => write!(f, "Access rule #{} is invalid: {}", index + 1, reason),
+            ValidationError::InvalidSessionTimeout => write!(f, "Session timeouts must be at least 1 second"),
+            ValidationError::SessionFileDirMissing { path } => write!(f, "Directory of session file '{}' does not exist", path),
+            ValidationError::InvalidSessionKey { path, reason } => write!(f, "Session key '{}' is invalid: {}", path, reason),
+            ValidationError::InvalidJwtPrefix { prefix } => write!(f, "JWT path prefix '{}' must start with '/'", prefix),
+            ValidationError::InvalidJwtKeys { reason } => write!(f, "JWT keys can't be loaded: {}", reason),
+            ValidationError::InvalidOidcSetting { reason } => write!(f, "Single sign-on is misconfigured: {}", reason),
+            ValidationError::InvalidApiKeysFile { path, reason } => write!(f, "API keys file '{}' can't be used: {}", path, reason),
+            ValidationError::InvalidApiKeyHeader { name } => write!(f, "'{}' is not a valid header name for API keys", name),
+            ValidationError::InvalidAdminApiRoles { reason } => write!(f, "Admin API roles are invalid: {}", reason),
+            ValidationError::InvalidControlSocket { path, reason } => write!(f, "Control socket '{}' can't be used: {}", path, reason),
+            ValidationError::InvalidPathPrefix { prefix, .. } => write!(f, "Upstream path prefix '{}' must start with '/'", prefix),
+            ValidationError::DuplicatePathPrefix { prefix, .. } => write!(f, "Upstream path prefix '{}' is used more than once", prefix),
+            ValidationError::InvalidBackendAddress { address, .. } => write!(f, "Backend address '{}' must be host:port", address),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Settings that work but are likely to surprise
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationWarning {
+    PrivilegedPort { index: usize, port: u16 },
+    CertificateExpired { index: usize, name: String },
+    ManyWorkers { workers: usize, cores: usize },
+    NoBackends { index: usize, name: String },
+    MetricsNotServed,
+    // Protected paths with nobody able to log in
+    NoUsers,
+    // A rule allows a role no user has
+    UnknownRole { index: usize, role: String },
+    // Every bearer token would be refused
+    NoJwtKeys,
+    // Discovery and keys would be fetched without TLS
+    InsecureOidcIssuer { issuer: String },
+    AdminApiNotServed,
+    // Credentials for the admin API would cross the network in the clear
+    InsecureAdminListener { index: usize, name: String },
+}
+
+impl ValidationWarning {
+    pub fn field(&self) -> ConfigField {
+        match self {
+            ValidationWarning::PrivilegedPort { index, .. } => ConfigField::ListenerPort(*index),
+            ValidationWarning::CertificateExpired { index, .. } => ConfigField::ListenerTls(*index),
+            ValidationWarning::ManyWorkers { .. } => ConfigField::Workers,
+            ValidationWarning::NoBackends { index, .. } => ConfigField::Upstream(*index),
+            ValidationWarning::MetricsNotServed => ConfigField::Metrics,
+            ValidationWarning::NoUsers => ConfigField::Auth,
+            ValidationWarning::UnknownRole { index, .. } => ConfigField::AccessRule(*index),
+            ValidationWarning::NoJwtKeys => ConfigField::Jwt,
+            ValidationWarning::InsecureOidcIssuer { .. } => ConfigField::Oidc,
+            ValidationWarning::AdminApiNotServed => ConfigField::AdminApi,
+            ValidationWarning::InsecureAdminListener { index, .. } => ConfigField::ListenerTls(*index),
+        }
+    }
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationWarning::PrivilegedPort { port, .. } => write!(f, "Port {} is privileged and needs elevated permissions", port),
+            ValidationWarning::CertificateExpired { name, .. } => write!(f, "The certificate of listener '{}' has expired", name),
+            ValidationWarning::ManyWorkers { workers, cores } => write!(f, "{} workers on {} CPU cores may cause contention", workers, cores),
+            ValidationWarning::NoBackends { name, .. } => write!(f, "Upstream pool '{}' has no backends", name),
+            ValidationWarning::MetricsNotServed => write!(f, "Metrics are limited to admin listeners but none is configured"),
+            ValidationWarning::NoUsers => write!(f, "Paths are protected but there are no active users to log in with"),
+            ValidationWarning::NoJwtKeys => write!(f, "JWT validation is enabled without any keys"),
+            ValidationWarning::InsecureOidcIssuer { issuer } => write!(f, "Identity provider '{}' is not reached over HTTPS", issuer),
+            ValidationWarning::AdminApiNotServed => write!(f, "The admin API is enabled but no admin listener is configured"),
+            ValidationWarning::InsecureAdminListener { name, .. } => {
+                write!(f, "Admin listener '{}' serves the admin API without HTTPS on a non-loopback address", name)
+            }
+            ValidationWarning::UnknownRole { index, role } => write!(f, "Access rule #{} allows role '{}' which no user, key or token has", index + 1, role),
+        }
+    }
+}
+
+// Outcome of validating a configuration
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<ValidationWarning>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn errors_for(&self, field: ConfigField) -> impl Iterator<Item = &ValidationError> {
+        self.errors.iter().filter(move |e| e.field() == field)
+    }
+
+    pub fn warnings_for(&self, field: ConfigField) -> impl Iterator<Item = &ValidationWarning> {
+        self.warnings.iter().filter(move |w| w.field() == field)
+    }
+}
+
+// Resolve a listener host to the address to bind. IP literals are used as is,
+// hostnames go through the local resolver and the first address wins.
+pub fn resolve_host(host: &str) -> Result<IpAddr, String> {
+    if let Ok(addr) = IpAddr::from_str(host) {
+        return Ok(addr);
+    }
+    if host.is_empty() {
+        return Err("host is empty".to_string());
+    }
+    match (host, 0).to_socket_addrs() {
+        Ok(mut addrs) => addrs
+            .next()
+            .map(|addr| addr.ip())
+            .ok_or_else(|| "hostname did not resolve to any address".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Parse an address or a CIDR network like "10.0.0.0/8" into the address and prefix length
+pub fn parse_network(entry: &str) -> Result<(IpAddr, u8), String> {
+    let (addr, prefix) = match entry.trim().split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry.trim(), None),
+    };
+    let addr = IpAddr::from_str(addr).map_err(|e| e.to_string())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|prefix| *prefix <= max)
+            .ok_or_else(|| format!("prefix length must be between 0 and {}", max))?,
+        None => max,
+    };
+    Ok((addr, prefix))
+}
+
+pub fn network_contains((network, prefix): (IpAddr, u8), ip: IpAddr) -> bool {
+    // IPv4 clients on a dual-stack socket show up as IPv4-mapped IPv6 addresses
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    };
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+// Whether `ip` is covered by an allow list, an empty list allows everyone
+pub fn is_trusted(entries: &[String], ip: IpAddr) -> bool {
+    entries.is_empty()
+        || entries
+            .iter()
+            .filter_map(|entry| parse_network(entry).ok())
+            .any(|network| network_contains(network, ip))
+}
+
+// Check the whole configuration without touching the network beyond name resolution
+pub fn validate_config(config: &ServerConfig) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    if config.listeners.is_empty() {
+        report.errors.push(ValidationError::NoListeners);
+    }
+
+    let mut resolved: Vec<Option<IpAddr>> = Vec::new();
+    for (index, listener) in config.listeners.iter().enumerate() {
+        if listener.name.trim().is_empty() {
+            report.errors.push(ValidationError::EmptyListenerName { index });
+        } else if config.listeners[..index].iter().any(|other| other.name == listener.name) {
+            report.errors.push(ValidationError::DuplicateListenerName { index, name: listener.name.clone() });
+        }
+
+        let addr = match resolve_host(&listener.host) {
+            Ok(addr) => Some(addr),
+            Err(reason) => {
+                report.errors.push(ValidationError::InvalidHost { index, host: listener.host.clone(), reason });
+                None
+            }
+        };
+
+        // Two sockets on one port clash when either is a wildcard or both are the same address
+        if let Some(addr) = addr {
+            let conflict = config.listeners[..index].iter().zip(&resolved).find(|(other, other_addr)| {
+                other.port == listener.port && other_addr.is_some_and(|other_addr| {
+                    other_addr == addr || other_addr.is_unspecified() || addr.is_unspecified()
+                })
+            });
+            if let Some((other, _)) = conflict {
+                report.errors.push(ValidationError::PortConflict { index, other: other.name.clone(), port: listener.port });
+            }
+        }
+        resolved.push(addr);
+
+        if listener.port < 1024 {
+            report.warnings.push(ValidationWarning::PrivilegedPort { index, port: listener.port });
+        }
+
+        if listener.protocol == Protocol::Https {
+            if listener.tls.cert_path.is_empty() || listener.tls.key_path.is_empty() {
+                report.errors.push(ValidationError::MissingTlsFiles { index });
+            } else {
+                for path in [&listener.tls.cert_path, &listener.tls.key_path] {
+                    if !Path::new(path).is_file() {
+                        report.errors.push(ValidationError::TlsFileNotFound { index, path: path.clone() });
+                    }
+                }
+                if let (Ok(chain), Ok(key)) = (fs::read(&listener.tls.cert_path), fs::read(&listener.tls.key_path)) {
+                    match certs::check_key_pair(&chain, &key) {
+                        Ok(info) if info["not_after"].as_i64().is_some_and(|not_after| not_after < jwt::unix_now() as i64) => {
+                            report.warnings.push(ValidationWarning::CertificateExpired { index, name: listener.name.clone() });
+                        }
+                        Ok(_) => {}
+                        Err(reason) => report.errors.push(ValidationError::InvalidCertificate { index, reason }),
+                    }
+                }
+            }
+        }
+    }
+
+    if config.max_connections == 0 {
+        report.errors.push(ValidationError::InvalidMaxConnections);
+    }
+    let workers = config.workers;
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    if workers == 0 {
+        report.errors.push(ValidationError::InvalidWorkerCount { workers });
+    } else if workers > cores * 4 {
+        report.warnings.push(ValidationWarning::ManyWorkers { workers, cores });
+    }
+
+    for entry in &config.status_trusted_ips {
+        if let Err(reason) = parse_network(entry) {
+            report.errors.push(ValidationError::InvalidTrustedIp { entry: entry.clone(), reason });
+        }
+    }
+
+    let access_log = &config.access_log;
+    if access_log.enabled {
+        if access_log.format == AccessLogFormat::Custom {
+            if let Err(reason) = access_log::check_template(&access_log.template) {
+                report.errors.push(ValidationError::InvalidAccessLogTemplate { reason });
+            }
+        }
+        let dir = Path::new(&access_log.path).parent().filter(|dir| !dir.as_os_str().is_empty());
+        if dir.is_some_and(|dir| !dir.is_dir()) {
+            report.errors.push(ValidationError::AccessLogDirMissing { path: access_log.path.clone() });
+        }
+    }
+
+    let forwarding = &config.log_forwarding;
+    if forwarding.target == ForwardTarget::Syslog {
+        if let Err(reason) = log_forward::check_address(forwarding.transport, &forwarding.address) {
+            report.errors.push(ValidationError::InvalidSyslogAddress { address: forwarding.address.clone(), reason });
+        }
+    }
+
+    let auth = &config.auth;
+    for path in &auth.protected_paths {
+        if !path.starts_with('/') {
+            report.errors.push(ValidationError::InvalidProtectedPath { path: path.clone() });
+        }
+    }
+    let users_file = Path::new(&auth.users_file);
+    let dir = users_file.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let problem = if auth.users_file.is_empty() {
+        Some("no path is set")
+    } else if dir.is_some_and(|dir| !dir.is_dir()) {
+        Some("its directory does not exist")
+    } else {
+        None
+    };
+    let users = if let Some(reason) = problem {
+        report.errors.push(ValidationError::InvalidUsersFile { path: auth.users_file.clone(), reason: reason.to_string() });
+        Vec::new()
+    } else {
+        users::read_users(users_file).unwrap_or_else(|e| {
+            report.errors.push(ValidationError::InvalidUsersFile { path: auth.users_file.clone(), reason: e.to_string() });
+            Vec::new()
+        })
+    };
+    for user in &users {
+        if !password::is_valid_hash(&user.password_hash) {
+            report.errors.push(ValidationError::InvalidPasswordHash { username: user.username.clone() });
+        }
+    }
+    if !auth.protected_paths.is_empty() && !users.iter().any(|user| !user.disabled) {
+        report.warnings.push(ValidationWarning::NoUsers);
+    }
+    let keys = if auth.api_keys.enabled { api_keys::read_keys(Path::new(&auth.api_keys.keys_file)) } else { Ok(Vec::new()) };
+    for (index, rule) in auth.rules.iter().enumerate() {
+        if let Err(reason) = rbac::check_rule(rule) {
+            report.errors.push(ValidationError::InvalidAccessRule { index, reason });
+            continue;
+        }
+        let special = [rbac::PUBLIC_ROLE, rbac::AUTHENTICATED_ROLE];
+        for role in rule.roles.iter().filter(|role| !special.contains(&role.as_str())) {
+            // Without a role map any role may come from a token or the identity provider
+            let granted = |enabled: bool, role_map: &[jwt::RoleMapping]| {
+                enabled && (role_map.is_empty() || role_map.iter().any(|mapping| &mapping.role == role))
+            };
+            let external = granted(auth.jwt.enabled, &auth.jwt.role_map) || granted(auth.oidc.enabled, &auth.oidc.role_map);
+            let held = users.iter().any(|user| user.roles.contains(role))
+                || keys.as_ref().is_ok_and(|keys| keys.iter().any(|key| key.roles.contains(role)));
+            if !external && !held {
+                report.warnings.push(ValidationWarning::UnknownRole { index, role: role.clone() });
+            }
+        }
+    }
+
+    let jwt_config = &auth.jwt;
+    if jwt_config.enabled {
+        for prefix in jwt_config.path_prefixes.iter().filter(|prefix| !prefix.starts_with('/')) {
+            report.errors.push(ValidationError::InvalidJwtPrefix { prefix: prefix.clone() });
+        }
+        match jwt::check_keys(jwt_config) {
+            Ok(0) => report.warnings.push(ValidationWarning::NoJwtKeys),
+            Ok(_) => {}
+            Err(reason) => report.errors.push(ValidationError::InvalidJwtKeys { reason }),
+        }
+    }
+
+    if auth.oidc.enabled {
+        match oidc::check_config(&auth.oidc) {
+            Ok(true) => {}
+            Ok(false) => report.warnings.push(ValidationWarning::InsecureOidcIssuer { issuer: auth.oidc.issuer.clone() }),
+            Err(reason) => report.errors.push(ValidationError::InvalidOidcSetting { reason }),
+        }
+    }
+
+    let key_config = &auth.api_keys;
+    if key_config.enabled {
+        let header_valid = !key_config.header.is_empty()
+            && key_config.header.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !header_valid {
+            report.errors.push(ValidationError::InvalidApiKeyHeader { name: key_config.header.clone() });
+        }
+        if let Err(e) = &keys {
+            report.errors.push(ValidationError::InvalidApiKeysFile { path: key_config.keys_file.clone(), reason: e.to_string() });
+        }
+    }
+
+    let session_config = &config.sessions;
+    if session_config.idle_timeout_secs == 0 || session_config.absolute_timeout_secs == 0 {
+        report.errors.push(ValidationError::InvalidSessionTimeout);
+    }
+    for path in [&session_config.store_path, &session_config.secret_key_path] {
+        let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+        if dir.is_some_and(|dir| !dir.is_dir()) {
+            report.errors.push(ValidationError::SessionFileDirMissing { path: path.clone() });
+        }
+    }
+    if let Err(reason) = sessions::check_key_file(&session_config.secret_key_path) {
+        report.errors.push(ValidationError::InvalidSessionKey { path: session_config.secret_key_path.clone(), reason });
+    }
+
+    if config.metrics.enabled && !config.listeners.iter().any(|listener| config.metrics.serve_on(listener.admin)) {
+        report.warnings.push(ValidationWarning::MetricsNotServed);
+    }
+
+    let admin_api = &config.admin_api;
+    if admin_api.enabled {
+        if admin_api.roles.is_empty() {
+            report.errors.push(ValidationError::InvalidAdminApiRoles { reason: "at least one role is needed".to_string() });
+        } else if let Some(role) = admin_api.roles.iter().find(|role| !users::valid_role(role)) {
+            report.errors.push(ValidationError::InvalidAdminApiRoles { reason: format!("'{}' is not a valid role name", role) });
+        }
+        if !config.listeners.iter().any(|listener| listener.admin) {
+            report.warnings.push(ValidationWarning::AdminApiNotServed);
+        }
+        for (index, listener) in config.listeners.iter().enumerate().filter(|(_, listener)| listener.admin) {
+            let loopback = resolve_host(&listener.host).is_ok_and(|addr| addr.is_loopback());
+            if listener.protocol == Protocol::Http && !loopback {
+                report.warnings.push(ValidationWarning::InsecureAdminListener { index, name: listener.name.clone() });
+            }
+        }
+    }
+
+    let control_socket = &config.control_socket;
+    if control_socket.enabled {
+        let reason = if control_socket.path.trim().is_empty() {
+            Err("no path is set".to_string())
+        } else {
+            control_socket::check_path(Path::new(&control_socket.path))
+        };
+        if let Err(reason) = reason {
+            report.errors.push(ValidationError::InvalidControlSocket { path: control_socket.path.clone(), reason });
+        }
+    }
+
+    for (index, upstream) in config.upstreams.iter().enumerate() {
+        if !upstream.path_prefix.starts_with('/') {
+            report.errors.push(ValidationError::InvalidPathPrefix { index, prefix: upstream.path_prefix.clone() });
+        } else if config.upstreams[..index].iter().any(|other| other.path_prefix == upstream.path_prefix) {
+            report.errors.push(ValidationError::DuplicatePathPrefix { index, prefix: upstream.path_prefix.clone() });
+        }
+        if upstream.backends.is_empty() {
+            report.warnings.push(ValidationWarning::NoBackends { index, name: upstream.name.clone() });
+        }
+        for backend in &upstream.backends {
+            let valid = backend
+                .address
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+            if !valid {
+                report.errors.push(ValidationError::InvalidBackendAddress { index, address: backend.address.clone() });
+            }
+        }
+    }
+
+    report
+}
+
+// Try binding each listener's address to catch ports held by other processes.
+// Only meaningful while the server itself is not holding them.
+pub fn check_ports_available(config: &ServerConfig) -> Vec<ValidationError> {
+    config
+        .listeners
+        .iter()
+        .enumerate()
+        .filter_map(|(index, listener)| {
+            let addr = resolve_host(&listener.host).ok()?;
+            TcpListener::bind((addr, listener.port)).err().map(|e| ValidationError::PortUnavailable {
+                index,
+                port: listener.port,
+                reason: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Whether a single listener's address can be bound right now
+pub fn check_port_available(listener: &ListenerConfig) -> Result<(), String> {
+    let addr = resolve_host(&listener.host)?;
+    TcpListener::bind((addr, listener.port))
+        .map(drop)
+        .map_err(|e| format!("{} can't be bound: {}", listener, e))
+}
+
+// A problem found by `--check-config`, with where it is
+pub struct Finding {
+    pub file: PathBuf,
+    // 1-based line and column of the setting, when it is written in the file
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(file: &Path, contents: &str, span: Option<Range<usize>>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            position: span.map(|span| line_and_column(contents, span.start)),
+            message: message.into(),
+        }
+    }
+
+    // "file:line:column" as editors and compilers print it
+    pub fn location(&self) -> String {
+        match self.position {
+            Some((line, column)) => format!("{}:{}:{}", self.file.display(), line, column),
+            None => self.file.display().to_string(),
+        }
+    }
+}
+
+// Everything `--check-config` reports about a configuration file
+#[derive(Default)]
+pub struct CheckReport {
+    pub errors: Vec<Finding>,
+    pub warnings: Vec<Finding>,
+}
+
+// Parse and validate a configuration file, pointing each problem at the setting it is
+// about. Nothing is bound, so ports held by other processes are not noticed.
+pub fn check_config_file(path: &Path) -> CheckReport {
+    let mut report = CheckReport::default();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            report.errors.push(Finding::new(path, "", None, e.to_string()));
+            return report;
+        }
+    };
+    let document = match ImDocument::parse(contents.as_str()) {
+        Ok(document) => document,
+        Err(e) => {
+            report.errors.push(Finding::new(path, &contents, e.span(), e.message().trim_end().replace('\n', "; ")));
+            return report;
+        }
+    };
+    let config: ServerConfig = match toml::from_str(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            report.errors.push(Finding::new(path, &contents, e.span(), e.message().trim_end().replace('\n', "; ")));
+            return report;
+        }
+    };
+
+    let validation = validate_config(&config);
+    for error in &validation.errors {
+        let finding = match error {
+            // Problems inside the users file point there
+            ValidationError::InvalidPasswordHash { username } => user_finding(Path::new(&config.auth.users_file), username, error),
+            _ => Finding::new(path, &contents, locate(&document, error.field()), error.to_string()),
+        };
+        report.errors.push(finding);
+    }
+    for warning in &validation.warnings {
+        report.warnings.push(Finding::new(path, &contents, locate(&document, warning.field()), warning.to_string()));
+    }
+
+    // Settings the server doesn't know are skipped when loading, e.g. misspelled ones
+    if let Ok(toml::Value::Table(known)) = toml::Value::try_from(&config) {
+        let mut unknown = Vec::new();
+        find_unknown_keys(document.as_table(), &known, "", &mut unknown);
+        for (name, span) in unknown {
+            report.warnings.push(Finding::new(path, &contents, span, format!("Unknown setting '{}' is ignored", name)));
+        }
+    }
+    report
+}
+
+// A step on the way from the document root to a setting
+enum Step {
+    Key(&'static str),
+    Index(usize),
+}
+
+fn field_path(field: ConfigField) -> Vec<Step> {
+    use Step::{Index, Key};
+    match field {
+        ConfigField::Listeners => vec![Key("listeners")],
+        ConfigField::ListenerName(index) => vec![Key("listeners"), Index(index), Key("name")],
+        ConfigField::ListenerHost(index) => vec![Key("listeners"), Index(index), Key("host")],
+        ConfigField::ListenerPort(index) => vec![Key("listeners"), Index(index), Key("port")],
+        ConfigField::ListenerTls(index) => vec![Key("listeners"), Index(index), Key("tls")],
+        ConfigField::MaxConnections => vec![Key("max_connections")],
+        ConfigField::Workers => vec![Key("workers")],
+        ConfigField::Metrics => vec![Key("metrics")],
+        ConfigField::StatusAccess => vec![Key("status_trusted_ips")],
+        ConfigField::AccessLog => vec![Key("access_log")],
+        ConfigField::LogForwarding => vec![Key("log_forwarding")],
+        ConfigField::Auth => vec![Key("auth")],
+        ConfigField::AccessRule(index) => vec![Key("auth"), Key("rules"), Index(index)],
+        ConfigField::Sessions => vec![Key("sessions")],
+        ConfigField::Jwt => vec![Key("auth"), Key("jwt")],
+        ConfigField::Oidc => vec![Key("auth"), Key("oidc")],
+        ConfigField::ApiKeys => vec![Key("auth"), Key("api_keys")],
+        ConfigField::AdminApi => vec![Key("admin_api")],
+        ConfigField::ControlSocket => vec![Key("control_socket")],
+        ConfigField::Upstream(index) => vec![Key("upstreams"), Index(index)],
+    }
+}
+
+// Span of the setting, or of the closest enclosing one written in the file.
+// Settings left at their default have no position.
+fn locate(document: &ImDocument<&str>, field: ConfigField) -> Option<Range<usize>> {
+    let mut item = document.as_item();
+    let mut span = None;
+    for step in field_path(field) {
+        let (next, next_span) = match step {
+            Step::Key(key) => {
+                let Some(table) = item.as_table_like() else { break };
+                let Some(next) = table.get(key) else { break };
+                (next, table.key(key).and_then(|key| key.span()).or_else(|| next.span()))
+            }
+            Step::Index(index) => {
+                let Some(next) = item.get(index) else { break };
+                (next, next.span())
+            }
+        };
+        item = next;
+        span = next_span.or(span);
+    }
+    span
+}
+
+fn user_finding(users_file: &Path, username: &str, error: &ValidationError) -> Finding {
+    let contents = fs::read_to_string(users_file).unwrap_or_default();
+    let span = ImDocument::parse(contents.as_str()).ok().and_then(|document| {
+        let users = document.get("users")?.as_array_of_tables()?;
+        let user = users.iter().find(|user| user.get("username").and_then(|name| name.as_str()) == Some(username))?;
+        user.key("password_hash").and_then(|key| key.span()).or_else(|| user.span())
+    });
+    Finding::new(users_file, &contents, span, error.to_string())
+}
+
+// Keys of `table` that don't appear in the configuration as the server understood it
+fn find_unknown_keys(table: &dyn TableLike, known: &toml::Table, prefix: &str, found: &mut Vec<(String, Option<Range<usize>>)>) {
+    for (key, item) in table.iter() {
+        let name = if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+        let Some(known) = known.get(key) else {
+            found.push((name, table.key(key).and_then(|key| key.span())));
+            continue;
+        };
+        if let (Some(table), Some(known)) = (item.as_table_like(), known.as_table()) {
+            find_unknown_keys(table, known, &name, found);
+        } else if let (Some(tables), Some(known)) = (item.as_array_of_tables(), known.as_array()) {
+            for (index, (table, known)) in tables.iter().zip(known).enumerate() {
+                if let Some(known) = known.as_table() {
+                    find_unknown_keys(table, known, &format!("{}[{}]", name, index + 1), found);
+                }
+            }
+        } else if let (Some(values), Some(known)) = (item.as_array(), known.as_array()) {
+            for (index, (value, known)) in values.iter().zip(known).enumerate() {
+                if let (Some(table), Some(known)) = (value.as_inline_table(), known.as_table()) {
+                    find_unknown_keys(table, known, &format!("{}[{}]", name, index + 1), found);
+                }
+            }
+        }
+    }
+}
+
+fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = contents.get(..offset).unwrap_or(contents);
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::{BackendConfig, UpstreamConfig};
+
+    fn listener(name: &str, host: &str, port: u16) -> ListenerConfig {
+        ListenerConfig { name: name.to_string(), host: host.to_string(), port, ..ListenerConfig::default() }
+    }
+
+    fn config(listeners: Vec<ListenerConfig>) -> ServerConfig {
+        ServerConfig { listeners, workers: 1, ..ServerConfig::default() }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let report = validate_config(&config(vec![listener("main", "127.0.0.1", 8000)]));
+        assert_eq!(report.errors, []);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn listeners_are_required_and_named_uniquely() {
+        assert_eq!(validate_config(&config(Vec::new())).errors, [ValidationError::NoListeners]);
+
+        let report = validate_config(&config(vec![
+            listener("main", "127.0.0.1", 8000),
+            listener(" ", "127.0.0.1", 8001),
+            listener("main", "127.0.0.1", 8002),
+        ]));
+        assert_eq!(report.errors, [
+            ValidationError::EmptyListenerName { index: 1 },
+            ValidationError::DuplicateListenerName { index: 2, name: "main".to_string() },
+        ]);
+        assert_eq!(report.errors[1].field(), ConfigField::ListenerName(2));
+    }
+
+    #[test]
+    fn ports_clash_on_the_same_or_a_wildcard_address() {
+        let report = validate_config(&config(vec![
+            listener("a", "127.0.0.1", 8000),
+            listener("b", "127.0.0.2", 8000),
+            listener("c", "0.0.0.0", 8000),
+            listener("d", "127.0.0.1", 8001),
+        ]));
+        assert_eq!(report.errors, [ValidationError::PortConflict { index: 2, other: "a".to_string(), port: 8000 }]);
+    }
+
+    #[test]
+    fn hosts_must_resolve() {
+        let report = validate_config(&config(vec![listener("main", "", 8000)]));
+        assert!(matches!(&report.errors[..], [ValidationError::InvalidHost { index: 0, .. }]));
+    }
+
+    #[test]
+    fn https_needs_existing_tls_files() {
+        let mut https = listener("secure", "127.0.0.1", 8443);
+        https.protocol = Protocol::Https;
+        let report = validate_config(&config(vec![https.clone()]));
+        assert_eq!(report.errors, [ValidationError::MissingTlsFiles { index: 0 }]);
+
+        https.tls.cert_path = "/nonexistent/cert.pem".to_string();
+        https.tls.key_path = "/nonexistent/key.pem".to_string();
+        let report = validate_config(&config(vec![https]));
+        assert_eq!(report.errors, [
+            ValidationError::TlsFileNotFound { index: 0, path: "/nonexistent/cert.pem".to_string() },
+            ValidationError::TlsFileNotFound { index: 0, path: "/nonexistent/key.pem".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn limits_and_workers() {
+        let mut server = config(vec![listener("main", "127.0.0.1", 80)]);
+        server.max_connections = 0;
+        server.workers = 0;
+        let report = validate_config(&server);
+        assert_eq!(report.errors, [ValidationError::InvalidMaxConnections, ValidationError::InvalidWorkerCount { workers: 0 }]);
+        assert_eq!(report.warnings, [ValidationWarning::PrivilegedPort { index: 0, port: 80 }]);
+
+        server.max_connections = 10;
+        server.workers = 100_000;
+        let report = validate_config(&server);
+        assert!(report.is_valid());
+        assert!(report.warnings.iter().any(|warning| matches!(warning, ValidationWarning::ManyWorkers { workers: 100_000, .. })));
+    }
+
+    #[test]
+    fn trusted_networks() {
+        let mut server = config(vec![listener("main", "127.0.0.1", 8000)]);
+        server.status_trusted_ips = vec!["10.0.0.0/8".to_string(), "::1".to_string(), "10.0.0.0/33".to_string()];
+        let report = validate_config(&server);
+        assert!(matches!(&report.errors[..], [ValidationError::InvalidTrustedIp { entry, .. }] if entry == "10.0.0.0/33"));
+
+        let network = parse_network("192.168.1.0/24").unwrap();
+        assert!(network_contains(network, "192.168.1.77".parse().unwrap()));
+        assert!(!network_contains(network, "192.168.2.1".parse().unwrap()));
+        assert!(is_trusted(&[], "203.0.113.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn upstream_prefixes_and_backends() {
+        let mut server = config(vec![listener("main", "127.0.0.1", 8000)]);
+        let pool = |prefix: &str, address: &str| UpstreamConfig {
+            path_prefix: prefix.to_string(),
+            backends: vec![BackendConfig { address: address.to_string(), weight: 1 }],
+            ..UpstreamConfig::default()
+        };
+        server.upstreams = vec![
+            pool("/api", "10.0.0.1:8080"),
+            pool("api", "10.0.0.2:8080"),
+            pool("/api", "10.0.0.3"),
+            UpstreamConfig { name: "empty".to_string(), path_prefix: "/empty".to_string(), ..UpstreamConfig::default() },
+        ];
+        let report = validate_config(&server);
+        assert_eq!(report.errors, [
+            ValidationError::InvalidPathPrefix { index: 1, prefix: "api".to_string() },
+            ValidationError::DuplicatePathPrefix { index: 2, prefix: "/api".to_string() },
+            ValidationError::InvalidBackendAddress { index: 2, address: "10.0.0.3".to_string() },
+        ]);
+        assert_eq!(report.warnings, [ValidationWarning::NoBackends { index: 3, name: "empty".to_string() }]);
+    }
+
+    fn check(name: &str, contents: &str) -> CheckReport {
+        let path = std::env::temp_dir().join(format!("solara-check-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let report = check_config_file(&path);
+        fs::remove_file(&path).unwrap();
+        report
+    }
+
+    fn positions(findings: &[Finding]) -> Vec<(Option<(usize, usize)>, String)> {
+        findings.iter().map(|finding| (finding.position, finding.message.clone())).collect()
+    }
+
+    #[test]
+    fn syntax_and_type_errors_point_at_the_text() {
+        let report = check("syntax", "workers = 1\n[[listeners]\nname = \"main\"\n");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].position.map(|(line, _)| line), Some(2));
+
+        let report = check("type", "workers = 1\n\n[[listeners]]\nname = \"main\"\nport = \"eighty\"\n");
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].position, Some((5, 8)));
+    }
+
+    #[test]
+    fn validation_errors_point_at_the_setting() {
+        let contents = "\
+workers = 1
+
+[[listeners]]
+name = \"main\"
+port = 8000
+
+[[listeners]]
+  name = \"main\"
+  port = 8001
+
+[[listeners]]
+name = \"tls\"
+port = 8443
+protocol = \"https\"
+";
+        let report = check("validation", contents);
+        assert_eq!(positions(&report.errors), [
+            (Some((8, 3)), ValidationError::DuplicateListenerName { index: 1, name: "main".to_string() }.to_string()),
+            // No [listeners.tls] table is written, so the listener itself is pointed at
+            (Some((11, 1)), ValidationError::MissingTlsFiles { index: 2 }.to_string()),
+        ]);
+    }
+
+    #[test]
+    fn unknown_settings_are_warned_about_where_they_are() {
+        let contents = "workers = 1\nworker = 2\n\n[[listeners]]\nname = \"main\"\nprot = 8000\n\n[sessions]\nidle_timeout = 60\n";
+        let report = check("unknown", contents);
+        assert_eq!(report.errors.len(), 0);
+        let unknown: Vec<_> = positions(&report.warnings).into_iter().filter(|(_, message)| message.starts_with("Unknown")).collect();
+        assert_eq!(unknown, [
+            (Some((2, 1)), "Unknown setting 'worker' is ignored".to_string()),
+            (Some((6, 1)), "Unknown setting 'listeners[1].prot' is ignored".to_string()),
+            (Some((9, 1)), "Unknown setting 'sessions.idle_timeout' is ignored".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn unreadable_files_have_no_position() {
+        let path = Path::new("/nonexistent/solara.toml");
+        let report = check_config_file(path);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].position, None);
+        assert_eq!(report.errors[0].location(), "/nonexistent/solara.toml");
+    }
+
+    #[test]
+    fn columns_count_characters() {
+        let contents = "a = 1\nname = \"żółw\" # x\n";
+        let offset = contents.find('#').unwrap();
+        assert_eq!(line_and_column(contents, offset), (2, 15));
+        assert_eq!(line_and_column(contents, 0), (1, 1));
+        let finding = Finding::new(Path::new("solara.toml"), contents, Some(offset..offset + 1), "x");
+        assert_eq!(finding.location(), "solara.toml:2:15");
+    }
+}
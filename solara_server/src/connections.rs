@@ -0,0 +1,260 @@
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::watch;
+use crate::api_keys;
+
+// Rocket does not expose its connections, so they are tracked by the listener and
+// peer address requests arrive on. A connection counts as closed once it was idle
+// for longer than the keep-alive timeout.
+static CONNECTIONS: Mutex<Option<HashMap<(String, SocketAddr), Connection>>> = Mutex::new(None);
+
+// Keep-alive timeout of the running server, in seconds
+static IDLE_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(0);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+struct Connection {
+    id: u64,
+    secure: bool,
+    opened: SystemTime,
+    last_active: Instant,
+    requests: u64,
+    bytes_received: u64,
+    bytes_sent: u64,
+    in_flight: usize,
+    // Method and URI of the request being handled
+    current: Option<String>,
+    // Set to true when the connection is asked to close
+    closing: watch::Sender<bool>,
+}
+
+impl Connection {
+    fn expired(&self, idle_timeout: Duration) -> bool {
+        self.in_flight == 0 && self.last_active.elapsed() > idle_timeout
+    }
+}
+
+// A live connection, for the Connection Monitor
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub listener: String,
+    pub remote: SocketAddr,
+    pub secure: bool,
+    pub opened: SystemTime,
+    pub requests: u64,
+    // Request body bytes announced by the client
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub current_request: Option<String>,
+    pub closing: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionColumn {
+    Client,
+    Listener,
+    Opened,
+    Requests,
+    Received,
+    Sent,
+    Current,
+}
+
+impl fmt::Display for ConnectionColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionColumn::Client => write!(f, "Client"),
+            ConnectionColumn::Listener => write!(f, "Listener"),
+            ConnectionColumn::Opened => write!(f, "Open For"),
+            ConnectionColumn::Requests => write!(f, "Requests"),
+            ConnectionColumn::Received => write!(f, "Received"),
+            ConnectionColumn::Sent => write!(f, "Sent"),
+            ConnectionColumn::Current => write!(f, "Current Request"),
+        }
+    }
+}
+
+impl ConnectionColumn {
+    pub const ALL: [ConnectionColumn; 7] = [
+        ConnectionColumn::Client, ConnectionColumn::Listener, ConnectionColumn::Opened, ConnectionColumn::Requests,
+        ConnectionColumn::Received, ConnectionColumn::Sent, ConnectionColumn::Current,
+    ];
+
+    pub fn compare(&self, a: &ConnectionInfo, b: &ConnectionInfo) -> Ordering {
+        match self {
+            ConnectionColumn::Client => a.remote.cmp(&b.remote),
+            ConnectionColumn::Listener => a.listener.cmp(&b.listener),
+            // Longest open first when ascending by age
+            ConnectionColumn::Opened => b.opened.cmp(&a.opened),
+            ConnectionColumn::Requests => a.requests.cmp(&b.requests),
+            ConnectionColumn::Received => a.bytes_received.cmp(&b.bytes_received),
+            ConnectionColumn::Sent => a.bytes_sent.cmp(&b.bytes_sent),
+            ConnectionColumn::Current => a.current_request.cmp(&b.current_request),
+        }
+    }
+}
+
+// Start tracking with the keep-alive timeout of the configuration
+pub fn apply_connections(keep_alive_secs: u32) {
+    IDLE_TIMEOUT_SECS.store(keep_alive_secs as u64, atomic::Ordering::Relaxed);
+    CONNECTIONS.lock().unwrap().get_or_insert_with(HashMap::new);
+}
+
+pub fn clear_connections() {
+    *CONNECTIONS.lock().unwrap() = None;
+}
+
+fn idle_timeout() -> Duration {
+    Duration::from_secs(IDLE_TIMEOUT_SECS.load(atomic::Ordering::Relaxed))
+}
+
+pub fn open_connections() -> Vec<ConnectionInfo> {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let Some(connections) = connections.as_mut() else {
+        return Vec::new();
+    };
+    let idle_timeout = idle_timeout();
+    connections.retain(|_, connection| !connection.expired(idle_timeout));
+    connections
+        .iter()
+        .map(|((listener, remote), connection)| ConnectionInfo {
+            id: connection.id,
+            listener: listener.clone(),
+            remote: *remote,
+            secure: connection.secure,
+            opened: connection.opened,
+            requests: connection.requests,
+            bytes_received: connection.bytes_received,
+            bytes_sent: connection.bytes_sent,
+            current_request: connection.current.clone(),
+            closing: *connection.closing.borrow(),
+        })
+        .collect()
+}
+
+// Ask a connection to close. A request in progress is cancelled with 503, and the
+// response tells the client the connection is closing. Idle connections close at
+// their next request or once the keep-alive timeout runs out.
+pub fn close_connection(id: u64) -> bool {
+    let connections = CONNECTIONS.lock().unwrap();
+    let connection = connections.as_ref().and_then(|connections| connections.values().find(|c| c.id == id));
+    match connection {
+        Some(connection) => {
+            connection.closing.send_replace(true);
+            true
+        }
+        None => false,
+    }
+}
+
+// Resolves once the connection of the request is asked to close, never for untracked requests
+pub async fn closed(req: &Request<'_>) {
+    let receiver = req.local_cache(|| ConnectionHandle(None)).0.clone();
+    match receiver {
+        Some(mut receiver) => {
+            // An error means tracking stopped, which is not a reason to cancel
+            if receiver.wait_for(|closing| *closing).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Called once the response of a request was written or the client went away
+pub fn request_finished(listener: &str, remote: Option<SocketAddr>, bytes_sent: u64) {
+    let Some(remote) = remote else {
+        return;
+    };
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let Some(connections) = connections.as_mut() else {
+        return;
+    };
+    let key = (listener.to_string(), remote);
+    let Some(connection) = connections.get_mut(&key) else {
+        return;
+    };
+    connection.in_flight = connection.in_flight.saturating_sub(1);
+    connection.bytes_sent += bytes_sent;
+    connection.last_active = Instant::now();
+    if connection.in_flight == 0 {
+        connection.current = None;
+        // The response told the client the connection is closing
+        if *connection.closing.borrow() {
+            connections.remove(&key);
+        }
+    }
+}
+
+// Close signal of the connection a request arrived on
+struct ConnectionHandle(Option<watch::Receiver<bool>>);
+
+// Records every request against the connection it arrived on
+pub struct ConnectionFairing {
+    pub listener: String,
+    pub secure: bool,
+}
+
+#[rocket::async_trait]
+impl Fairing for ConnectionFairing {
+    fn info(&self) -> Info {
+        Info { name: "Connection Tracking", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let Some(remote) = req.remote() else {
+            return;
+        };
+        let mut connections = CONNECTIONS.lock().unwrap();
+        let Some(connections) = connections.as_mut() else {
+            return;
+        };
+
+        // The client may have reconnected from the same port after the old connection timed out
+        let key = (self.listener.clone(), remote);
+        if connections.get(&key).is_some_and(|connection| connection.expired(idle_timeout())) {
+            connections.remove(&key);
+        }
+        let connection = connections.entry(key).or_insert_with(|| Connection {
+            id: NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed),
+            secure: self.secure,
+            opened: SystemTime::now(),
+            last_active: Instant::now(),
+            requests: 0,
+            bytes_received: 0,
+            bytes_sent: 0,
+            in_flight: 0,
+            current: None,
+            closing: watch::channel(false).0,
+        });
+        connection.requests += 1;
+        connection.in_flight += 1;
+        connection.last_active = Instant::now();
+        connection.bytes_received += req
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok())
+            .unwrap_or(0);
+        connection.current = Some(format!("{} {}", req.method(), api_keys::redacted_uri(req.uri())));
+        let receiver = connection.closing.subscribe();
+        req.local_cache(|| ConnectionHandle(Some(receiver)));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let closing = req.local_cache(|| ConnectionHandle(None)).0.as_ref().is_some_and(|receiver| *receiver.borrow());
+        if closing {
+            res.set_header(Header::new("Connection", "close"));
+        }
+    }
+}
@@ -0,0 +1,677 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use crate::config::{self, ValidationError};
+use crate::control_socket::{self, ControlSocket, ControlSocketConfig};
+use crate::log_forward;
+use crate::logs;
+use crate::notifications::Severity;
+use crate::server::{self, ListenerConfig, ServerConfig, ServerStatus};
+
+// How a reload was carried out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReloadKind {
+    // Settings were swapped inside the running server
+    Live,
+    // Some listeners were started, stopped or handed over to a new socket
+    Handover,
+    // Some listeners keep their address and restart on it once drained. Rocket can't
+    // share a socket with its successor, so they refuse connections until they are back.
+    Restart,
+}
+
+impl fmt::Display for ReloadKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadKind::Live => write!(f, "applied live"),
+            ReloadKind::Handover => write!(f, "applied with listener handover"),
+            ReloadKind::Restart => write!(f, "applied, listeners on the same address restart once drained"),
+        }
+    }
+}
+
+// Snapshot of a listener for display
+#[derive(Clone, Debug)]
+pub struct ListenerState {
+    pub config: ListenerConfig,
+    pub status: ServerStatus,
+}
+
+// Owns the lifecycle of the server running in the background runtime
+#[derive(Clone)]
+pub struct ServerController {
+    runtime: Handle,
+    state: Arc<Mutex<ControllerState>>,
+}
+
+struct ControllerState {
+    listeners: Vec<ListenerSlot>,
+    next_generation: u64,
+    running_config: Option<ServerConfig>,
+    // Messages for the log viewer, drained by the GUI
+    messages: Vec<(Severity, String)>,
+    // Configuration applied through the admin API, picked up by the GUI
+    remote_config: Option<ServerConfig>,
+    // Open from start to stop, independent of the listeners
+    control_socket: Option<ControlSocket>,
+}
+
+// A listener and the server instance currently serving it
+struct ListenerSlot {
+    config: ListenerConfig,
+    status: ServerStatus,
+    // Bumped for every instance so a draining instance can't report over its successor
+    generation: u64,
+    shutdown_sender: Option<oneshot::Sender<()>>,
+    // Start again once the instance has shut down
+    restart: bool,
+}
+
+// What a reload does with a configured listener
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ListenerChange {
+    Keep,
+    // New socket on another address, the old one drains once it serves
+    Handover,
+    // Same address, drain and start again
+    Restart,
+    // Not running, e.g. new or failed earlier
+    Start,
+}
+
+// What a reload from `running` to `config` does with each configured listener
+fn plan_reload<'a>(slots: &[ListenerSlot], running: &ServerConfig, config: &'a ServerConfig) -> Vec<(&'a ListenerConfig, ListenerChange)> {
+    let restart_all = running.requires_restart(config);
+    config
+        .listeners
+        .iter()
+        .map(|listener| {
+            let change = match slots.iter().find(|slot| slot.config.name == listener.name) {
+                Some(slot) if slot.status != ServerStatus::Running => ListenerChange::Start,
+                Some(slot) if !restart_all && slot.config == *listener => ListenerChange::Keep,
+                Some(slot) if same_address(&slot.config, listener) => ListenerChange::Restart,
+                Some(_) => ListenerChange::Handover,
+                None => ListenerChange::Start,
+            };
+            (listener, change)
+        })
+        .collect()
+}
+
+// The instance being replaced while a listener is handed over
+struct Handover {
+    config: ListenerConfig,
+    generation: u64,
+    shutdown_sender: oneshot::Sender<()>,
+}
+
+impl ControllerState {
+    fn log(&mut self, message: String) {
+        println!("{}", message);
+        log_forward::server_message(Severity::Info, &message);
+        logs::record(Severity::Info, &message);
+        self.messages.push((Severity::Info, message));
+    }
+
+    // Problems are also raised as notifications in the GUI
+    fn report(&mut self, severity: Severity, message: String) {
+        eprintln!("{}", message);
+        log_forward::server_message(severity, &message);
+        logs::record(severity, &message);
+        self.messages.push((severity, message));
+    }
+
+    fn slot_mut(&mut self, name: &str) -> Option<&mut ListenerSlot> {
+        self.listeners.iter_mut().find(|slot| slot.config.name == name)
+    }
+
+    // Combined status of all listeners
+    fn status(&self) -> ServerStatus {
+        let any = |f: fn(&ServerStatus) -> bool| self.listeners.iter().any(|slot| f(&slot.status));
+        if any(|s| *s == ServerStatus::Starting) {
+            ServerStatus::Starting
+        } else if any(|s| *s == ServerStatus::Stopping) {
+            ServerStatus::Stopping
+        } else if any(|s| *s == ServerStatus::Running) {
+            ServerStatus::Running
+        } else if let Some(error) = self.listeners.iter().find(|slot| matches!(slot.status, ServerStatus::Error(_))) {
+            error.status.clone()
+        } else {
+            ServerStatus::Stopped
+        }
+    }
+
+    fn all_stopped(&self) -> bool {
+        self.listeners
+            .iter()
+            .all(|slot| matches!(slot.status, ServerStatus::Stopped | ServerStatus::Error(_)))
+    }
+}
+
+impl ServerController {
+    pub fn new(runtime: Handle) -> Self {
+        Self {
+            runtime,
+            state: Arc::new(Mutex::new(ControllerState {
+                listeners: Vec::new(),
+                next_generation: 0,
+                running_config: None,
+                messages: Vec::new(),
+                remote_config: None,
+                control_socket: None,
+            })),
+        }
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.state.lock().unwrap().status()
+    }
+
+    pub fn listeners(&self) -> Vec<ListenerState> {
+        self.state
+            .lock()
+            .unwrap()
+            .listeners
+            .iter()
+            .map(|slot| ListenerState {
+                config: slot.config.clone(),
+                status: slot.status.clone(),
+            })
+            .collect()
+    }
+
+    pub fn take_messages(&self) -> Vec<(Severity, String)> {
+        std::mem::take(&mut self.state.lock().unwrap().messages)
+    }
+
+    pub fn log(&self, message: String) {
+        self.state.lock().unwrap().log(message);
+    }
+
+    pub fn running_config(&self) -> Option<ServerConfig> {
+        self.state.lock().unwrap().running_config.clone()
+    }
+
+    pub fn take_remote_config(&self) -> Option<ServerConfig> {
+        self.state.lock().unwrap().remote_config.take()
+    }
+
+    pub fn start(&self, config: ServerConfig) -> Result<(), Vec<ValidationError>> {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(state.status(), ServerStatus::Stopped | ServerStatus::Error(_)) {
+            state.log("Server is already running".to_string());
+            return Ok(());
+        }
+        // Refuse to start rather than binding something the user did not ask for
+        let mut errors = config::validate_config(&config).errors;
+        if errors.is_empty() {
+            errors = config::check_ports_available(&config);
+        }
+        if !errors.is_empty() {
+            for e in &errors {
+                state.report(Severity::Error, format!("Cannot start server: {}", e));
+            }
+            state.listeners.clear();
+            return Err(errors);
+        }
+
+        {
+            let _guard = self.runtime.enter();
+            server::apply_live_config(&config);
+        }
+        state.running_config = Some(config.clone());
+        self.update_control_socket(&mut state, &config.control_socket);
+        state.listeners = config.listeners.iter().map(|listener| ListenerSlot {
+            config: listener.clone(),
+            status: ServerStatus::Starting,
+            generation: 0,
+            shutdown_sender: None,
+            restart: false,
+        }).collect();
+        for listener in &config.listeners {
+            state.log(format!("Starting listener '{}' on {}", listener.name, listener));
+            self.spawn_instance(&mut state, &config, listener.clone(), None);
+        }
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        let mut signalled = false;
+        for slot in state.listeners.iter_mut() {
+            slot.restart = false;
+            if let Some(sender) = slot.shutdown_sender.take() {
+                slot.status = ServerStatus::Stopping;
+                if sender.send(()).is_err() {
+                    slot.status = ServerStatus::Stopped; // Assume stopped
+                }
+                signalled = true;
+            }
+        }
+        if signalled {
+            state.log("Stopping server".to_string());
+        } else {
+            state.log("Server not running or already stopping".to_string());
+        }
+        if let Some(socket) = state.control_socket.take() {
+            state.log(format!("Closed control socket {}", socket.path().display()));
+        }
+        if state.all_stopped() {
+            server::clear_live_config();
+            state.running_config = None;
+        }
+    }
+
+    // Stop serving the site while admin listeners keep running, so it can be started remotely again
+    pub fn stop_site(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let mut signalled = false;
+        for slot in state.listeners.iter_mut().filter(|slot| !slot.config.admin) {
+            slot.restart = false;
+            if let Some(sender) = slot.shutdown_sender.take() {
+                slot.status = ServerStatus::Stopping;
+                if sender.send(()).is_err() {
+                    slot.status = ServerStatus::Stopped;
+                }
+                signalled = true;
+            }
+        }
+        if !signalled {
+            return Err("No site listener is running".to_string());
+        }
+        state.log("Stopping site listeners".to_string());
+        Ok(())
+    }
+
+    // Start the site listeners stopped by `stop_site` or failed since, with the running configuration
+    pub fn start_site(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(config) = state.running_config.clone() else {
+            return Err("Server is not running".to_string());
+        };
+        let stopped: Vec<ListenerConfig> = state
+            .listeners
+            .iter()
+            .filter(|slot| !slot.config.admin && matches!(slot.status, ServerStatus::Stopped | ServerStatus::Error(_)))
+            .map(|slot| slot.config.clone())
+            .collect();
+        if stopped.is_empty() {
+            return Err("No site listener is stopped".to_string());
+        }
+        for listener in stopped {
+            state.slot_mut(&listener.name).expect("listener slot exists").status = ServerStatus::Starting;
+            state.log(format!("Starting listener '{}' on {}", listener.name, listener));
+            self.spawn_instance(&mut state, &config, listener, None);
+        }
+        Ok(())
+    }
+
+    // Reload on behalf of a remote client, the GUI then adopts the configuration
+    pub fn reload_remote(&self, config: ServerConfig) -> Result<ReloadKind, String> {
+        let kind = self.reload(config.clone())?;
+        self.state.lock().unwrap().remote_config = Some(config);
+        Ok(kind)
+    }
+
+    // Restart a running listener once it has drained, e.g. to pick up a renewed certificate.
+    // A handover can't bind the same address while the old socket is still open.
+    pub fn restart_listener(&self, name: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(slot) = state.slot_mut(name) else {
+            return Err(format!("Listener '{}' does not exist", name));
+        };
+        let Some(sender) = slot.shutdown_sender.take().filter(|_| slot.status == ServerStatus::Running) else {
+            return Err(format!("Listener '{}' is not running", name));
+        };
+        slot.restart = true;
+        slot.status = ServerStatus::Stopping;
+        if sender.send(()).is_err() {
+            slot.restart = false;
+            slot.status = ServerStatus::Stopped;
+            return Err(format!("Listener '{}' is not running", name));
+        }
+        state.log(format!("Restarting listener '{}'", name));
+        Ok(())
+    }
+
+    // How a reload to `config` would be carried out, for labelling it before it's applied
+    pub fn reload_kind(&self, config: &ServerConfig) -> Option<ReloadKind> {
+        let state = self.state.lock().unwrap();
+        let running = state.running_config.as_ref()?;
+        let plan = plan_reload(&state.listeners, running, config);
+        let removed = state.listeners.iter().any(|slot| !config.listeners.iter().any(|l| l.name == slot.config.name));
+        Some(if plan.iter().any(|(_, change)| *change == ListenerChange::Restart) {
+            ReloadKind::Restart
+        } else if removed || plan.iter().any(|(_, change)| *change != ListenerChange::Keep) {
+            ReloadKind::Handover
+        } else {
+            ReloadKind::Live
+        })
+    }
+
+    // Apply a new configuration to the running server without dropping requests.
+    // Listeners moving to another address start a new socket and drain the old one.
+    // The old socket can't be bound twice, so listeners that keep their address but need
+    // a restart, e.g. for new certificates, drain first and then start again like
+    // `restart_listener`. They refuse connections in between.
+    pub fn reload(&self, config: ServerConfig) -> Result<ReloadKind, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.status() != ServerStatus::Running {
+            return Err("Server is not running".to_string());
+        }
+        let report = config::validate_config(&config);
+        if let Some(e) = report.errors.first() {
+            return Err(format!("Invalid configuration: {}", e));
+        }
+        let running = state.running_config.clone().unwrap_or_default();
+        let plan = plan_reload(&state.listeners, &running, &config);
+        // Nothing is applied unless every new socket can be bound
+        for (listener, change) in &plan {
+            if matches!(change, ListenerChange::Handover | ListenerChange::Start) {
+                config::check_port_available(listener)
+                    .map_err(|e| format!("Listener '{}' can't be started: {}", listener.name, e))?;
+            }
+        }
+
+        // The proxy routes look pools up through the live config, swapping it is enough
+        {
+            let _guard = self.runtime.enter();
+            server::apply_live_config(&config);
+        }
+        state.running_config = Some(config.clone());
+        self.update_control_socket(&mut state, &config.control_socket);
+        let mut kind = ReloadKind::Live;
+
+        // Listeners that are no longer configured are drained and dropped
+        let (kept, removed): (Vec<ListenerSlot>, Vec<ListenerSlot>) = std::mem::take(&mut state.listeners)
+            .into_iter()
+            .partition(|slot| config.listeners.iter().any(|l| l.name == slot.config.name));
+        state.listeners = kept;
+        for slot in removed {
+            state.log(format!("Stopping removed listener '{}'", slot.config.name));
+            if let Some(sender) = slot.shutdown_sender {
+                let _ = sender.send(());
+            }
+            kind = ReloadKind::Handover;
+        }
+
+        for (listener, change) in plan {
+            let handover = match change {
+                ListenerChange::Keep => continue,
+                ListenerChange::Restart => {
+                    let slot = state.slot_mut(&listener.name).expect("listener slot exists");
+                    // The instance starts again from the slot once it has shut down
+                    slot.config = listener.clone();
+                    slot.restart = true;
+                    slot.status = ServerStatus::Stopping;
+                    if slot.shutdown_sender.take().is_none_or(|sender| sender.send(()).is_err()) {
+                        slot.restart = false;
+                        slot.status = ServerStatus::Stopped;
+                        state.report(Severity::Error, format!("Listener '{}' could not be restarted", listener.name));
+                        continue;
+                    }
+                    state.log(format!("Restarting listener '{}' on {} once drained, it refuses connections until then",
+                        listener.name, listener));
+                    kind = ReloadKind::Restart;
+                    continue;
+                }
+                ListenerChange::Handover => {
+                    let slot = state.slot_mut(&listener.name).expect("listener slot exists");
+                    slot.shutdown_sender.take().map(|shutdown_sender| Handover {
+                        config: slot.config.clone(),
+                        generation: slot.generation,
+                        shutdown_sender,
+                    })
+                }
+                ListenerChange::Start => {
+                    match state.slot_mut(&listener.name) {
+                        // A listener that failed earlier gets a fresh start
+                        Some(slot) => slot.status = ServerStatus::Starting,
+                        None => state.listeners.push(ListenerSlot {
+                            config: listener.clone(),
+                            status: ServerStatus::Starting,
+                            generation: 0,
+                            shutdown_sender: None,
+                            restart: false,
+                        }),
+                    }
+                    None
+                }
+            };
+            match &handover {
+                Some(previous) => state.log(format!("Handing listener '{}' over from {} to {}",
+                    listener.name, previous.config, listener)),
+                None => state.log(format!("Starting listener '{}' on {}", listener.name, listener)),
+            }
+            self.spawn_instance(&mut state, &config, listener.clone(), handover);
+            if kind == ReloadKind::Live {
+                kind = ReloadKind::Handover;
+            }
+        }
+
+        if kind == ReloadKind::Live {
+            state.log("Configuration reloaded without restart".to_string());
+        }
+        Ok(kind)
+    }
+
+    // Open, move or close the control socket to match the configuration
+    fn update_control_socket(&self, state: &mut ControllerState, config: &ControlSocketConfig) {
+        let wanted = config.enabled.then(|| Path::new(&config.path));
+        if state.control_socket.as_ref().map(|socket| socket.path()) == wanted {
+            return;
+        }
+        if let Some(socket) = state.control_socket.take() {
+            state.log(format!("Closed control socket {}", socket.path().display()));
+        }
+        let Some(path) = wanted else {
+            return;
+        };
+        let _guard = self.runtime.enter();
+        match control_socket::open(path, self.clone()) {
+            Ok(socket) => {
+                state.log(format!("Control socket listening on {}", path.display()));
+                state.control_socket = Some(socket);
+            }
+            Err(e) => state.report(Severity::Error, format!("Failed to open control socket {}: {}", path.display(), e)),
+        }
+    }
+
+    // Ignite and launch the instance for one listener. When `previous` is given it is only
+    // told to shut down once the new socket is serving, and keeps running if that fails.
+    fn spawn_instance(
+        &self,
+        state: &mut ControllerState,
+        config: &ServerConfig,
+        listener: ListenerConfig,
+        previous: Option<Handover>,
+    ) {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let (liftoff_tx, liftoff_rx) = oneshot::channel();
+        let mut pending_shutdown = Some(shutdown_tx);
+
+        state.next_generation += 1;
+        let generation = state.next_generation;
+        let slot = state.slot_mut(&listener.name).expect("listener slot exists");
+        slot.generation = generation;
+        slot.config = listener.clone();
+        // A fresh start can be stopped while it is still starting up
+        if previous.is_none() {
+            slot.shutdown_sender = pending_shutdown.take();
+        }
+
+        let state = self.state.clone();
+        let previous = Arc::new(Mutex::new(previous));
+        let config = config.clone();
+        let name = listener.name.clone();
+
+        self.runtime.spawn(async move {
+            // Phase 1: Ignite the server
+            let rocket_instance = match server::start_server(config, listener, liftoff_tx).await {
+                Ok(rocket_instance) => rocket_instance,
+                Err(e) => {
+                    let mut state = state.lock().unwrap();
+                    state.report(Severity::Error, format!("Listener '{}' failed to ignite: {}", name, e));
+                    restore_or_fail(&mut state, &name, generation, previous.lock().unwrap().take(), e.to_string());
+                    return;
+                }
+            };
+
+            // Hand the previous instance's shutdown over once the new one is serving
+            let liftoff_state = state.clone();
+            let liftoff_previous = previous.clone();
+            let liftoff_name = name.clone();
+            tokio::spawn(async move {
+                if liftoff_rx.await.is_err() {
+                    return;
+                }
+                let mut state = liftoff_state.lock().unwrap();
+                let Some(slot) = state.slot_mut(&liftoff_name) else {
+                    return;
+                };
+                if slot.generation != generation || slot.status == ServerStatus::Stopping {
+                    return;
+                }
+                slot.status = ServerStatus::Running;
+                if let Some(shutdown_tx) = pending_shutdown {
+                    slot.shutdown_sender = Some(shutdown_tx);
+                }
+                state.log(format!("Listener '{}' is running", liftoff_name));
+                if let Some(previous) = liftoff_previous.lock().unwrap().take() {
+                    let _ = previous.shutdown_sender.send(());
+                    state.log(format!("Previous socket of listener '{}' is draining", liftoff_name));
+                }
+            });
+
+            // Phase 2: Launch the ignited server until it is told to shut down
+            let result = server::launch_ignited_server(rocket_instance, shutdown_rx).await;
+            let controller = ServerController { runtime: Handle::current(), state: state.clone() };
+            let mut state = state.lock().unwrap();
+            if state.slot_mut(&name).is_none_or(|slot| slot.generation != generation) {
+                // Removed, or a newer instance took over and owns the status now
+                return;
+            }
+            if let Err(e) = &result {
+                state.report(Severity::Error, format!("Listener '{}' failed: {}", name, e));
+                // Failing before liftoff leaves the previous instance untouched
+                if let Some(previous) = previous.lock().unwrap().take() {
+                    restore_or_fail(&mut state, &name, generation, Some(previous), e.to_string());
+                    return;
+                }
+            }
+            let slot = state.slot_mut(&name).expect("listener slot exists");
+            slot.shutdown_sender = None;
+            slot.status = match result {
+                Ok(_) => ServerStatus::Stopped,
+                Err(e) => ServerStatus::Error(e.to_string()),
+            };
+            let restart = std::mem::take(&mut slot.restart) && slot.status == ServerStatus::Stopped;
+            if slot.status == ServerStatus::Stopped {
+                state.log(format!("Listener '{}' shut down gracefully", name));
+            }
+            if let Some(config) = state.running_config.clone().filter(|_| restart) {
+                let slot = state.slot_mut(&name).expect("listener slot exists");
+                slot.status = ServerStatus::Starting;
+                let listener = slot.config.clone();
+                state.log(format!("Starting listener '{}' on {}", name, listener));
+                controller.spawn_instance(&mut state, &config, listener, None);
+                return;
+            }
+            if state.all_stopped() {
+                server::clear_live_config();
+                state.running_config = None;
+            }
+        });
+    }
+}
+
+fn same_address(a: &ListenerConfig, b: &ListenerConfig) -> bool {
+    a.host == b.host && a.port == b.port
+}
+
+// A failed handover leaves the previous instance in charge, a failed start is an error
+fn restore_or_fail(
+    state: &mut ControllerState,
+    name: &str,
+    generation: u64,
+    previous: Option<Handover>,
+    error: String,
+) {
+    let restored = previous.is_some();
+    let Some(slot) = state.slot_mut(name).filter(|slot| slot.generation == generation) else {
+        return;
+    };
+    match previous {
+        Some(previous) => {
+            slot.config = previous.config;
+            slot.generation = previous.generation;
+            slot.shutdown_sender = Some(previous.shutdown_sender);
+        }
+        None => {
+            slot.shutdown_sender = None;
+            slot.status = ServerStatus::Error(error);
+        }
+    }
+    if restored {
+        state.report(Severity::Warning, format!("Handover of listener '{}' failed, keeping the previous socket", name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(name: &str, port: u16) -> ListenerConfig {
+        ListenerConfig { name: name.to_string(), port, ..ListenerConfig::default() }
+    }
+
+    fn slot(config: ListenerConfig, status: ServerStatus) -> ListenerSlot {
+        ListenerSlot { config, status, generation: 0, shutdown_sender: None, restart: false }
+    }
+
+    fn config(listeners: Vec<ListenerConfig>) -> ServerConfig {
+        ServerConfig { listeners, ..ServerConfig::default() }
+    }
+
+    fn changes(slots: &[ListenerSlot], running: &ServerConfig, config: &ServerConfig) -> Vec<(String, ListenerChange)> {
+        plan_reload(slots, running, config).into_iter().map(|(listener, change)| (listener.name.clone(), change)).collect()
+    }
+
+    #[test]
+    fn unchanged_running_listeners_are_kept_and_failed_ones_started() {
+        let running = config(vec![listener("main", 8000), listener("broken", 8001)]);
+        let slots = [
+            slot(listener("main", 8000), ServerStatus::Running),
+            slot(listener("broken", 8001), ServerStatus::Error("address in use".to_string())),
+        ];
+        let new = config(vec![listener("main", 8000), listener("broken", 8001), listener("extra", 8002)]);
+        assert_eq!(changes(&slots, &running, &new), [
+            ("main".to_string(), ListenerChange::Keep),
+            ("broken".to_string(), ListenerChange::Start),
+            ("extra".to_string(), ListenerChange::Start),
+        ]);
+    }
+
+    #[test]
+    fn changed_listeners_restart_on_their_address_or_hand_over() {
+        let running = config(vec![listener("main", 8000), listener("side", 8001)]);
+        let slots = [slot(listener("main", 8000), ServerStatus::Running), slot(listener("side", 8001), ServerStatus::Running)];
+
+        let mut main = listener("main", 8000);
+        main.tls.cert_path = "new.pem".to_string();
+        let new = config(vec![main, listener("side", 9001)]);
+        assert_eq!(changes(&slots, &running, &new), [
+            ("main".to_string(), ListenerChange::Restart),
+            ("side".to_string(), ListenerChange::Handover),
+        ]);
+
+        // Settings every instance is built with restart all of them
+        let new = ServerConfig { keep_alive_secs: running.keep_alive_secs + 1, ..running.clone() };
+        assert_eq!(changes(&slots, &running, &new), [
+            ("main".to_string(), ListenerChange::Restart),
+            ("side".to_string(), ListenerChange::Restart),
+        ]);
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
+use crate::admin_api;
+use crate::config;
+use crate::connections;
+use crate::control::ServerController;
+use crate::server::ServerConfig;
+
+// File the running configuration came from, re-read by `solaractl reload`
+static CONFIG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+// Local control through solaractl. Only the user running the server can use the socket.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSocketConfig {
+    pub enabled: bool,
+    // Unix-domain socket, relative to the working directory
+    pub path: String,
+}
+
+impl Default for ControlSocketConfig {
+    fn default() -> Self {
+        Self { enabled: false, path: "solara.sock".to_string() }
+    }
+}
+
+// An open control socket, closed and removed when dropped
+pub struct ControlSocket {
+    path: PathBuf,
+    _stop: oneshot::Sender<()>,
+}
+
+impl ControlSocket {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Status,
+    Start,
+    Stop,
+    Reload,
+    Restart { listener: String },
+    Logs {
+        #[serde(default)]
+        after: u64,
+        limit: Option<usize>,
+        level: Option<String>,
+        #[serde(default)]
+        filter: String,
+    },
+    Connections,
+    Close { id: u64 },
+    // A configuration file's contents, or the server's own file when missing
+    TestConfig { config: Option<String> },
+}
+
+pub fn set_config_file(path: Option<PathBuf>) {
+    *CONFIG_FILE.write().unwrap() = path;
+}
+
+// Whether `path` can be used for a new socket: missing, or a socket left behind
+pub fn check_path(path: &Path) -> Result<(), String> {
+    // sun_path holds 108 bytes including the terminating zero, the socket is bound in
+    // the staging directory first
+    if staged_socket(path).as_os_str().len().max(path.as_os_str().len()) > 107 {
+        return Err("the path is too long for a unix socket".to_string());
+    }
+    if path.parent().filter(|dir| !dir.as_os_str().is_empty()).is_some_and(|dir| !dir.is_dir()) {
+        return Err("its directory doesn't exist".to_string());
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => Err("a file that is not a socket is in the way".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn staging_dir(path: &Path) -> PathBuf {
+    path.with_file_name(format!(".solara-{}", std::process::id()))
+}
+
+fn staged_socket(path: &Path) -> PathBuf {
+    staging_dir(path).join("sock")
+}
+
+// Listen on `path` in the current runtime. A socket file left behind by a process that is
+// gone is replaced, one another process still listens on is not.
+pub fn open(path: &Path, controller: ServerController) -> io::Result<ControlSocket> {
+    check_path(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another process is listening on it"));
+        }
+        fs::remove_file(path)?;
+    }
+    // Bound in a directory only this user can enter, so nobody can connect before the
+    // mode is set, then moved into place
+    let staging = staging_dir(path);
+    if fs::symlink_metadata(&staging).is_ok_and(|metadata| metadata.is_dir()) {
+        // Left behind by a crashed process with the same ID
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staged_socket(path);
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    let listener = bound?;
+
+    let (stop, mut stopped) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve(stream, controller.clone()));
+                    }
+                    Err(e) => eprintln!("Control socket failed to accept a connection: {}", e),
+                },
+            }
+        }
+    });
+    Ok(ControlSocket { path: path.to_path_buf(), _stop: stop })
+}
+
+// One JSON request per line, each answered with one JSON line
+async fn serve(stream: UnixStream, controller: ServerController) {
+    let uid = stream.peer_cred().map(|cred| cred.uid()).ok();
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let answer = match serde_json::from_str::<Command>(&line) {
+            // Reloading and checking configurations read files, off the async workers
+            Ok(command) => {
+                let controller = controller.clone();
+                tokio::task::spawn_blocking(move || handle(&controller, command, uid))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|answer| answer)
+                    .unwrap_or_else(|e| json!({ "error": e }))
+            }
+            Err(e) => json!({ "error": format!("Invalid request: {}", e) }),
+        };
+        if writer.write_all(format!("{}\n", answer).as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn handle(controller: &ServerController, command: Command, uid: Option<u32>) -> Result<Value, String> {
+    let log = |action: &str| match uid {
+        Some(uid) => controller.log(format!("Control socket: {} (uid {})", action, uid)),
+        None => controller.log(format!("Control socket: {}", action)),
+    };
+    match command {
+        Command::Status => Ok(admin_api::status_json(controller)),
+        // Like the admin API, only the site listeners so admin listeners stay reachable
+        Command::Start => {
+            controller.start_site()?;
+            log("started site listeners");
+            Ok(json!({ "message": "Starting site listeners" }))
+        }
+        Command::Stop => {
+            controller.stop_site()?;
+            log("stopped site listeners");
+            Ok(json!({ "message": "Stopping site listeners" }))
+        }
+        Command::Reload => {
+            let config_file = CONFIG_FILE.read().unwrap().clone();
+            let kind = match &config_file {
+                Some(path) => {
+                    let config = config::load_config(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                    controller.reload_remote(config)?
+                }
+                None => controller.reload(controller.running_config().ok_or("Server is not running")?)?,
+            };
+            match &config_file {
+                Some(path) => log(&format!("reloaded {}", path.display())),
+                None => log("reloaded the configuration"),
+            }
+            Ok(json!({ "message": format!("Configuration {}", kind) }))
+        }
+        Command::Restart { listener } => {
+            controller.restart_listener(&listener)?;
+            log(&format!("restarted listener '{}'", listener));
+            Ok(json!({ "message": format!("Restarting listener '{}'", listener) }))
+        }
+        Command::Logs { after, limit, level, filter } => {
+            let level = admin_api::parse_level(level.as_deref())?;
+            Ok(admin_api::log_entries(&filter, level, after, limit.unwrap_or(admin_api::DEFAULT_LOG_LIMIT)))
+        }
+        Command::Connections => Ok(json!({ "connections": connections::open_connections() })),
+        Command::Close { id } => {
+            if !connections::close_connection(id) {
+                return Err(format!("Connection {} is not open", id));
+            }
+            log(&format!("closed connection {}", id));
+            Ok(json!({ "message": format!("Closing connection {}", id) }))
+        }
+        Command::TestConfig { config } => {
+            let contents = match config {
+                Some(contents) => contents,
+                None => {
+                    let path = CONFIG_FILE.read().unwrap().clone().ok_or("The server was not started from a configuration file")?;
+                    fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?
+                }
+            };
+            let config: ServerConfig = toml::from_str(&contents).map_err(|e| format!("Invalid configuration: {}", e))?;
+            Ok(admin_api::report_json(&config::validate_config(&config)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Handle;
+
+    async fn ask(stream: &mut BufReader<UnixStream>, request: Value) -> Value {
+        stream.get_mut().write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn socket_is_private_and_answers_commands() {
+        let dir = std::env::temp_dir().join(format!("solara-control-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("solara.sock");
+        let socket = open(&path, ServerController::new(Handle::current())).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Another server can't take over a socket in use
+        assert_eq!(open(&path, ServerController::new(Handle::current())).err().map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+
+        let mut stream = BufReader::new(UnixStream::connect(&path).await.unwrap());
+        let answer = ask(&mut stream, json!({ "command": "test_config", "config": "[[listeners]]\nname = 3" })).await;
+        assert!(answer["error"].as_str().unwrap().starts_with("Invalid configuration"));
+        let answer = ask(&mut stream, json!({ "command": "close", "id": u64::MAX })).await;
+        assert_eq!(answer["error"], format!("Connection {} is not open", u64::MAX));
+        let answer = ask(&mut stream, json!({ "command": "launch" })).await;
+        assert!(answer["error"].as_str().unwrap().starts_with("Invalid request"));
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        drop(socket);
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
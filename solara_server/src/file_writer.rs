@@ -0,0 +1,150 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+// Writes a store file on a thread of its own, so requests never wait for the disk.
+// A snapshot still queued behind a newer one is skipped.
+pub struct FileWriter {
+    sender: Sender<Snapshot>,
+    thread: JoinHandle<()>,
+}
+
+struct Snapshot {
+    contents: Vec<u8>,
+    // Told the outcome, for changes the caller reports back
+    done: Option<Sender<io::Result<()>>>,
+}
+
+type WriteFn = fn(&Path, &[u8]) -> io::Result<()>;
+
+impl FileWriter {
+    // `what` names the contents in error messages, e.g. "sessions"
+    pub fn start(what: &'static str, path: &Path, write: WriteFn) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_path_buf();
+        let thread = thread::Builder::new()
+            .name(format!("{}-writer", what))
+            .spawn(move || write_loop(what, &path, write, receiver))?;
+        Ok(Self { sender, thread })
+    }
+
+    pub fn write(&self, contents: Vec<u8>) {
+        let _ = self.sender.send(Snapshot { contents, done: None });
+    }
+
+    // Write after anything queued before, waiting for the outcome
+    pub fn write_and_wait(&self, contents: Vec<u8>) -> io::Result<()> {
+        let (done, outcome) = mpsc::channel();
+        self.sender
+            .send(Snapshot { contents, done: Some(done) })
+            .map_err(|_| io::Error::other("the writer has stopped"))?;
+        outcome.recv().unwrap_or_else(|_| Err(io::Error::other("the writer has stopped")))
+    }
+
+    // Wait until the last snapshot is on disk
+    pub fn finish(self) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
+
+// Replace a file in one step, readable by the owner only. A crash leaves either the
+// old or the new contents, never a truncated file.
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension("tmp");
+    // A temp file left behind by a crash may have other permissions, start afresh
+    match fs::remove_file(&temp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+fn write_loop(what: &str, path: &Path, write: WriteFn, receiver: Receiver<Snapshot>) {
+    while let Ok(snapshot) = receiver.recv() {
+        let mut contents = snapshot.contents;
+        let mut waiting: Vec<Sender<io::Result<()>>> = snapshot.done.into_iter().collect();
+        while let Ok(newer) = receiver.try_recv() {
+            contents = newer.contents;
+            waiting.extend(newer.done);
+        }
+        match write(path, &contents) {
+            Ok(()) => waiting.iter().for_each(|done| {
+                let _ = done.send(Ok(()));
+            }),
+            Err(e) if waiting.is_empty() => eprintln!("Failed to save {} to {}: {}", what, path.display(), e),
+            Err(e) => waiting.iter().for_each(|done| {
+                let _ = done.send(Err(io::Error::new(e.kind(), e.to_string())));
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    #[test]
+    fn only_the_newest_queued_snapshot_is_written() {
+        let path = std::env::temp_dir().join(format!("solara-writer-{}.txt", std::process::id()));
+        let (sender, receiver) = mpsc::channel();
+        for contents in ["first", "second", "third"] {
+            sender.send(Snapshot { contents: contents.as_bytes().to_vec(), done: None }).unwrap();
+        }
+        drop(sender);
+        write_loop("test", &path, write, receiver);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn waiting_writes_report_the_outcome() {
+        let dir = std::env::temp_dir().join(format!("solara-writer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let writer = FileWriter::start("test", &dir.join("store.txt"), write).unwrap();
+        writer.write(b"usage".to_vec());
+        writer.write_and_wait(b"change".to_vec()).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("store.txt")).unwrap(), "change");
+        writer.finish();
+
+        let writer = FileWriter::start("test", &dir.join("missing").join("store.txt"), write).unwrap();
+        assert!(writer.write_and_wait(b"change".to_vec()).is_err());
+        writer.finish();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_files_replace_a_stale_temp_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("solara-private-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let temp = dir.join("store.tmp");
+        fs::write(&temp, "stale").unwrap();
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&dir.join("store.json"), b"contents").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("store.json")).unwrap(), "contents");
+        let mode = fs::metadata(dir.join("store.json")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!temp.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
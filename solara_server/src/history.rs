@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+use crate::metrics::{self, LatencySnapshot, RequestTotals};
+use crate::server::ServerStats;
+
+// One minute at one second resolution, one hour at ten second resolution
+const FINE_CAPACITY: usize = 60;
+const COARSE_CAPACITY: usize = 360;
+const SAMPLES_PER_COARSE: usize = 10;
+
+// Longest window the fine samples cover, wider windows use the coarse ones
+pub const FINE_WINDOW_SECS: f64 = FINE_CAPACITY as f64;
+pub const MAX_WINDOW_SECS: f64 = (COARSE_CAPACITY * SAMPLES_PER_COARSE) as f64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sample {
+    // Seconds since the history was created
+    pub time: f64,
+    pub requests_per_sec: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+    // Percentage of requests answered with a 5xx status
+    pub error_rate: f64,
+    pub connections: f64,
+    pub cpu_percent: f64,
+    pub memory_mb: f64,
+}
+
+impl Sample {
+    // Average of several samples, stamped with the time of the last one
+    fn average(samples: &[Sample]) -> Sample {
+        let n = samples.len().max(1) as f64;
+        let sum = |value: fn(&Sample) -> f64| samples.iter().map(value).sum::<f64>() / n;
+        Sample {
+            time: samples.last().map_or(0.0, |sample| sample.time),
+            requests_per_sec: sum(|s| s.requests_per_sec),
+            latency_p50_ms: sum(|s| s.latency_p50_ms),
+            latency_p95_ms: sum(|s| s.latency_p95_ms),
+            latency_p99_ms: sum(|s| s.latency_p99_ms),
+            error_rate: sum(|s| s.error_rate),
+            connections: sum(|s| s.connections),
+            cpu_percent: sum(|s| s.cpu_percent),
+            memory_mb: sum(|s| s.memory_mb),
+        }
+    }
+}
+
+// Rolling history of traffic and resource metrics for the Monitoring charts
+pub struct MetricsHistory {
+    started: Instant,
+    fine: VecDeque<Sample>,
+    coarse: VecDeque<Sample>,
+    // Fine samples not yet folded into a coarse one
+    pending: Vec<Sample>,
+    previous: Option<(Instant, RequestTotals, LatencySnapshot)>,
+}
+
+impl MetricsHistory {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            fine: VecDeque::with_capacity(FINE_CAPACITY),
+            coarse: VecDeque::with_capacity(COARSE_CAPACITY),
+            pending: Vec::with_capacity(SAMPLES_PER_COARSE),
+            previous: None,
+        }
+    }
+
+    // Seconds since the history was created, the time axis of the samples
+    pub fn now(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    // Take a sample. Request rates and latencies are derived from the change since the last call.
+    pub fn record(&mut self, stats: &ServerStats) {
+        let now = Instant::now();
+        let totals = metrics::request_totals();
+        let latency = metrics::latency_snapshot();
+
+        let mut sample = Sample {
+            time: now.duration_since(self.started).as_secs_f64(),
+            connections: stats.active_connections as f64,
+            cpu_percent: stats.process.as_ref().map_or(0.0, |process| process.cpu_percent),
+            memory_mb: stats.process.as_ref().map_or(0.0, |process| process.resident_memory_bytes as f64 / 1_048_576.0),
+            ..Sample::default()
+        };
+        if let Some((at, previous_totals, previous_latency)) = &self.previous {
+            let elapsed = now.duration_since(*at).as_secs_f64().max(0.001);
+            let requests = totals.total.saturating_sub(previous_totals.total);
+            let errors = totals.server_errors.saturating_sub(previous_totals.server_errors);
+            sample.requests_per_sec = requests as f64 / elapsed;
+            if requests > 0 {
+                sample.error_rate = errors as f64 / requests as f64 * 100.0;
+            }
+            let window = latency.since(previous_latency);
+            let quantile_ms = |q| window.quantile(q).map_or(0.0, |seconds| seconds * 1000.0);
+            sample.latency_p50_ms = quantile_ms(0.5);
+            sample.latency_p95_ms = quantile_ms(0.95);
+            sample.latency_p99_ms = quantile_ms(0.99);
+        }
+        self.previous = Some((now, totals, latency));
+
+        push_bounded(&mut self.fine, sample, FINE_CAPACITY);
+        self.pending.push(sample);
+        if self.pending.len() == SAMPLES_PER_COARSE {
+            push_bounded(&mut self.coarse, Sample::average(&self.pending), COARSE_CAPACITY);
+            self.pending.clear();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&Sample> {
+        self.fine.back()
+    }
+
+    // Samples within the last `window_secs`, at the finest resolution that covers it
+    pub fn samples(&self, window_secs: f64) -> Vec<Sample> {
+        let from = self.now() - window_secs;
+        let source = if window_secs <= FINE_WINDOW_SECS { &self.fine } else { &self.coarse };
+        source.iter().filter(|sample| sample.time >= from).copied().collect()
+    }
+}
+
+fn push_bounded(samples: &mut VecDeque<Sample>, sample: Sample, capacity: usize) {
+    if samples.len() == capacity {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
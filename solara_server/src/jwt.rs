@@ -0,0 +1,561 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::{PublicKey, RSAPublicKey};
+use x509_parser::x509::SubjectPublicKeyInfo;
+use crate::auth::{self, Identity};
+
+// Keys and settings of the running server, None while it is stopped
+static VERIFIER: RwLock<Option<Arc<Verifier>>> = RwLock::new(None);
+
+// RFC 7518 asks for HS256 secrets of at least 256 bits
+const MIN_SECRET_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JwtAlgorithm {
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[default]
+    #[serde(rename = "RS256")]
+    Rs256,
+    #[serde(rename = "ES256")]
+    Es256,
+}
+
+impl fmt::Display for JwtAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtAlgorithm::Hs256 => write!(f, "HS256"),
+            JwtAlgorithm::Rs256 => write!(f, "RS256"),
+            JwtAlgorithm::Es256 => write!(f, "ES256"),
+        }
+    }
+}
+
+impl JwtAlgorithm {
+    pub const ALL: [JwtAlgorithm; 3] = [JwtAlgorithm::Hs256, JwtAlgorithm::Rs256, JwtAlgorithm::Es256];
+
+    fn from_name(name: &str) -> Option<JwtAlgorithm> {
+        JwtAlgorithm::ALL.into_iter().find(|algorithm| algorithm.to_string() == name)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JwtKeyFile {
+    pub algorithm: JwtAlgorithm,
+    // PEM public key or certificate for RS256 and ES256, the shared secret itself for HS256
+    pub path: String,
+    // Only used for tokens naming this key ID, empty for any token
+    pub kid: String,
+}
+
+// A claim value and the role it grants
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoleMapping {
+    pub claim_value: String,
+    pub role: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    pub enabled: bool,
+    // Path prefixes that only accept requests with a valid bearer token
+    pub path_prefixes: Vec<String>,
+    pub keys: Vec<JwtKeyFile>,
+    // Local JWKS file, e.g. a copy of the identity provider's jwks_uri
+    pub jwks_path: String,
+    // Required "iss" and "aud" values, empty skips the check
+    pub issuer: String,
+    pub audience: String,
+    // Clock difference tolerated for "exp" and "nbf"
+    pub leeway_secs: u64,
+    // Claim with the username
+    pub username_claim: String,
+    // Claim with the roles, a list or a space separated string. Dots reach into
+    // nested objects, e.g. "realm_access.roles".
+    pub roles_claim: String,
+    // Roles granted per claim value. When empty the values are used as roles.
+    pub role_map: Vec<RoleMapping>,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path_prefixes: vec!["/api".to_string()],
+            keys: Vec::new(),
+            jwks_path: String::new(),
+            issuer: String::new(),
+            audience: String::new(),
+            leeway_secs: 60,
+            username_claim: "sub".to_string(),
+            roles_claim: "roles".to_string(),
+            role_map: Vec::new(),
+        }
+    }
+}
+
+// Why a token was turned away, sent back in the WWW-Authenticate header
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenError {
+    Missing,
+    Malformed,
+    UnsupportedAlgorithm(String),
+    UnknownKey,
+    BadSignature,
+    MissingExpiry,
+    Expired,
+    NotYetValid,
+    WrongIssuer,
+    WrongAudience,
+    MissingUsername,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Missing => write!(f, "A bearer token is required"),
+            TokenError::Malformed => write!(f, "The token is malformed"),
+            TokenError::UnsupportedAlgorithm(alg) => write!(f, "Algorithm '{}' is not accepted", alg),
+            TokenError::UnknownKey => write!(f, "No key is configured for the token"),
+            TokenError::BadSignature => write!(f, "The token signature is invalid"),
+            TokenError::MissingExpiry => write!(f, "The token has no expiry"),
+            TokenError::Expired => write!(f, "The token has expired"),
+            TokenError::NotYetValid => write!(f, "The token is not valid yet"),
+            TokenError::WrongIssuer => write!(f, "The token issuer is not accepted"),
+            TokenError::WrongAudience => write!(f, "The token is not meant for this server"),
+            TokenError::MissingUsername => write!(f, "The token does not name a user"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum KeyMaterial {
+    Secret(Vec<u8>),
+    // Big-endian modulus and exponent without leading zeros
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    // Uncompressed P-256 point
+    Ec(Vec<u8>),
+}
+
+// A key tokens can be checked against, from a key file or a JWKS
+#[derive(Clone, Debug)]
+pub struct VerifyKey {
+    algorithm: JwtAlgorithm,
+    kid: Option<String>,
+    material: KeyMaterial,
+}
+
+impl VerifyKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match &self.material {
+            KeyMaterial::Secret(secret) => {
+                let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+                ring::hmac::verify(&key, message, signature).is_ok()
+            }
+            KeyMaterial::Rsa { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+                .is_ok(),
+            KeyMaterial::Ec(point) => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, signature)
+                .is_ok(),
+        }
+    }
+}
+
+struct Verifier {
+    config: JwtConfig,
+    keys: Vec<VerifyKey>,
+}
+
+pub fn apply_jwt(config: &JwtConfig) {
+    let keys = if config.enabled {
+        load_keys(config).unwrap_or_else(|e| {
+            eprintln!("Failed to load JWT keys, bearer tokens will be refused: {}", e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    *VERIFIER.write().unwrap() = Some(Arc::new(Verifier { config: config.clone(), keys }));
+}
+
+pub fn clear_jwt() {
+    *VERIFIER.write().unwrap() = None;
+}
+
+fn verifier() -> Option<Arc<Verifier>> {
+    VERIFIER.read().unwrap().clone().filter(|verifier| verifier.config.enabled)
+}
+
+// Whether a path only accepts bearer tokens
+pub fn covers(path: &str) -> bool {
+    verifier().is_some_and(|verifier| verifier.config.path_prefixes.iter().any(|prefix| auth::path_has_prefix(path, prefix)))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs())
+}
+
+// Check a bearer token and work out who it is for
+pub fn verify(token: &str) -> Result<Identity, TokenError> {
+    let verifier = verifier().ok_or(TokenError::UnknownKey)?;
+    verify_token(&verifier, token, unix_now())
+}
+
+fn verify_token(verifier: &Verifier, token: &str, now: u64) -> Result<Identity, TokenError> {
+    let config = &verifier.config;
+    let payload = verified_claims(&verifier.keys, token)?;
+    check_lifetime(&payload, now, config.leeway_secs)?;
+    if !config.issuer.is_empty() && payload["iss"].as_str() != Some(config.issuer.as_str()) {
+        return Err(TokenError::WrongIssuer);
+    }
+    if !config.audience.is_empty() && !has_audience(&payload, &config.audience) {
+        return Err(TokenError::WrongAudience);
+    }
+
+    let username = claim(&payload, &config.username_claim)
+        .and_then(Value::as_str)
+        .filter(|username| !username.is_empty())
+        .ok_or(TokenError::MissingUsername)?
+        .to_string();
+    let roles = mapped_roles(&payload, &config.roles_claim, &config.role_map);
+    Ok(Identity { username, roles })
+}
+
+// Claims of a token signed by one of the keys, before any of them are checked
+pub fn verified_claims(keys: &[VerifyKey], token: &str) -> Result<Value, TokenError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(TokenError::Malformed);
+    };
+    let signed = &token[..header.len() + 1 + payload.len()];
+    let header = decode_json(header)?;
+    let payload = decode_json(payload)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| TokenError::Malformed)?;
+
+    // The algorithm has to match the key, so "none" or HS256 with a public key get nowhere
+    let alg = header["alg"].as_str().ok_or(TokenError::Malformed)?;
+    let algorithm = JwtAlgorithm::from_name(alg).ok_or_else(|| TokenError::UnsupportedAlgorithm(alg.to_string()))?;
+    let kid = header["kid"].as_str();
+    let mut candidates = keys
+        .iter()
+        .filter(|key| key.algorithm == algorithm && (kid.is_none() || key.kid.is_none() || key.kid.as_deref() == kid))
+        .peekable();
+    if candidates.peek().is_none() {
+        return Err(TokenError::UnknownKey);
+    }
+    if !candidates.any(|key| key.verify(signed.as_bytes(), &signature)) {
+        return Err(TokenError::BadSignature);
+    }
+    Ok(payload)
+}
+
+// "exp" is required, "nbf" checked when present
+pub fn check_lifetime(claims: &Value, now: u64, leeway_secs: u64) -> Result<(), TokenError> {
+    let exp = claims["exp"].as_f64().ok_or(TokenError::MissingExpiry)?;
+    if now as f64 > exp + leeway_secs as f64 {
+        return Err(TokenError::Expired);
+    }
+    if claims["nbf"].as_f64().is_some_and(|nbf| ((now + leeway_secs) as f64) < nbf) {
+        return Err(TokenError::NotYetValid);
+    }
+    Ok(())
+}
+
+// "aud" may be a single value or a list
+pub fn has_audience(claims: &Value, audience: &str) -> bool {
+    match &claims["aud"] {
+        Value::String(aud) => aud == audience,
+        Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+        _ => false,
+    }
+}
+
+// Roles granted by the values of a claim, the values themselves without a role map
+pub fn mapped_roles(claims: &Value, roles_claim: &str, role_map: &[RoleMapping]) -> Vec<String> {
+    let values: Vec<String> = match claim(claims, roles_claim) {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        Some(Value::String(values)) => values.split_whitespace().map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    if role_map.is_empty() {
+        return values;
+    }
+    role_map
+        .iter()
+        .filter(|mapping| values.contains(&mapping.claim_value))
+        .map(|mapping| mapping.role.clone())
+        .collect()
+}
+
+fn decode_json(part: &str) -> Result<Value, TokenError> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).map_err(|_| TokenError::Malformed)?;
+    serde_json::from_slice::<Value>(&bytes).ok().filter(Value::is_object).ok_or(TokenError::Malformed)
+}
+
+pub fn claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(claims, |value, name| value.get(name))
+}
+
+// Check the key files and JWKS, for configuration validation
+pub fn check_keys(config: &JwtConfig) -> Result<usize, String> {
+    load_keys(config).map(|keys| keys.len())
+}
+
+fn load_keys(config: &JwtConfig) -> Result<Vec<VerifyKey>, String> {
+    let mut keys = Vec::new();
+    for key in &config.keys {
+        let material = read_key_file(key).map_err(|e| format!("{}: {}", key.path, e))?;
+        let kid = Some(key.kid.clone()).filter(|kid| !kid.is_empty());
+        keys.push(VerifyKey { algorithm: key.algorithm, kid, material });
+    }
+    if !config.jwks_path.is_empty() {
+        let jwks = fs::read(&config.jwks_path).map_err(|e| format!("{}: {}", config.jwks_path, e))?;
+        keys.extend(parse_jwks(&jwks).map_err(|e| format!("{}: {}", config.jwks_path, e))?);
+    }
+    Ok(keys)
+}
+
+fn read_key_file(key: &JwtKeyFile) -> Result<KeyMaterial, String> {
+    let contents = fs::read(&key.path).map_err(|e| e.to_string())?;
+    if key.algorithm == JwtAlgorithm::Hs256 {
+        let secret = contents.strip_suffix(b"\n").unwrap_or(&contents);
+        let secret = secret.strip_suffix(b"\r").unwrap_or(secret);
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!("HS256 secrets need at least {} bytes", MIN_SECRET_LEN));
+        }
+        return Ok(KeyMaterial::Secret(secret.to_vec()));
+    }
+
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&contents).map_err(|e| format!("not a PEM file: {}", e))?;
+    let material = match pem.label.as_str() {
+        "CERTIFICATE" => {
+            let certificate = pem.parse_x509().map_err(|e| e.to_string())?;
+            public_key_material(certificate.public_key())?
+        }
+        "PUBLIC KEY" => {
+            let (_, spki) = SubjectPublicKeyInfo::from_der(&pem.contents).map_err(|e| e.to_string())?;
+            public_key_material(&spki)?
+        }
+        "RSA PUBLIC KEY" => {
+            let (_, rsa) = RSAPublicKey::from_der(&pem.contents).map_err(|e| e.to_string())?;
+            rsa_material(rsa.modulus, rsa.exponent)
+        }
+        label => return Err(format!("'{}' PEM blocks are not supported, use a public key or certificate", label)),
+    };
+    match (&material, key.algorithm) {
+        (KeyMaterial::Rsa { .. }, JwtAlgorithm::Rs256) | (KeyMaterial::Ec(_), JwtAlgorithm::Es256) => Ok(material),
+        _ => Err(format!("the key does not fit {}", key.algorithm)),
+    }
+}
+
+fn public_key_material(spki: &SubjectPublicKeyInfo) -> Result<KeyMaterial, String> {
+    match spki.parsed().map_err(|e| e.to_string())? {
+        PublicKey::RSA(rsa) => Ok(rsa_material(rsa.modulus, rsa.exponent)),
+        PublicKey::EC(point) if point.data().len() == 65 => Ok(KeyMaterial::Ec(point.data().to_vec())),
+        _ => Err("only RSA and P-256 keys are supported".to_string()),
+    }
+}
+
+fn rsa_material(modulus: &[u8], exponent: &[u8]) -> KeyMaterial {
+    let strip = |bytes: &[u8]| bytes.iter().skip_while(|b| **b == 0).copied().collect::<Vec<u8>>();
+    KeyMaterial::Rsa { n: strip(modulus), e: strip(exponent) }
+}
+
+// RSA, P-256 and symmetric keys of a JWKS document. Keys for other uses are skipped.
+pub fn parse_jwks(contents: &[u8]) -> Result<Vec<VerifyKey>, String> {
+    let jwks: Value = serde_json::from_slice(contents).map_err(|e| e.to_string())?;
+    let entries = jwks["keys"].as_array().ok_or("no \"keys\" list")?;
+    let field = |jwk: &Value, name: &str| -> Result<Vec<u8>, String> {
+        let value = jwk[name].as_str().ok_or_else(|| format!("key is missing \"{}\"", name))?;
+        URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).map_err(|e| format!("\"{}\" is not base64url: {}", name, e))
+    };
+    let mut keys = Vec::new();
+    for jwk in entries {
+        if jwk["use"].as_str().is_some_and(|usage| usage != "sig") {
+            continue;
+        }
+        let (algorithm, material) = match (jwk["kty"].as_str(), jwk["crv"].as_str()) {
+            (Some("RSA"), _) => (JwtAlgorithm::Rs256, rsa_material(&field(jwk, "n")?, &field(jwk, "e")?)),
+            (Some("EC"), Some("P-256")) => {
+                let mut point = vec![4];
+                point.extend(field(jwk, "x")?);
+                point.extend(field(jwk, "y")?);
+                (JwtAlgorithm::Es256, KeyMaterial::Ec(point))
+            }
+            (Some("oct"), _) => (JwtAlgorithm::Hs256, KeyMaterial::Secret(field(jwk, "k")?)),
+            _ => continue,
+        };
+        if jwk["alg"].as_str().is_some_and(|alg| alg != algorithm.to_string()) {
+            continue;
+        }
+        keys.push(VerifyKey { algorithm, kid: jwk["kid"].as_str().map(str::to_string), material });
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const NOW: u64 = 1_700_000_000;
+
+    fn encode(value: &Value) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).unwrap())
+    }
+
+    fn hs256_token(header: Value, claims: Value) -> String {
+        let signed = format!("{}.{}", encode(&header), encode(&claims));
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, SECRET);
+        let tag = ring::hmac::sign(&key, signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(tag.as_ref()))
+    }
+
+    fn secret_key(kid: Option<&str>) -> VerifyKey {
+        VerifyKey { algorithm: JwtAlgorithm::Hs256, kid: kid.map(str::to_string), material: KeyMaterial::Secret(SECRET.to_vec()) }
+    }
+
+    fn verifier(keys: Vec<VerifyKey>) -> Verifier {
+        let config = JwtConfig {
+            enabled: true,
+            issuer: "https://issuer.example".to_string(),
+            audience: "solara".to_string(),
+            ..JwtConfig::default()
+        };
+        Verifier { config, keys }
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "alice",
+            "iss": "https://issuer.example",
+            "aud": ["other", "solara"],
+            "exp": NOW + 300,
+            "roles": ["admin", "viewer"],
+        })
+    }
+
+    fn with(claims: Value, name: &str, value: Value) -> Value {
+        let mut claims = claims;
+        claims[name] = value;
+        claims
+    }
+
+    fn check(claims: Value) -> Result<Identity, TokenError> {
+        let token = hs256_token(json!({ "alg": "HS256", "typ": "JWT" }), claims);
+        verify_token(&verifier(vec![secret_key(None)]), &token, NOW)
+    }
+
+    #[test]
+    fn valid_token_names_the_user_and_roles() {
+        let identity = check(claims()).unwrap();
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.roles, vec!["admin", "viewer"]);
+    }
+
+    #[test]
+    fn expiry_is_required_and_checked_with_leeway() {
+        let mut no_expiry = claims();
+        no_expiry.as_object_mut().unwrap().remove("exp");
+        assert_eq!(check(no_expiry), Err(TokenError::MissingExpiry));
+        assert_eq!(check(with(claims(), "exp", json!(NOW - 61))), Err(TokenError::Expired));
+        assert!(check(with(claims(), "exp", json!(NOW - 59))).is_ok());
+        assert_eq!(check(with(claims(), "nbf", json!(NOW + 61))), Err(TokenError::NotYetValid));
+        assert!(check(with(claims(), "nbf", json!(NOW + 59))).is_ok());
+    }
+
+    #[test]
+    fn issuer_audience_and_username_are_checked() {
+        assert_eq!(check(with(claims(), "iss", json!("https://evil.example"))), Err(TokenError::WrongIssuer));
+        assert_eq!(check(with(claims(), "aud", json!("other"))), Err(TokenError::WrongAudience));
+        assert!(check(with(claims(), "aud", json!("solara"))).is_ok());
+        assert_eq!(check(with(claims(), "sub", json!(""))), Err(TokenError::MissingUsername));
+        assert_eq!(check(with(claims(), "sub", json!(42))), Err(TokenError::MissingUsername));
+    }
+
+    #[test]
+    fn algorithm_must_match_a_configured_key() {
+        let keys = vec![secret_key(None)];
+        let unsigned = format!("{}.{}.", encode(&json!({ "alg": "none" })), encode(&claims()));
+        assert_eq!(verified_claims(&keys, &unsigned), Err(TokenError::UnsupportedAlgorithm("none".to_string())));
+
+        // An HS256 token is never checked against a public key used as the secret
+        let public = VerifyKey { algorithm: JwtAlgorithm::Rs256, kid: None, material: KeyMaterial::Secret(SECRET.to_vec()) };
+        let token = hs256_token(json!({ "alg": "HS256" }), claims());
+        assert_eq!(verified_claims(&[public], &token), Err(TokenError::UnknownKey));
+
+        let token = hs256_token(json!({ "alg": "RS256" }), claims());
+        assert_eq!(verified_claims(&keys, &token), Err(TokenError::UnknownKey));
+    }
+
+    #[test]
+    fn key_ids_select_the_key() {
+        let token = hs256_token(json!({ "alg": "HS256", "kid": "one" }), claims());
+        assert!(verified_claims(&[secret_key(Some("one"))], &token).is_ok());
+        assert!(verified_claims(&[secret_key(None)], &token).is_ok());
+        assert_eq!(verified_claims(&[secret_key(Some("two"))], &token), Err(TokenError::UnknownKey));
+    }
+
+    #[test]
+    fn tampered_and_malformed_tokens_are_refused() {
+        let keys = vec![secret_key(None)];
+        let token = hs256_token(json!({ "alg": "HS256" }), claims());
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let forged = encode(&with(claims(), "sub", json!("mallory")));
+        parts[1] = &forged;
+        assert_eq!(verified_claims(&keys, &parts.join(".")), Err(TokenError::BadSignature));
+
+        assert_eq!(verified_claims(&keys, "abc"), Err(TokenError::Malformed));
+        assert_eq!(verified_claims(&keys, &format!("{}.x", token)), Err(TokenError::Malformed));
+        assert_eq!(verified_claims(&keys, "e30.W10.AA"), Err(TokenError::Malformed));
+    }
+
+    #[test]
+    fn es256_tokens_verify_against_a_jwks_key() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = pair.public_key().as_ref();
+        let jwks = json!({ "keys": [
+            { "kty": "EC", "crv": "P-256", "use": "enc", "kid": "enc", "x": URL_SAFE_NO_PAD.encode(&point[1..33]), "y": URL_SAFE_NO_PAD.encode(&point[33..]) },
+            { "kty": "EC", "crv": "P-256", "alg": "ES384", "kid": "other", "x": URL_SAFE_NO_PAD.encode(&point[1..33]), "y": URL_SAFE_NO_PAD.encode(&point[33..]) },
+            { "kty": "EC", "crv": "P-256", "kid": "ec", "x": URL_SAFE_NO_PAD.encode(&point[1..33]), "y": URL_SAFE_NO_PAD.encode(&point[33..]) },
+        ] });
+        let keys = parse_jwks(&serde_json::to_vec(&jwks).unwrap()).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].kid.as_deref(), Some("ec"));
+
+        let signed = format!("{}.{}", encode(&json!({ "alg": "ES256", "kid": "ec" })), encode(&claims()));
+        let signature = pair.sign(&rng, signed.as_bytes()).unwrap();
+        let token = format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature.as_ref()));
+        assert_eq!(verify_token(&verifier(keys), &token, NOW).unwrap().username, "alice");
+    }
+
+    #[test]
+    fn roles_come_from_nested_claims_and_the_role_map() {
+        let claims = json!({ "realm_access": { "roles": ["ops", "dev"] }, "scope": "read write" });
+        assert_eq!(mapped_roles(&claims, "realm_access.roles", &[]), vec!["ops", "dev"]);
+        assert_eq!(mapped_roles(&claims, "scope", &[]), vec!["read", "write"]);
+        assert!(mapped_roles(&claims, "missing.roles", &[]).is_empty());
+        let role_map = vec![
+            RoleMapping { claim_value: "ops".to_string(), role: "admin".to_string() },
+            RoleMapping { claim_value: "qa".to_string(), role: "tester".to_string() },
+        ];
+        assert_eq!(mapped_roles(&claims, "realm_access.roles", &role_map), vec!["admin"]);
+    }
+}
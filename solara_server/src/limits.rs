@@ -0,0 +1,245 @@
+use rocket::data::Data;
+use rocket::http::{Header, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::route::{Handler, Outcome, Route};
+use rocket::Request;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::api_keys;
+use crate::connections;
+use crate::server::ServerConfig;
+
+// Requests currently being handled, across all listeners
+static IN_FLIGHT_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+// Requests turned away with 503 because every slot stayed busy
+static REJECTED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+// Requests whose handler ran longer than the request timeout
+static TIMED_OUT_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+// Limits enforced on every request, swapped on reload
+static REQUEST_LIMITS: RwLock<Option<Arc<RequestLimits>>> = RwLock::new(None);
+
+struct RequestLimits {
+    max_in_flight: usize,
+    slots: Arc<Slots>,
+    queue_timeout: Duration,
+    request_timeout: Option<Duration>,
+}
+
+// The slot pool, resized in place so requests holding a slot across a reload still count
+struct Slots {
+    semaphore: Arc<Semaphore>,
+    // Slots to take out of the pool as they are released, after shrinking below those in use
+    pending_shrink: AtomicUsize,
+}
+
+impl Slots {
+    fn new(size: usize) -> Self {
+        Self { semaphore: Arc::new(Semaphore::new(size)), pending_shrink: AtomicUsize::new(0) }
+    }
+
+    fn resize(&self, from: usize, to: usize) {
+        if to > from {
+            // Growing first cancels a shrink that is still waiting for slots
+            let grow = to - from;
+            let cancelled = self
+                .pending_shrink
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| Some(pending.saturating_sub(grow)))
+                .unwrap();
+            self.semaphore.add_permits(grow - cancelled.min(grow));
+        } else if to < from {
+            let shrink = from - to;
+            let forgotten = self.semaphore.forget_permits(shrink);
+            self.pending_shrink.fetch_add(shrink - forgotten, Ordering::SeqCst);
+        }
+    }
+
+    // Return a slot to the pool unless the pool still has to shrink
+    fn release(&self, permit: OwnedSemaphorePermit) {
+        let shrinking = self
+            .pending_shrink
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| pending.checked_sub(1))
+            .is_ok();
+        if shrinking {
+            permit.forget();
+        }
+    }
+}
+
+// Install the limits from the configuration. The slot pool is kept and resized,
+// so requests already holding a slot are still accounted for.
+pub fn apply_limits(config: &ServerConfig) {
+    let mut limits = REQUEST_LIMITS.write().unwrap();
+    let slots = match limits.as_ref() {
+        Some(current) => {
+            current.slots.resize(current.max_in_flight, config.max_connections);
+            current.slots.clone()
+        }
+        None => Arc::new(Slots::new(config.max_connections)),
+    };
+    *limits = Some(Arc::new(RequestLimits {
+        max_in_flight: config.max_connections,
+        slots,
+        queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+        request_timeout: (config.request_timeout_secs > 0)
+            .then(|| Duration::from_secs(config.request_timeout_secs)),
+    }));
+}
+
+pub fn clear_limits() {
+    *REQUEST_LIMITS.write().unwrap() = None;
+}
+
+pub fn in_flight_requests() -> usize {
+    IN_FLIGHT_REQUESTS.load(Ordering::SeqCst)
+}
+
+pub fn rejected_requests() -> u64 {
+    REJECTED_REQUESTS.load(Ordering::SeqCst)
+}
+
+pub fn timed_out_requests() -> u64 {
+    TIMED_OUT_REQUESTS.load(Ordering::SeqCst)
+}
+
+// Put every route behind the concurrency limit and request timeout, and cancel
+// requests whose connection is closed from the Connection Monitor
+pub fn limit_routes(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(LimitedHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct LimitedHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for LimitedHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        tokio::select! {
+            biased;
+            _ = connections::closed(req) => Outcome::Error(Status::ServiceUnavailable),
+            outcome = self.limited(req, data) => outcome,
+        }
+    }
+}
+
+impl LimitedHandler {
+    async fn limited<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let Some(limits) = REQUEST_LIMITS.read().unwrap().clone() else {
+            return self.0.handle(req, data).await;
+        };
+
+        // Wait briefly for a free slot, then give up with 503 instead of piling up
+        let semaphore = limits.slots.semaphore.clone();
+        let permit = match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => match tokio::time::timeout(limits.queue_timeout, semaphore.acquire_owned()).await {
+                Ok(Ok(permit)) => permit,
+                _ => {
+                    REJECTED_REQUESTS.fetch_add(1, Ordering::SeqCst);
+                    return Outcome::from(req, Overloaded);
+                }
+            },
+        };
+
+        let _in_flight = InFlight::enter(limits.slots.clone(), permit);
+        match limits.request_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, self.0.handle(req, data)).await {
+                Ok(outcome) => outcome,
+                Err(_) => {
+                    TIMED_OUT_REQUESTS.fetch_add(1, Ordering::SeqCst);
+                    eprintln!("Request {} {} timed out after {:?}", req.method(), api_keys::redacted_uri(req.uri()), timeout);
+                    Outcome::Error(Status::ServiceUnavailable)
+                }
+            },
+            None => self.0.handle(req, data).await,
+        }
+    }
+}
+
+// Holds a slot for the lifetime of a request, also when the client goes away mid-request
+struct InFlight {
+    slots: Arc<Slots>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl InFlight {
+    fn enter(slots: Arc<Slots>, permit: OwnedSemaphorePermit) -> Self {
+        IN_FLIGHT_REQUESTS.fetch_add(1, Ordering::SeqCst);
+        Self { slots, permit: Some(permit) }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT_REQUESTS.fetch_sub(1, Ordering::SeqCst);
+        if let Some(permit) = self.permit.take() {
+            self.slots.release(permit);
+        }
+    }
+}
+
+// 503 telling the client to come back shortly
+struct Overloaded;
+
+impl<'r> Responder<'r, 'static> for Overloaded {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from("Server is at its connection limit, try again shortly".respond_to(req)?)
+            .status(Status::ServiceUnavailable)
+            .header(Header::new("Retry-After", "1"))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acquire(slots: &Slots, count: usize) -> Vec<OwnedSemaphorePermit> {
+        (0..count).map(|_| slots.semaphore.clone().try_acquire_owned().unwrap()).collect()
+    }
+
+    #[test]
+    fn growing_adds_slots() {
+        let slots = Slots::new(2);
+        let held = acquire(&slots, 2);
+        slots.resize(2, 5);
+        assert_eq!(slots.semaphore.available_permits(), 3);
+        held.into_iter().for_each(|permit| slots.release(permit));
+        assert_eq!(slots.semaphore.available_permits(), 5);
+    }
+
+    #[test]
+    fn shrinking_below_the_slots_in_use_waits_for_them() {
+        let slots = Slots::new(4);
+        let mut held = acquire(&slots, 3);
+        slots.resize(4, 1);
+        // The free slot goes right away, two of the three in use once released
+        assert_eq!(slots.semaphore.available_permits(), 0);
+        slots.release(held.pop().unwrap());
+        slots.release(held.pop().unwrap());
+        assert_eq!(slots.semaphore.available_permits(), 0);
+        slots.release(held.pop().unwrap());
+        assert_eq!(slots.semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn growing_cancels_a_pending_shrink() {
+        let slots = Slots::new(3);
+        let held = acquire(&slots, 3);
+        slots.resize(3, 1);
+        slots.resize(1, 4);
+        assert_eq!(slots.semaphore.available_permits(), 1);
+        held.into_iter().for_each(|permit| slots.release(permit));
+        assert_eq!(slots.semaphore.available_permits(), 4);
+    }
+}
@@ -0,0 +1,609 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use chrono::{Local, SecondsFormat};
+use crate::notifications::Severity;
+use crate::trace::RequestRecord;
+
+// Messages waiting to be sent, further messages are dropped while the receiver is unreachable
+const QUEUE_CAPACITY: usize = 10_000;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Socket of the native journald protocol
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+// Queue of the sender thread and the settings it was started with
+static FORWARDER: Mutex<Option<(LogForwardingConfig, SyncSender<LogMessage>)>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardTarget {
+    Off,
+    Syslog,
+    Journald,
+}
+
+impl fmt::Display for ForwardTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForwardTarget::Off => write!(f, "Off"),
+            ForwardTarget::Syslog => write!(f, "Syslog"),
+            ForwardTarget::Journald => write!(f, "journald"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    Udp,
+    Tcp,
+    Unix,
+}
+
+impl fmt::Display for SyslogTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyslogTransport::Udp => write!(f, "UDP"),
+            SyslogTransport::Tcp => write!(f, "TCP"),
+            SyslogTransport::Unix => write!(f, "Unix socket"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Facility {
+    User,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl fmt::Display for Facility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Facility::User => write!(f, "user"),
+            Facility::Daemon => write!(f, "daemon"),
+            local => write!(f, "local{}", local.code() - 16),
+        }
+    }
+}
+
+impl Facility {
+    pub const ALL: [Facility; 10] = [
+        Facility::User, Facility::Daemon, Facility::Local0, Facility::Local1, Facility::Local2,
+        Facility::Local3, Facility::Local4, Facility::Local5, Facility::Local6, Facility::Local7,
+    ];
+
+    // Numeric code from RFC 5424
+    fn code(&self) -> u8 {
+        match self {
+            Facility::User => 1,
+            Facility::Daemon => 3,
+            Facility::Local0 => 16,
+            Facility::Local1 => 17,
+            Facility::Local2 => 18,
+            Facility::Local3 => 19,
+            Facility::Local4 => 20,
+            Facility::Local5 => 21,
+            Facility::Local6 => 22,
+            Facility::Local7 => 23,
+        }
+    }
+}
+
+// Where server and access logs are forwarded to, next to the access log file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogForwardingConfig {
+    pub target: ForwardTarget,
+    pub transport: SyslogTransport,
+    // host:port for UDP and TCP, the socket path for unix sockets
+    pub address: String,
+    pub facility: Facility,
+    pub app_name: String,
+    pub access_log: bool,
+    pub server_log: bool,
+}
+
+impl Default for LogForwardingConfig {
+    fn default() -> Self {
+        Self {
+            target: ForwardTarget::Off,
+            transport: SyslogTransport::Udp,
+            address: "127.0.0.1:514".to_string(),
+            facility: Facility::Daemon,
+            app_name: "solara".to_string(),
+            access_log: true,
+            server_log: true,
+        }
+    }
+}
+
+// Check the syslog address for the transport, for configuration validation
+pub fn check_address(transport: SyslogTransport, address: &str) -> Result<(), String> {
+    match transport {
+        SyslogTransport::Unix if address.is_empty() => Err("socket path is empty".to_string()),
+        SyslogTransport::Unix => Ok(()),
+        _ => match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+            _ => Err("must be host:port".to_string()),
+        },
+    }
+}
+
+// Server or access log line with the fields journald gets as structured data
+struct LogMessage {
+    severity: Severity,
+    // MSGID in syslog, SOLARA_LOG in the journal
+    kind: &'static str,
+    text: String,
+    fields: Vec<(&'static str, String)>,
+}
+
+// Start, restart or stop forwarding
+pub fn apply_forwarding(config: &LogForwardingConfig) {
+    let mut forwarder = FORWARDER.lock().unwrap();
+    if forwarder.as_ref().is_some_and(|(current, _)| current == config) {
+        return;
+    }
+    // Dropping the sender ends the previous thread once its queue is empty
+    *forwarder = None;
+    if config.target == ForwardTarget::Off {
+        return;
+    }
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    let thread_config = config.clone();
+    let spawned = thread::Builder::new()
+        .name("log-forwarder".to_string())
+        .spawn(move || forward_loop(thread_config, receiver));
+    match spawned {
+        Ok(_) => *forwarder = Some((config.clone(), sender)),
+        Err(e) => eprintln!("Failed to start log forwarding: {}", e),
+    }
+}
+
+pub fn clear_forwarding() {
+    *FORWARDER.lock().unwrap() = None;
+}
+
+// Forward a message of the server log
+pub fn server_message(severity: Severity, message: &str) {
+    send(|config| config.server_log, || LogMessage {
+        severity,
+        kind: "server",
+        text: message.to_string(),
+        fields: Vec::new(),
+    });
+}
+
+// Forward an access log line
+pub fn access_message(record: &RequestRecord, line: &str) {
+    send(|config| config.access_log, || {
+        let mut fields = vec![
+            ("REQUEST_ID", record.request_id.clone()),
+            ("TRACE_ID", record.trace_id.clone()),
+            ("LISTENER", record.listener.clone()),
+            ("HTTP_METHOD", record.method.clone()),
+            ("HTTP_URI", record.uri.clone()),
+            ("HTTP_STATUS", record.status.to_string()),
+            ("BYTES_SENT", record.response_bytes.to_string()),
+            ("DURATION_MS", format!("{:.3}", record.timing.total.as_secs_f64() * 1000.0)),
+        ];
+        if let Some(remote) = record.remote {
+            fields.push(("REMOTE_ADDR", remote.ip().to_string()));
+        }
+        LogMessage { severity: Severity::Info, kind: "access", text: line.to_string(), fields }
+    });
+}
+
+fn send(wanted: fn(&LogForwardingConfig) -> bool, message: impl FnOnce() -> LogMessage) {
+    let forwarder = FORWARDER.lock().unwrap();
+    let Some((config, sender)) = forwarder.as_ref().filter(|(config, _)| wanted(config)) else {
+        return;
+    };
+    if let Err(TrySendError::Full(_)) = sender.try_send(message()) {
+        // Reported once per configuration, repeating it would only add to the flood
+        static WARNED: Mutex<Option<String>> = Mutex::new(None);
+        let mut warned = WARNED.lock().unwrap();
+        if warned.as_deref() != Some(config.address.as_str()) {
+            eprintln!("Log forwarding to {} is not keeping up, dropping messages", config.address);
+            *warned = Some(config.address.clone());
+        }
+    }
+}
+
+fn forward_loop(config: LogForwardingConfig, receiver: Receiver<LogMessage>) {
+    let hostname = hostname();
+    let mut connection: Option<Connection> = None;
+    let mut last_error: Option<String> = None;
+    for message in receiver {
+        let payload = match config.target {
+            ForwardTarget::Journald => journald_entry(&config, &message),
+            _ => syslog_message(&config, &hostname, &message).into_bytes(),
+        };
+        match deliver(&config, &mut connection, &payload) {
+            Ok(()) => last_error = None,
+            Err(e) => {
+                let error = e.to_string();
+                if last_error.as_ref() != Some(&error) {
+                    eprintln!("Failed to forward logs to {}: {}", target_name(&config), error);
+                    last_error = Some(error);
+                }
+            }
+        }
+    }
+}
+
+// Send over the open connection, reconnecting once if it broke
+fn deliver(config: &LogForwardingConfig, connection: &mut Option<Connection>, payload: &[u8]) -> io::Result<()> {
+    let mut retry = true;
+    loop {
+        let open = match connection {
+            Some(open) => open,
+            None => connection.insert(Connection::open(config)?),
+        };
+        match open.send(payload) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                *connection = None;
+                if !retry {
+                    return Err(e);
+                }
+                retry = false;
+            }
+        }
+    }
+}
+
+fn target_name(config: &LogForwardingConfig) -> String {
+    match config.target {
+        ForwardTarget::Journald => JOURNALD_SOCKET.to_string(),
+        _ => format!("{} {}", config.transport, config.address),
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+impl Connection {
+    fn open(config: &LogForwardingConfig) -> io::Result<Connection> {
+        let unix_path = match (config.target, config.transport) {
+            (ForwardTarget::Journald, _) => JOURNALD_SOCKET,
+            (_, SyslogTransport::Unix) => config.address.as_str(),
+            (_, SyslogTransport::Udp) => {
+                let socket = UdpSocket::bind(if is_ipv6(&config.address) { "[::]:0" } else { "0.0.0.0:0" })?;
+                socket.connect(&config.address)?;
+                return Ok(Connection::Udp(socket));
+            }
+            (_, SyslogTransport::Tcp) => {
+                let addr = std::net::ToSocketAddrs::to_socket_addrs(&config.address)?
+                    .next()
+                    .ok_or_else(|| io::Error::other("address did not resolve"))?;
+                let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+                stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+                return Ok(Connection::Tcp(stream));
+            }
+        };
+        open_unix(unix_path)
+    }
+
+    fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.send(payload).map(|_| ()),
+            // Octet counting framing from RFC 6587, messages may contain line breaks
+            Connection::Tcp(stream) => {
+                stream.write_all(format!("{} ", payload.len()).as_bytes())?;
+                stream.write_all(payload)
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(payload).map(|_| ()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_unix(path: &str) -> io::Result<Connection> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(Connection::Unix(socket))
+}
+
+#[cfg(not(unix))]
+fn open_unix(_path: &str) -> io::Result<Connection> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "unix sockets are not available on this platform"))
+}
+
+fn is_ipv6(address: &str) -> bool {
+    address.starts_with('[')
+}
+
+// RFC 5424 severity codes
+fn severity_code(severity: Severity) -> u8 {
+    match severity {
+        Severity::Info => 6,
+        Severity::Warning => 4,
+        Severity::Error => 3,
+    }
+}
+
+// "<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG"
+fn syslog_message(config: &LogForwardingConfig, hostname: &str, message: &LogMessage) -> String {
+    let priority = config.facility.code() * 8 + severity_code(message.severity);
+    format!(
+        "<{}>1 {} {} {} {} {} [origin software=\"{}\" swVersion=\"{}\"] {}",
+        priority,
+        Local::now().to_rfc3339_opts(SecondsFormat::Micros, false),
+        header_field(hostname, 255),
+        header_field(&config.app_name, 48),
+        std::process::id(),
+        message.kind,
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        message.text,
+    )
+}
+
+// Header fields are printable ASCII without spaces, "-" when empty
+fn header_field(value: &str, max: usize) -> String {
+    let value: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max).collect();
+    if value.is_empty() { "-".to_string() } else { value }
+}
+
+// Native journal protocol: KEY=value lines, values with line breaks are length prefixed
+fn journald_entry(config: &LogForwardingConfig, message: &LogMessage) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let mut field = |name: &str, value: &str| {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+    field("MESSAGE", &message.text);
+    field("PRIORITY", &severity_code(message.severity).to_string());
+    field("SYSLOG_FACILITY", &config.facility.code().to_string());
+    field("SYSLOG_IDENTIFIER", &config.app_name);
+    field("SOLARA_LOG", message.kind);
+    for (name, value) in &message.fields {
+        field(name, value);
+    }
+    entry
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn config(transport: SyslogTransport, address: String) -> LogForwardingConfig {
+        LogForwardingConfig {
+            target: ForwardTarget::Syslog,
+            transport,
+            address,
+            facility: Facility::Local3,
+            ..LogForwardingConfig::default()
+        }
+    }
+
+    fn message(severity: Severity, text: &str) -> LogMessage {
+        LogMessage { severity, kind: "server", text: text.to_string(), fields: Vec::new() }
+    }
+
+    // Fields of an RFC 5424 message up to MSGID, and the rest
+    fn split_header(message: &str) -> (Vec<&str>, &str) {
+        let fields: Vec<&str> = message.splitn(7, ' ').collect();
+        (fields[..6].to_vec(), fields[6])
+    }
+
+    #[test]
+    fn syslog_header() {
+        let config = config(SyslogTransport::Udp, "127.0.0.1:514".to_string());
+        let text = syslog_message(&config, "web-1", &message(Severity::Warning, "disk almost full"));
+        let (header, rest) = split_header(&text);
+        // local3 is facility 19, warning severity 4
+        assert_eq!(header[0], "<156>1");
+        assert!(chrono::DateTime::parse_from_rfc3339(header[1]).is_ok(), "timestamp {}", header[1]);
+        assert_eq!(header[2], "web-1");
+        assert_eq!(header[3], "solara");
+        assert_eq!(header[4], std::process::id().to_string());
+        assert_eq!(header[5], "server");
+        assert!(rest.starts_with("[origin software=\"solara_server\" swVersion=\""));
+        assert!(rest.ends_with("] disk almost full"));
+    }
+
+    #[test]
+    fn header_fields_are_truncated_and_printable() {
+        assert_eq!(header_field("", 48), "-");
+        assert_eq!(header_field("my app\u{e9}", 48), "myapp");
+        assert_eq!(header_field(&"a".repeat(60), 48), "a".repeat(48));
+
+        let mut config = config(SyslogTransport::Udp, "127.0.0.1:514".to_string());
+        config.app_name = " ".to_string();
+        let text = syslog_message(&config, &"h".repeat(300), &message(Severity::Error, "x"));
+        let (header, _) = split_header(&text);
+        assert_eq!(header[0], "<155>1");
+        assert_eq!(header[2], "h".repeat(255));
+        assert_eq!(header[3], "-");
+    }
+
+    #[test]
+    fn udp_sends_one_datagram_per_message() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = config(SyslogTransport::Udp, receiver.local_addr().unwrap().to_string());
+
+        let mut connection = None;
+        for text in ["first", "second\nline"] {
+            let payload = syslog_message(&config, "host", &message(Severity::Info, text)).into_bytes();
+            deliver(&config, &mut connection, &payload).unwrap();
+        }
+        let mut buf = [0u8; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        let first = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(first.starts_with("<158>1 ") && first.ends_with("] first"), "{}", first);
+        let len = receiver.recv(&mut buf).unwrap();
+        assert!(std::str::from_utf8(&buf[..len]).unwrap().ends_with("] second\nline"));
+    }
+
+    #[test]
+    fn tcp_uses_octet_counting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = config(SyslogTransport::Tcp, listener.local_addr().unwrap().to_string());
+        let payloads: Vec<Vec<u8>> = ["one", "two\nlines"]
+            .iter()
+            .map(|text| syslog_message(&config, "host", &message(Severity::Info, text)).into_bytes())
+            .collect();
+
+        let mut connection = None;
+        for payload in &payloads {
+            deliver(&config, &mut connection, payload).unwrap();
+        }
+        drop(connection);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+
+        // MSG-LEN SP SYSLOG-MSG, back to back
+        let mut rest = &received[..];
+        for payload in &payloads {
+            let space = rest.iter().position(|b| *b == b' ').unwrap();
+            let len: usize = std::str::from_utf8(&rest[..space]).unwrap().parse().unwrap();
+            assert_eq!(len, payload.len());
+            assert_eq!(&rest[space + 1..space + 1 + len], &payload[..]);
+            rest = &rest[space + 1 + len..];
+        }
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn forwards_server_messages_from_the_queue() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut config = config(SyslogTransport::Udp, receiver.local_addr().unwrap().to_string());
+        config.access_log = false;
+        apply_forwarding(&config);
+        server_message(Severity::Error, "listener failed");
+        let mut buf = [0u8; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        clear_forwarding();
+        let text = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(text.starts_with("<155>1 ") && text.ends_with("] listener failed"), "{}", text);
+    }
+
+    // Fields of a native journal entry, decoding length prefixed values
+    fn parse_journal(mut entry: &[u8]) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        while !entry.is_empty() {
+            let end = entry.iter().position(|b| *b == b'=' || *b == b'\n').unwrap();
+            let name = String::from_utf8(entry[..end].to_vec()).unwrap();
+            let value;
+            if entry[end] == b'=' {
+                let newline = end + entry[end..].iter().position(|b| *b == b'\n').unwrap();
+                value = entry[end + 1..newline].to_vec();
+                entry = &entry[newline + 1..];
+            } else {
+                let len = u64::from_le_bytes(entry[end + 1..end + 9].try_into().unwrap()) as usize;
+                value = entry[end + 9..end + 9 + len].to_vec();
+                assert_eq!(entry[end + 9 + len], b'\n');
+                entry = &entry[end + 10 + len..];
+            }
+            fields.push((name, String::from_utf8(value).unwrap()));
+        }
+        fields
+    }
+
+    #[test]
+    fn journald_entries() {
+        let config = LogForwardingConfig { target: ForwardTarget::Journald, ..LogForwardingConfig::default() };
+        let message = LogMessage {
+            severity: Severity::Warning,
+            kind: "access",
+            text: "multi\nline".to_string(),
+            fields: vec![("HTTP_STATUS", "404".to_string())],
+        };
+        let entry = journald_entry(&config, &message);
+        assert!(entry.starts_with(b"MESSAGE\n\x0a\0\0\0\0\0\0\0multi\nline\n"));
+        let expected = [
+            ("MESSAGE", "multi\nline"),
+            ("PRIORITY", "4"),
+            ("SYSLOG_FACILITY", "3"),
+            ("SYSLOG_IDENTIFIER", "solara"),
+            ("SOLARA_LOG", "access"),
+            ("HTTP_STATUS", "404"),
+        ];
+        let fields = parse_journal(&entry);
+        assert_eq!(fields.len(), expected.len());
+        for ((name, value), (expected_name, expected_value)) in fields.iter().zip(expected) {
+            assert_eq!((name.as_str(), value.as_str()), (expected_name, expected_value));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn journald_entries_over_a_unix_socket() {
+        use std::os::unix::net::UnixDatagram;
+        let path = std::env::temp_dir().join(format!("solara-journal-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let config = LogForwardingConfig { target: ForwardTarget::Journald, ..LogForwardingConfig::default() };
+        let entry = journald_entry(&config, &message(Severity::Info, "started"));
+        let mut connection = open_unix(path.to_str().unwrap()).unwrap();
+        connection.send(&entry).unwrap();
+
+        let mut buf = [0u8; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(&buf[..len], &entry[..]);
+        assert_eq!(parse_journal(&buf[..len])[0], ("MESSAGE".to_string(), "started".to_string()));
+    }
+
+    #[test]
+    fn addresses() {
+        assert!(check_address(SyslogTransport::Udp, "logs.example.com:514").is_ok());
+        assert!(check_address(SyslogTransport::Tcp, "[::1]:6514").is_ok());
+        assert!(check_address(SyslogTransport::Tcp, "logs.example.com").is_err());
+        assert!(check_address(SyslogTransport::Udp, ":514").is_err());
+        assert!(check_address(SyslogTransport::Unix, "").is_err());
+        assert!(check_address(SyslogTransport::Unix, "/dev/log").is_ok());
+    }
+}
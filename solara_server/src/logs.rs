@@ -0,0 +1,141 @@
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{DateTime, Local, SecondsFormat};
+use crate::notifications::Severity;
+
+// Latest server messages with their sequence numbers, queried through the admin API
+static RECENT: Mutex<VecDeque<(u64, LogEntry)>> = Mutex::new(VecDeque::new());
+const MAX_RECENT: usize = 1000;
+
+// An entry of the GUI log viewer
+#[derive(Clone)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self { time: Local::now(), severity, message: message.into() }
+    }
+
+    // Case-insensitive text search, and only entries at or above `level`
+    pub fn matches(&self, filter: &str, level: Option<Severity>) -> bool {
+        level.is_none_or(|level| self.severity >= level)
+            && (filter.is_empty() || self.message.to_lowercase().contains(&filter.to_lowercase()))
+    }
+
+    // Full timestamp with the local offset, as written to exported files
+    pub fn timestamp(&self) -> String {
+        self.time.to_rfc3339_opts(SecondsFormat::Millis, false)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "timestamp": self.timestamp(),
+            "level": self.severity.to_string(),
+            "message": self.message,
+        })
+    }
+}
+
+// Short form shown in the log viewer
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.time.format("%H:%M:%S"), self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Text,
+    Csv,
+    JsonLines,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Text => write!(f, "Plain text"),
+            ExportFormat::Csv => write!(f, "CSV"),
+            ExportFormat::JsonLines => write!(f, "JSON lines"),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Text, ExportFormat::Csv, ExportFormat::JsonLines];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+
+    // Format matching the extension picked in the save dialog, plain text otherwise
+    pub fn from_path(path: &Path) -> ExportFormat {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension || (extension == "json" && *format == ExportFormat::JsonLines))
+            .unwrap_or(ExportFormat::Text)
+    }
+}
+
+// Write the entries to `path`, one per line (plus a header row for CSV)
+pub fn export(path: &Path, entries: &[&LogEntry], format: ExportFormat) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    if format == ExportFormat::Csv {
+        writeln!(out, "timestamp,level,message")?;
+    }
+    for entry in entries {
+        match format {
+            ExportFormat::Text => writeln!(out, "{} [{}] {}", entry.timestamp(), entry.severity, entry.message)?,
+            ExportFormat::Csv => writeln!(out, "{},{},{}", entry.timestamp(), entry.severity, csv_field(&entry.message))?,
+            ExportFormat::JsonLines => writeln!(out, "{}", entry.to_json())?,
+        }
+    }
+    out.flush()
+}
+
+// Quote fields that contain separators, quotes or line breaks (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Keep a server message for later queries, dropping the oldest beyond the limit
+pub fn record(severity: Severity, message: &str) {
+    let mut recent = RECENT.lock().unwrap();
+    let seq = recent.back().map_or(1, |(seq, _)| seq + 1);
+    if recent.len() >= MAX_RECENT {
+        recent.pop_front();
+    }
+    recent.push_back((seq, LogEntry::new(severity, message)));
+}
+
+// The newest `limit` messages after sequence number `after` that pass the filter, oldest first
+pub fn recent(filter: &str, level: Option<Severity>, after: u64, limit: usize) -> Vec<(u64, LogEntry)> {
+    let recent = RECENT.lock().unwrap();
+    let mut entries: Vec<(u64, LogEntry)> = recent
+        .iter()
+        .rev()
+        .take_while(|(seq, _)| *seq > after)
+        .filter(|(_, entry)| entry.matches(filter, level))
+        .take(limit)
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries
+}
@@ -1,49 +1,142 @@
+mod access_log;
+mod admin_api;
+mod api_keys;
+mod auth;
+mod certs;
+mod chart;
+mod config;
+mod connections;
+mod control;
+mod control_socket;
+mod file_writer;
+mod history;
+mod jwt;
+mod limits;
+mod log_forward;
+mod logs;
+mod metrics;
+mod notifications;
+mod oidc;
+mod password;
+mod process;
+mod rbac;
+mod remote;
 mod server;
+mod sessions;
+mod trace;
+mod upstream;
+mod users;
 
-use eframe::egui;
+use eframe::egui::{self, Color32};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How often certificates and disk space are checked against the alert thresholds
+const RESOURCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 use tokio::runtime::Handle;
-use tokio::sync::oneshot;
-use server::{ServerConfig, ServerStatus, ServerStats}; // Import new types
+use access_log::AccessLogFormat;
+use config::{ConfigField, ValidationReport};
+use connections::ConnectionColumn;
+use chart::Series;
+use control::{ListenerState, ReloadKind, ServerController};
+use history::MetricsHistory;
+use log_forward::{Facility, ForwardTarget, SyslogTransport};
+use logs::{ExportFormat, LogEntry};
+use notifications::{NotificationCenter, Severity};
+use jwt::{JwtAlgorithm, JwtKeyFile, RoleMapping};
+use rbac::AccessRule;
+use remote::{RemoteClient, RemoteSettings};
+use server::{ListenerConfig, Protocol, ServerConfig, ServerStatus, ServerStats}; // Import new types
+use upstream::BackendHealth;
 
 // Enum to represent the different tabs
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum Tab {
     Dashboard,
     Monitoring,
+    Notifications,
+    Users,
     Files,
 }
 
-// Enum for protocol selection - Keep for UI, but server.rs doesn't use it yet
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
-enum Protocol {
-    HTTP,
-    HTTPS,
+// Buttons of a row in the users table
+#[derive(Clone, Copy)]
+enum UserAction {
+    SetPassword,
+    EditRoles,
+    Enable,
+    Disable,
+    Unlock,
+    Delete,
+}
+
+// Form for a new API key, lists as typed in the Users tab
+#[derive(Default)]
+struct NewApiKey {
+    name: String,
+    path_prefixes: String,
+    methods: String,
+    roles: String,
+    // 0 never expires
+    expires_days: u32,
+}
+
+// Server the Dashboard and Monitoring views show and control
+enum Target {
+    Local,
+    Remote(RemoteClient),
 }
 
 struct SolaraApp {
     server_status: ServerStatus,
     active_tab: Tab,
-    host: String,
-    port: u16,
-    protocol: Protocol,
-    tokio_handle: Handle,
-    shutdown_sender: Option<oneshot::Sender<()>>,
+    config: ServerConfig, // Host, port and upstream pools
+    config_path: Option<PathBuf>, // File the configuration was last loaded from or saved to
+    controller: ServerController, // Starts, stops and reloads the server in the Tokio runtime
+    runtime: Handle,
+    target: Target, // This process, or a Solara instance reached through its admin API
+    remote_form: RemoteSettings, // Connection form of the Dashboard
+    local_config: Option<ServerConfig>, // Configuration being edited locally, kept while a remote target is shown
+    listener_states: Vec<ListenerState>, // Per-listener state reported by the controller
+    validation: ValidationReport, // Problems with the configuration being edited
+    validated: Option<(ServerConfig, bool)>, // Config (and whether ports were probed) the report is for
     server_stats: ServerStats, // Add server stats
-    logs: Vec<String>, // Add a field to store logs
+    last_stats_update: Option<Instant>,
+    history: MetricsHistory, // Rolling samples for the Monitoring charts
+    chart_window_secs: f64, // Time span shown by the charts, changed by zooming
+    logs: Vec<LogEntry>, // Entries of the log viewer, oldest first
+    log_filter: String, // Text the log viewer is filtered by
+    log_level: Option<Severity>, // Lowest level shown in the log viewer, None shows all
+    notifications: NotificationCenter, // Errors and threshold alerts, shown as toasts and in their tab
+    selected_request: Option<String>, // Request ID opened from the Connection Monitor
+    connection_sort: (ConnectionColumn, bool), // Column the connection table is sorted by, and whether ascending
+    selected_connection: Option<u64>, // Connection picked in the connection table
+    new_user: (String, String), // Username and password typed into the Users tab
+    password_reset: Option<(String, String)>, // User whose password is being set, and the new password
+    role_edit: Option<(String, String)>, // User whose roles are being edited, and the roles as typed
+    access_check: (String, String), // Method and URL of the access simulator
+    new_api_key: NewApiKey, // API key form of the Users tab
+    generated_key: Option<(String, String)>, // Name and full text of a new API key, shown until dismissed
+    last_resource_check: Option<Instant>,
 }
 
 impl eframe::App for SolaraApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Use a static variable to track when we last updated stats
-        static mut LAST_UPDATE: Option<std::time::Instant> = None;
-        
+        // Pick up status changes reported by the server controller
+        self.poll_server_status();
+
         // Update stats every second
-        unsafe {
-            let now = std::time::Instant::now();
-            if LAST_UPDATE.is_none() || now.duration_since(LAST_UPDATE.unwrap()).as_secs() >= 1 {
-                self.update_stats();
-                LAST_UPDATE = Some(now);
-            }
+        let now = Instant::now();
+        if self.last_stats_update.is_none_or(|last| now.duration_since(last).as_secs() >= 1) {
+            self.update_stats();
+            self.last_stats_update = Some(now);
+        }
+        // Certificates and disk space of a remote target are not reachable from here
+        if matches!(self.target, Target::Local)
+            && self.last_resource_check.is_none_or(|last| now.duration_since(last) >= RESOURCE_CHECK_INTERVAL)
+        {
+            self.notifications.check_resources(&self.config, self.config_path.as_deref());
+            self.last_resource_check = Some(now);
         }
         
         // Request repaint continuously to check server status (can be optimized later)
@@ -57,6 +150,14 @@ impl eframe::App for SolaraApp {
             // Use radio buttons for tab selection
             ui.selectable_value(&mut self.active_tab, Tab::Dashboard, "Dashboard");
             ui.selectable_value(&mut self.active_tab, Tab::Monitoring, "Monitoring");
+            // Badge with the number of unacknowledged notifications, coloured by the worst one
+            let notifications_label = match self.notifications.highest_unacknowledged() {
+                Some(severity) => egui::RichText::new(format!("Notifications ({})", self.notifications.unacknowledged()))
+                    .color(severity.color()),
+                None => egui::RichText::new("Notifications"),
+            };
+            ui.selectable_value(&mut self.active_tab, Tab::Notifications, notifications_label);
+            ui.selectable_value(&mut self.active_tab, Tab::Users, "Users");
             ui.selectable_value(&mut self.active_tab, Tab::Files, "Files");
 
             ui.separator();
@@ -65,6 +166,9 @@ impl eframe::App for SolaraApp {
                  ui.separator();
                  // Display the ServerStatus enum
                  ui.label(format!("Status: {}", self.server_status));
+                 if let Target::Remote(remote) = &self.target {
+                     ui.label(format!("Remote: {}", remote.url()));
+                 }
             });
         });
 
@@ -79,106 +183,363 @@ impl eframe::App for SolaraApp {
                     // ui.label(format!("Status: {}", self.server_status));
                     // ui.separator();
 
-                    // Determine if server is in a startable or stoppable state
-                    let can_start = matches!(self.server_status, ServerStatus::Stopped | ServerStatus::Error(_));
-                    let can_stop = matches!(self.server_status, ServerStatus::Running | ServerStatus::Starting); // Allow stopping even if starting
+                    self.show_target(ui);
+                    ui.separator();
+
+                    // Re-validate whenever the configuration is edited
+                    self.refresh_validation();
+
+                    // Determine if server is in a startable or stoppable state. A remote server keeps
+                    // its admin listener, so only its site listeners are started and stopped.
+                    let (can_start, can_stop) = match &self.target {
+                        Target::Local => (
+                            matches!(self.server_status, ServerStatus::Stopped | ServerStatus::Error(_))
+                                && self.validation.is_valid(),
+                            matches!(self.server_status, ServerStatus::Running | ServerStatus::Starting), // Allow stopping even if starting
+                        ),
+                        Target::Remote(remote) => {
+                            let sites: Vec<&ListenerState> = self.listener_states.iter().filter(|state| !state.config.admin).collect();
+                            (
+                                remote.reachable() == Some(true)
+                                    && sites.iter().any(|state| matches!(state.status, ServerStatus::Stopped | ServerStatus::Error(_))),
+                                sites.iter().any(|state| matches!(state.status, ServerStatus::Running | ServerStatus::Starting)),
+                            )
+                        }
+                    };
 
                     // Server Control Buttons
                     ui.horizontal(|ui| {
                         if ui.add_enabled(can_start, egui::Button::new("Start Server")).clicked() {
-                            println!("Start Server button clicked");
-                            self.server_status = ServerStatus::Starting;
-
-                            let (tx, rx) = oneshot::channel();
-                            self.shutdown_sender = Some(tx); // Store the sender
-
-                            let config = ServerConfig {
-                                host: self.host.clone(),
-                                port: self.port,
-                                max_connections: 100, // Add this field with a default value
-                            };
-                            let handle = self.tokio_handle.clone();
-
-                            handle.spawn(async move {
-                                // Phase 1: Ignite the server (no longer needs rx)
-                                match server::start_server(config.clone()).await { // Removed rx here
-                                    Ok(rocket_instance) => {
-                                        println!("Server ignited successfully.");
-                                        // Phase 2: Launch the ignited server, passing the receiver again
-                                        match server::launch_ignited_server(rocket_instance, rx).await { // Re-added rx here
-                                            Ok(_) => {
-                                                println!("Server shut down gracefully.");
-                                            }
-                                            Err(e) => {
-                                                eprintln!("Server launch failed: {}", e);
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Server ignition failed: {}", e);
+                            match &self.target {
+                                Target::Local => {
+                                    if self.controller.start(self.config.clone()).is_err() {
+                                        // Something changed since the last check, e.g. a port was taken
+                                        self.validated = None;
                                     }
                                 }
-                            });
-                            self.server_status = ServerStatus::Running; // Simplification for now
+                                Target::Remote(remote) => remote.start(),
+                            }
                         }
 
                         if ui.add_enabled(can_stop, egui::Button::new("Stop Server")).clicked() {
-                            println!("Stop Server button clicked");
-                            if let Some(sender) = self.shutdown_sender.take() {
-                                self.server_status = ServerStatus::Stopping;
-                                if sender.send(()).is_ok() {
-                                    println!("Shutdown signal sent.");
-                                } else {
-                                    eprintln!("Failed to send shutdown signal (receiver dropped?).");
-                                    self.server_status = ServerStatus::Stopped; // Assume stopped
-                                }
-                            } else {
-                                println!("Server not running or already stopping.");
-                                self.server_status = ServerStatus::Stopped;
+                            match &self.target {
+                                Target::Local => self.controller.stop(),
+                                Target::Remote(remote) => remote.stop(),
                             }
                         }
                     });
 
                     ui.separator();
                     ui.label("Configuration:");
-                    // Configuration Inputs - Disable when server is not stopped/error
-                    let config_enabled = matches!(self.server_status, ServerStatus::Stopped | ServerStatus::Error(_));
+                    // Configuration Inputs - Editable while running so changes can be applied live
+                    let config_enabled = matches!(self.server_status,
+                        ServerStatus::Stopped | ServerStatus::Error(_) | ServerStatus::Running);
+                    let can_reload = self.server_status == ServerStatus::Running && self.validation.is_valid();
+                    let validation = &self.validation;
+
                     egui::Grid::new("config_grid")
                         .num_columns(2)
                         .spacing([40.0, 4.0])
                         .striped(true)
                         .show(ui, |ui| {
-                            ui.label("Host:");
-                            ui.add_enabled(config_enabled, egui::TextEdit::singleline(&mut self.host));
+                            ui.label("Max Connections:");
+                            let response = ui.add_enabled(config_enabled,
+                                egui::DragValue::new(&mut self.config.max_connections).clamp_range(0..=100_000));
+                            mark_field(ui, &response, validation, ConfigField::MaxConnections);
+                            ui.end_row();
+
+                            ui.label("Queue Timeout (ms):");
+                            ui.add_enabled(config_enabled,
+                                egui::DragValue::new(&mut self.config.queue_timeout_ms).clamp_range(0..=60_000));
+                            ui.end_row();
+
+                            // Workers and keep-alive only take effect after the listeners restart
+                            ui.label("Workers:");
+                            let response = ui.add_enabled(config_enabled,
+                                egui::DragValue::new(&mut self.config.workers).clamp_range(0..=1024));
+                            mark_field(ui, &response, validation, ConfigField::Workers);
+                            ui.end_row();
+
+                            ui.label("Keep-Alive (s):");
+                            ui.add_enabled(config_enabled,
+                                egui::DragValue::new(&mut self.config.keep_alive_secs).clamp_range(0..=3600))
+                                .on_hover_text("0 disables keep-alive");
+                            ui.end_row();
+
+                            ui.label("Request Timeout (s):");
+                            ui.add_enabled(config_enabled,
+                                egui::DragValue::new(&mut self.config.request_timeout_secs).clamp_range(0..=3600))
+                                .on_hover_text("0 disables the timeout");
+                            ui.end_row();
+
+                            ui.label("Metrics (/metrics):");
+                            ui.horizontal(|ui| {
+                                ui.add_enabled(config_enabled,
+                                    egui::Checkbox::new(&mut self.config.metrics.enabled, "Enabled"));
+                                let response = ui.add_enabled(config_enabled && self.config.metrics.enabled,
+                                    egui::Checkbox::new(&mut self.config.metrics.admin_only, "Admin listeners only"));
+                                mark_field(ui, &response, validation, ConfigField::Metrics);
+                            });
+                            ui.end_row();
+
+                            ui.label("Admin API (/api):");
+                            ui.horizontal(|ui| {
+                                let admin_api = &mut self.config.admin_api;
+                                let response = ui.add_enabled(config_enabled, egui::Checkbox::new(&mut admin_api.enabled, "Enabled"))
+                                    .on_hover_text("Remote control on the admin listeners");
+                                mark_field(ui, &response, validation, ConfigField::AdminApi);
+                                ui.label("Roles:");
+                                let mut roles = admin_api.roles.join(",");
+                                let response = ui.add_enabled(config_enabled && admin_api.enabled,
+                                    egui::TextEdit::singleline(&mut roles).hint_text("admin").desired_width(140.0));
+                                if response.changed() {
+                                    admin_api.roles = if roles.trim().is_empty() {
+                                        Vec::new()
+                                    } else {
+                                        roles.split(',').map(|role| role.trim().to_string()).collect()
+                                    };
+                                }
+                                mark_field(ui, &response, validation, ConfigField::AdminApi);
+                            });
                             ui.end_row();
 
-                            ui.label("Port:");
-                            ui.add_enabled(config_enabled, egui::DragValue::new(&mut self.port).clamp_range(1..=65535));
+                            ui.label("Control Socket:");
+                            ui.horizontal(|ui| {
+                                let control_socket = &mut self.config.control_socket;
+                                let response = ui.add_enabled(config_enabled, egui::Checkbox::new(&mut control_socket.enabled, "Enabled"))
+                                    .on_hover_text("Local control with solaractl, for the user running the server");
+                                mark_field(ui, &response, validation, ConfigField::ControlSocket);
+                                let response = ui.add_enabled(config_enabled && control_socket.enabled,
+                                    egui::TextEdit::singleline(&mut control_socket.path).hint_text("solara.sock").desired_width(140.0));
+                                mark_field(ui, &response, validation, ConfigField::ControlSocket);
+                            });
                             ui.end_row();
 
-                            ui.label("Protocol:");
+                            // Kept as typed, including empty pieces, so commas can be entered one at a time
+                            ui.label("Status Trusted IPs:");
+                            let mut trusted = self.config.status_trusted_ips.join(",");
+                            let response = ui.add_enabled(config_enabled, egui::TextEdit::singleline(&mut trusted)
+                                .hint_text("everyone, or e.g. 127.0.0.1, 10.0.0.0/8"));
+                            if response.changed() {
+                                self.config.status_trusted_ips = if trusted.trim().is_empty() {
+                                    Vec::new()
+                                } else {
+                                    trusted.split(',').map(str::to_string).collect()
+                                };
+                            }
+                            mark_field(ui, &response, validation, ConfigField::StatusAccess);
+                            ui.end_row();
+
+                            ui.label("OTLP Trace Export:");
+                            ui.horizontal(|ui| {
+                                ui.add_enabled(config_enabled,
+                                    egui::Checkbox::new(&mut self.config.tracing.otlp_enabled, "Enabled"));
+                                ui.add_enabled(config_enabled && self.config.tracing.otlp_enabled,
+                                    egui::TextEdit::singleline(&mut self.config.tracing.otlp_endpoint)
+                                        .hint_text("http://127.0.0.1:4318/v1/traces"));
+                            });
+                            ui.end_row();
+
+                            ui.label("Access Log:");
+                            ui.horizontal(|ui| {
+                                let access_log = &mut self.config.access_log;
+                                ui.add_enabled(config_enabled, egui::Checkbox::new(&mut access_log.enabled, "Enabled"));
+                                let log_enabled = config_enabled && access_log.enabled;
+                                ui.add_enabled_ui(log_enabled, |ui| {
+                                    egui::ComboBox::from_id_source("access_log_format")
+                                        .selected_text(access_log.format.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for format in [AccessLogFormat::Common, AccessLogFormat::Combined,
+                                                AccessLogFormat::Custom, AccessLogFormat::Json] {
+                                                ui.selectable_value(&mut access_log.format, format, format.to_string());
+                                            }
+                                        });
+                                });
+                                let response = ui.add_enabled(log_enabled, egui::TextEdit::singleline(&mut access_log.path)
+                                    .hint_text("standard output"));
+                                mark_field(ui, &response, validation, ConfigField::AccessLog);
+                            });
+                            ui.end_row();
+
+                            if self.config.access_log.format == AccessLogFormat::Custom {
+                                ui.label("Access Log Template:");
+                                let response = ui.add_enabled(config_enabled && self.config.access_log.enabled,
+                                    egui::TextEdit::singleline(&mut self.config.access_log.template).desired_width(400.0))
+                                    .on_hover_text("Variables like $remote_addr, $time_local, $request, $status, $body_bytes_sent, \
+                                        $request_time, $request_id, $listener or $http_user_agent");
+                                mark_field(ui, &response, validation, ConfigField::AccessLog);
+                                ui.end_row();
+                            }
+
+                            ui.label("Log Forwarding:");
                             ui.add_enabled_ui(config_enabled, |ui| {
-                                egui::ComboBox::from_label("")
-                                    .selected_text(format!("{:?}", self.protocol))
-                                    .show_ui(ui, |ui| {
-                                        ui.selectable_value(&mut self.protocol, Protocol::HTTP, "HTTP");
-                                        ui.selectable_value(&mut self.protocol, Protocol::HTTPS, "HTTPS");
-                                    })
-                                    .response.on_hover_text("Select HTTP or HTTPS");
+                                let forwarding = &mut self.config.log_forwarding;
+                                ui.horizontal(|ui| {
+                                    egui::ComboBox::from_id_source("forward_target")
+                                        .selected_text(forwarding.target.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for target in [ForwardTarget::Off, ForwardTarget::Syslog, ForwardTarget::Journald] {
+                                                ui.selectable_value(&mut forwarding.target, target, target.to_string());
+                                            }
+                                        });
+                                    if forwarding.target == ForwardTarget::Syslog {
+                                        egui::ComboBox::from_id_source("syslog_transport")
+                                            .selected_text(forwarding.transport.to_string())
+                                            .show_ui(ui, |ui| {
+                                                for transport in [SyslogTransport::Udp, SyslogTransport::Tcp, SyslogTransport::Unix] {
+                                                    ui.selectable_value(&mut forwarding.transport, transport, transport.to_string());
+                                                }
+                                            });
+                                        let hint = if forwarding.transport == SyslogTransport::Unix { "/dev/log" } else { "127.0.0.1:514" };
+                                        let response = ui.add(egui::TextEdit::singleline(&mut forwarding.address)
+                                            .hint_text(hint).desired_width(140.0));
+                                        mark_field(ui, &response, validation, ConfigField::LogForwarding);
+                                    }
+                                    if forwarding.target != ForwardTarget::Off {
+                                        egui::ComboBox::from_id_source("syslog_facility")
+                                            .selected_text(forwarding.facility.to_string())
+                                            .show_ui(ui, |ui| {
+                                                for facility in Facility::ALL {
+                                                    ui.selectable_value(&mut forwarding.facility, facility, facility.to_string());
+                                                }
+                                            });
+                                        ui.checkbox(&mut forwarding.access_log, "Access log");
+                                        ui.checkbox(&mut forwarding.server_log, "Server log");
+                                    }
+                                });
                             });
                             ui.end_row();
                         });
 
+                    ui.label("Listeners:");
+                    let mut remove_listener = None;
+                    let listener_count = self.config.listeners.len();
+                    egui::Grid::new("listener_grid")
+                        .num_columns(9)
+                        .spacing([10.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for header in ["Name", "Host", "Port", "Protocol", "Certificate", "Key", "Admin", "State", ""] {
+                                ui.strong(header);
+                            }
+                            ui.end_row();
+
+                            for (i, listener) in self.config.listeners.iter_mut().enumerate() {
+                                let response = ui.add_enabled(config_enabled, egui::TextEdit::singleline(&mut listener.name).desired_width(70.0));
+                                mark_field(ui, &response, validation, ConfigField::ListenerName(i));
+                                let response = ui.add_enabled(config_enabled, egui::TextEdit::singleline(&mut listener.host).desired_width(110.0));
+                                mark_field(ui, &response, validation, ConfigField::ListenerHost(i));
+                                let response = ui.add_enabled(config_enabled, egui::DragValue::new(&mut listener.port).clamp_range(1..=65535));
+                                mark_field(ui, &response, validation, ConfigField::ListenerPort(i));
+
+                                ui.add_enabled_ui(config_enabled, |ui| {
+                                    egui::ComboBox::from_id_source(("listener_protocol", i))
+                                        .selected_text(listener.protocol.to_string())
+                                        .show_ui(ui, |ui| {
+                                            ui.selectable_value(&mut listener.protocol, Protocol::Http, "HTTP");
+                                            ui.selectable_value(&mut listener.protocol, Protocol::Https, "HTTPS");
+                                        })
+                                        .response.on_hover_text("Select HTTP or HTTPS");
+                                });
+
+                                // Certificate and key only matter for HTTPS
+                                let tls_enabled = config_enabled && listener.protocol == Protocol::Https;
+                                let response = ui.add_enabled(tls_enabled, egui::TextEdit::singleline(&mut listener.tls.cert_path)
+                                    .hint_text("cert.pem").desired_width(100.0));
+                                mark_field(ui, &response, validation, ConfigField::ListenerTls(i));
+                                let response = ui.add_enabled(tls_enabled, egui::TextEdit::singleline(&mut listener.tls.key_path)
+                                    .hint_text("key.pem").desired_width(100.0));
+                                mark_field(ui, &response, validation, ConfigField::ListenerTls(i));
+                                ui.add_enabled(config_enabled, egui::Checkbox::without_text(&mut listener.admin))
+                                    .on_hover_text("Only serve monitoring endpoints and the admin API on this listener");
+
+                                match self.listener_states.iter().find(|state| state.config.name == listener.name) {
+                                    Some(state) => {
+                                        let color = match state.status {
+                                            ServerStatus::Running => egui::Color32::GREEN,
+                                            ServerStatus::Error(_) => egui::Color32::RED,
+                                            ServerStatus::Stopped => egui::Color32::GRAY,
+                                            _ => egui::Color32::YELLOW,
+                                        };
+                                        ui.colored_label(color, state.status.to_string())
+                                            .on_hover_text(state.config.to_string());
+                                    }
+                                    None => {
+                                        ui.weak("Not started");
+                                    }
+                                }
+
+                                if ui.add_enabled(config_enabled && listener_count > 1, egui::Button::new("Remove")).clicked() {
+                                    remove_listener = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    if let Some(i) = remove_listener {
+                        self.config.listeners.remove(i);
+                    }
+
+                    // Everything that is wrong, including fields without an input of their own
+                    for error in &self.validation.errors {
+                        ui.colored_label(egui::Color32::RED, format!("Error: {}", error));
+                    }
+                    for warning in &self.validation.warnings {
+                        ui.colored_label(egui::Color32::YELLOW, format!("Warning: {}", warning));
+                    }
+                    if ui.add_enabled(config_enabled, egui::Button::new("Add Listener")).clicked() {
+                        let port = self.config.listeners.iter().map(|l| l.port).max().unwrap_or(7999).saturating_add(1);
+                        self.config.listeners.push(ListenerConfig {
+                            name: format!("listener{}", self.config.listeners.len() + 1),
+                            port,
+                            ..ListenerConfig::default()
+                        });
+                    }
+
                     // Save/Load Buttons - Disable when server is not stopped/error
                     ui.horizontal(|ui| {
                         if ui.add_enabled(config_enabled, egui::Button::new("Save Config")).clicked() {
-                            println!("Save Config button clicked"); // Placeholder
+                            self.save_config_dialog();
                         }
 
                         if ui.add_enabled(config_enabled, egui::Button::new("Load Config")).clicked() {
-                            println!("Load Config button clicked"); // Placeholder
+                            self.load_config_dialog();
+                        }
+
+                        // Listeners that keep their address restart for some changes, say so up front
+                        let (apply_label, apply_hover) = match &self.target {
+                            Target::Local if can_reload
+                                && self.controller.reload_kind(&self.config) == Some(ReloadKind::Restart) => (
+                                "Apply (restarts listeners)",
+                                "Listeners keeping their address restart for these changes and refuse connections until they are back",
+                            ),
+                            Target::Local => ("Apply without restart", "Swap the configuration into the running server"),
+                            Target::Remote(_) => (
+                                "Apply configuration",
+                                "Swap the configuration into the server, listeners keeping their address restart if they have to",
+                            ),
+                        };
+                        if ui.add_enabled(can_reload, egui::Button::new(apply_label))
+                            .on_hover_text(apply_hover)
+                            .clicked() {
+                            self.apply_config();
+                        }
+
+                        let can_reload_file = can_reload && self.config_path.is_some();
+                        if ui.add_enabled(can_reload_file, egui::Button::new("Reload Config File"))
+                            .on_hover_text("Re-read the configuration file and apply it to the running server")
+                            .clicked() {
+                            self.reload_config_file();
                         }
                     });
+
+                    if !self.config.upstreams.is_empty() {
+                        ui.separator();
+                        ui.label("Upstream Pools:");
+                        for pool in &self.config.upstreams {
+                            ui.label(format!("{} -> '{}' ({}, {} backends)",
+                                pool.path_prefix, pool.name, pool.strategy, pool.backends.len()));
+                        }
+                    }
                 }
                 Tab::Monitoring => {
                     ui.heading("Server Monitoring");
@@ -197,52 +558,100 @@ impl eframe::App for SolaraApp {
                                     .striped(true)
                                     .show(ui, |ui| {
                                         ui.label("Active Connections:");
-                                        ui.label(format!("{}", self.server_stats.active_connections));
+                                        ui.label(format!("{} / {}", self.server_stats.active_connections, self.config.max_connections));
                                         ui.end_row();
-                                        
-                                        ui.label("Uptime:");
-                                        ui.label(format!("{} seconds", self.server_stats.uptime_seconds));
+
+                                        ui.label("Rejected (503):");
+                                        ui.label(format!("{}", self.server_stats.rejected_requests));
                                         ui.end_row();
-                                        
-                                        // Add more stats as they become available
-                                        ui.label("Memory Usage:");
-                                        ui.label("N/A"); // Placeholder
+
+                                        ui.label("Timed Out:");
+                                        ui.label(format!("{}", self.server_stats.timed_out_requests));
                                         ui.end_row();
                                         
-                                        ui.label("CPU Usage:");
-                                        ui.label("N/A"); // Placeholder
+                                        ui.label("Uptime:");
+                                        ui.label(server::format_duration(self.server_stats.uptime_seconds));
                                         ui.end_row();
+                                        
+                                        // The server runs inside this process, so these are its resources
+                                        match &self.server_stats.process {
+                                            Some(process) => {
+                                                ui.label("Memory Usage:");
+                                                ui.label(format!("{} resident, {} virtual",
+                                                    process::format_bytes(process.resident_memory_bytes),
+                                                    process::format_bytes(process.virtual_memory_bytes)));
+                                                ui.end_row();
+
+                                                ui.label("CPU Usage:");
+                                                ui.label(format!("{:.1}% ({:.1}s total)", process.cpu_percent, process.cpu_seconds));
+                                                ui.end_row();
+
+                                                ui.label("Open Files:");
+                                                ui.label(format!("{}", process.open_fds));
+                                                ui.end_row();
+
+                                                ui.label("Threads:");
+                                                ui.label(format!("{}", process.threads));
+                                                ui.end_row();
+                                            }
+                                            None => {
+                                                ui.label("Memory Usage:");
+                                                ui.label("N/A");
+                                                ui.end_row();
+
+                                                ui.label("CPU Usage:");
+                                                ui.label("N/A");
+                                                ui.end_row();
+                                            }
+                                        }
                                     });
                             });
                             
-                            // Graph placeholder - will be replaced with actual data visualization
                             ui.group(|ui| {
-                                ui.heading("Resource Usage");
+                                ui.heading("History");
                                 ui.separator();
-                                
-                                let available_width = ui.available_width();
-                                let graph_height = 100.0;
-                                
-                                // Placeholder for CPU usage graph
-                                ui.label("CPU Usage Over Time");
-                                ui.add(egui::widgets::ProgressBar::new(0.4)
-                                    .animate(true)
-                                    .desired_width(available_width));
-                                
-                                // Placeholder for Memory usage graph
-                                ui.label("Memory Usage Over Time");
-                                ui.add(egui::widgets::ProgressBar::new(0.6)
-                                    .animate(true)
-                                    .desired_width(available_width));
-                                
-                                // Add a refresh button
-                                if ui.button("Refresh Statistics").clicked() {
-                                    // Fetch latest stats here
-                                    if let ServerStatus::Running = self.server_status {
-                                        // Only update if server is running
-                                        // self.server_stats = server::get_server_stats();
+
+                                // Ctrl+scroll over a chart to zoom, or pick a preset span
+                                ui.horizontal(|ui| {
+                                    for (label, secs) in [("1 min", 60.0), ("10 min", 600.0), ("1 hour", history::MAX_WINDOW_SECS)] {
+                                        if ui.selectable_label(self.chart_window_secs == secs, label).clicked() {
+                                            self.chart_window_secs = secs;
+                                        }
                                     }
-                                }
+                                    if ui.button("Refresh Statistics").clicked() {
+                                        self.update_stats();
+                                    }
+                                });
+
+                                let samples = self.history.samples(self.chart_window_secs);
+                                let now = self.history.now();
+                                let window = &mut self.chart_window_secs;
+                                egui::ScrollArea::vertical()
+                                    .id_source("history_charts")
+                                    .max_height(420.0)
+                                    .show(ui, |ui| {
+                                        chart::time_series(ui, "Requests", "req/s", &[
+                                            Series { name: "rate", color: Color32::LIGHT_BLUE, value: |s| s.requests_per_sec },
+                                        ], &samples, now, window);
+                                        chart::time_series(ui, "Latency", "ms", &[
+                                            Series { name: "p50", color: Color32::LIGHT_GREEN, value: |s| s.latency_p50_ms },
+                                            Series { name: "p95", color: Color32::YELLOW, value: |s| s.latency_p95_ms },
+                                            Series { name: "p99", color: Color32::LIGHT_RED, value: |s| s.latency_p99_ms },
+                                        ], &samples, now, window);
+                                        chart::time_series(ui, "Errors", "% 5xx", &[
+                                            Series { name: "error rate", color: Color32::LIGHT_RED, value: |s| s.error_rate },
+                                        ], &samples, now, window);
+                                        chart::time_series(ui, "Connections", "", &[
+                                            Series { name: "in flight", color: Color32::GOLD, value: |s| s.connections },
+                                        ], &samples, now, window);
+                                        // A single fully busy core is 100%
+                                        chart::time_series(ui, "CPU", "%", &[
+                                            Series { name: "process", color: Color32::from_rgb(255, 140, 0), value: |s| s.cpu_percent },
+                                        ], &samples, now, window);
+                                        chart::time_series(ui, "Memory", "MB", &[
+                                            Series { name: "resident", color: Color32::from_rgb(180, 130, 255), value: |s| s.memory_mb },
+                                        ], &samples, now, window);
+                                    });
                             });
                         });
                         
@@ -255,35 +664,47 @@ impl eframe::App for SolaraApp {
                                 // Log filtering options
                                 ui.horizontal(|ui| {
                                     ui.label("Filter:");
-                                    let mut filter = String::new();
-                                    ui.text_edit_singleline(&mut filter);
-                                    
+                                    ui.text_edit_singleline(&mut self.log_filter);
+
                                     ui.label("Level:");
-                                    egui::ComboBox::from_label("")
-                                        .selected_text("All")
+                                    egui::ComboBox::from_id_source("log_level")
+                                        .selected_text(self.log_level.map_or("All".to_string(), |level| level.to_string()))
                                         .show_ui(ui, |ui| {
-                                            ui.selectable_value(&mut String::new(), "All".to_string(), "All");
-                                            ui.selectable_value(&mut String::new(), "Info".to_string(), "Info");
-                                            ui.selectable_value(&mut String::new(), "Warn".to_string(), "Warn");
-                                            ui.selectable_value(&mut String::new(), "Error".to_string(), "Error");
+                                            ui.selectable_value(&mut self.log_level, None, "All");
+                                            for level in [Severity::Info, Severity::Warning, Severity::Error] {
+                                                ui.selectable_value(&mut self.log_level, Some(level), level.to_string());
+                                            }
                                         });
-                                    
+
                                     if ui.button("Clear Logs").clicked() {
                                         self.logs.clear();
                                     }
+                                    if ui.add_enabled(!self.logs.is_empty(), egui::Button::new("Export"))
+                                        .on_hover_text("Save the entries shown below")
+                                        .clicked()
+                                    {
+                                        self.export_logs_dialog();
+                                    }
                                 });
-                                
+
                                 // Log display area with scrolling
                                 egui::ScrollArea::vertical()
                                     .max_height(250.0)
                                     .show(ui, |ui| {
-                                        for log in &self.logs {
-                                            ui.label(log);
+                                        let mut shown = 0;
+                                        for entry in self.logs.iter().filter(|entry| entry.matches(&self.log_filter, self.log_level)) {
+                                            match entry.severity {
+                                                Severity::Info => ui.label(entry.to_string()),
+                                                severity => ui.colored_label(severity.color(), entry.to_string()),
+                                            }
+                                            .on_hover_text(entry.timestamp());
+                                            shown += 1;
                                         }
-                                        
-                                        // Add some example logs if empty
+
                                         if self.logs.is_empty() {
                                             ui.weak("No logs to display");
+                                        } else if shown == 0 {
+                                            ui.weak("No logs match the filter");
                                         }
                                     });
                             });
@@ -293,24 +714,216 @@ impl eframe::App for SolaraApp {
                                 ui.heading("Connection Monitor");
                                 ui.separator();
                                 
-                                ui.label(format!("Active Connections: {}", self.server_stats.active_connections));
-                                
-                                // Placeholder for connection list (will be replaced with actual data)
+                                let mut connections = match &self.target {
+                                    Target::Local => connections::open_connections(),
+                                    Target::Remote(remote) => remote.connections(),
+                                };
+                                let (column, ascending) = self.connection_sort;
+                                connections.sort_by(|a, b| {
+                                    let order = column.compare(a, b);
+                                    if ascending { order } else { order.reverse() }
+                                });
+                                ui.label(format!("Open Connections: {}, requests in flight: {}",
+                                    connections.len(), self.server_stats.active_connections));
+
                                 egui::ScrollArea::vertical()
+                                    .id_source("open_connections")
                                     .max_height(150.0)
                                     .show(ui, |ui| {
-                                        if self.server_status == ServerStatus::Running {
-                                            ui.label("127.0.0.1 - [GET] /index.html - 200 OK");
-                                            ui.label("192.168.1.5 - [POST] /api/data - 201 Created");
-                                            ui.label("127.0.0.1 - [GET] /styles.css - 304 Not Modified");
-                                        } else {
-                                            ui.weak("Server not running");
+                                        egui::Grid::new("connection_table").num_columns(ConnectionColumn::ALL.len()).striped(true).show(ui, |ui| {
+                                            // Clicking a header sorts by it, clicking again flips the order
+                                            for header in ConnectionColumn::ALL {
+                                                let arrow = match self.connection_sort {
+                                                    (sorted, true) if sorted == header => " ⬆",
+                                                    (sorted, false) if sorted == header => " ⬇",
+                                                    _ => "",
+                                                };
+                                                if ui.selectable_label(false, egui::RichText::new(format!("{}{}", header, arrow)).strong()).clicked() {
+                                                    self.connection_sort = (header, self.connection_sort != (header, true));
+                                                }
+                                            }
+                                            ui.end_row();
+
+                                            let now = std::time::SystemTime::now();
+                                            for connection in &connections {
+                                                let selected = self.selected_connection == Some(connection.id);
+                                                let scheme = if connection.secure { "https" } else { "http" };
+                                                if ui.selectable_label(selected, connection.remote.to_string()).clicked() {
+                                                    self.selected_connection = if selected { None } else { Some(connection.id) };
+                                                }
+                                                ui.label(format!("{} ({})", connection.listener, scheme));
+                                                let open_for = now.duration_since(connection.opened).unwrap_or_default();
+                                                ui.label(server::format_duration(open_for.as_secs()));
+                                                ui.label(connection.requests.to_string());
+                                                ui.label(process::format_bytes(connection.bytes_received));
+                                                ui.label(process::format_bytes(connection.bytes_sent));
+                                                if connection.closing {
+                                                    ui.colored_label(egui::Color32::YELLOW, "closing");
+                                                } else {
+                                                    ui.label(connection.current_request.as_deref().unwrap_or("idle"));
+                                                }
+                                                ui.end_row();
+                                            }
+                                        });
+                                        if connections.is_empty() {
+                                            ui.weak("No open connections");
                                         }
                                     });
+
+                                let selected = self.selected_connection.filter(|id| connections.iter().any(|c| c.id == *id));
+                                if ui.add_enabled(selected.is_some(), egui::Button::new("Close Connection"))
+                                    .on_hover_text("Cancels the request in progress and closes the connection after its response. \
+                                        Idle connections close at their next request or when keep-alive runs out.")
+                                    .clicked()
+                                {
+                                    if let Some(id) = selected {
+                                        if let Some(connection) = connections.iter().find(|c| c.id == id) {
+                                            let message = format!("Closing connection from {} on listener '{}'", connection.remote, connection.listener);
+                                            match &self.target {
+                                                Target::Local => { connections::close_connection(id); }
+                                                Target::Remote(remote) => remote.close_connection(id),
+                                            }
+                                            self.add_log(&message);
+                                        }
+                                    }
+                                }
+
+                                ui.separator();
+                                ui.label("Recent requests, click one for details:");
+
+                                egui::ScrollArea::vertical()
+                                    .id_source("recent_requests")
+                                    .max_height(150.0)
+                                    .show(ui, |ui| {
+                                        let recent = match &self.target {
+                                            Target::Local => trace::recent_requests(),
+                                            Target::Remote(remote) => remote.recent_requests(),
+                                        };
+                                        if recent.is_empty() {
+                                            ui.weak("No requests yet");
+                                        }
+                                        for record in recent.iter().rev() {
+                                            let time = chrono::DateTime::<chrono::Local>::from(record.started);
+                                            let text = format!("{} {} - [{}] {} - {} ({:.1} ms)",
+                                                time.format("%H:%M:%S"),
+                                                record.remote.map_or("-".to_string(), |remote| remote.ip().to_string()),
+                                                record.method, record.uri, record.status,
+                                                record.timing.total.as_secs_f64() * 1000.0);
+                                            let selected = self.selected_request.as_deref() == Some(record.request_id.as_str());
+                                            if ui.selectable_label(selected, text).clicked() {
+                                                self.selected_request = Some(record.request_id.clone());
+                                            }
+                                        }
+                                    });
+                            });
+
+                            // Upstream backend health next to the connection monitor
+                            ui.group(|ui| {
+                                ui.heading("Upstream Backends");
+                                ui.separator();
+
+                                if self.server_stats.upstreams.is_empty() {
+                                    ui.weak("No upstream pools configured");
+                                }
+                                for pool in &self.server_stats.upstreams {
+                                    ui.label(format!("{} ({}) - {}", pool.name, pool.path_prefix, pool.strategy));
+                                    egui::Grid::new(format!("upstream_grid_{}", pool.name))
+                                        .num_columns(6)
+                                        .spacing([20.0, 4.0])
+                                        .striped(true)
+                                        .show(ui, |ui| {
+                                            ui.strong("Backend");
+                                            ui.strong("Health");
+                                            ui.strong("Latency");
+                                            ui.strong("Active");
+                                            ui.strong("Requests");
+                                            ui.strong("Failed");
+                                            ui.end_row();
+
+                                            for backend in &pool.backends {
+                                                ui.label(format!("{} (w{})", backend.address, backend.weight));
+                                                let color = match backend.health {
+                                                    BackendHealth::Healthy => egui::Color32::GREEN,
+                                                    BackendHealth::Unhealthy => egui::Color32::RED,
+                                                    BackendHealth::Ejected => egui::Color32::YELLOW,
+                                                };
+                                                ui.colored_label(color, backend.health.to_string());
+                                                ui.label(format!("{:.1} ms", backend.avg_latency_ms));
+                                                ui.label(format!("{}", backend.in_flight));
+                                                ui.label(format!("{}", backend.total_requests));
+                                                ui.label(format!("{}", backend.failed_requests));
+                                                ui.end_row();
+                                            }
+                                        });
+                                }
                             });
                         });
                     });
                 }
+                Tab::Notifications => {
+                    ui.heading("Notifications");
+                    ui.separator();
+
+                    ui.label("Alert thresholds:");
+                    egui::Grid::new("alert_grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Server error rate (%):");
+                            ui.add(egui::DragValue::new(&mut self.config.alerts.error_rate_percent)
+                                .clamp_range(0.0..=100.0).speed(0.1));
+                            ui.end_row();
+
+                            ui.label("Certificate expiry (days):");
+                            ui.add(egui::DragValue::new(&mut self.config.alerts.cert_expiry_days).clamp_range(0..=365));
+                            ui.end_row();
+
+                            ui.label("Free disk space (%):");
+                            ui.add(egui::DragValue::new(&mut self.config.alerts.disk_free_percent)
+                                .clamp_range(0.0..=100.0).speed(0.1));
+                            ui.end_row();
+                        });
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Acknowledge All").clicked() {
+                            self.notifications.acknowledge_all();
+                        }
+                        if ui.button("Clear Acknowledged").clicked() {
+                            self.notifications.clear_acknowledged();
+                        }
+                    });
+
+                    let mut acknowledge = None;
+                    egui::ScrollArea::vertical()
+                        .id_source("notification_list")
+                        .show(ui, |ui| {
+                            if self.notifications.notifications().is_empty() {
+                                ui.label("No notifications");
+                            }
+                            // Newest first
+                            for notification in self.notifications.notifications().iter().rev() {
+                                ui.horizontal(|ui| {
+                                    let color = if notification.acknowledged {
+                                        ui.visuals().weak_text_color()
+                                    } else {
+                                        notification.severity.color()
+                                    };
+                                    ui.colored_label(color, format!("[{}] {}",
+                                        notification.time.format("%Y-%m-%d %H:%M:%S"), notification.severity));
+                                    ui.label(&notification.message);
+                                    if !notification.acknowledged && ui.small_button("Acknowledge").clicked() {
+                                        acknowledge = Some(notification.id);
+                                    }
+                                });
+                            }
+                        });
+                    if let Some(id) = acknowledge {
+                        self.notifications.acknowledge(id);
+                    }
+                }
+                Tab::Users => self.show_users(ui),
                 Tab::Files => {
                     ui.heading("File Management");
                     ui.label("This is the file management view.");
@@ -318,66 +931,1147 @@ impl eframe::App for SolaraApp {
                 }
             }
         });
+
+        self.show_request_details(ctx);
+        self.notifications.show_toasts(ctx);
     }
 }
 
 impl SolaraApp {
     fn new(_cc: &eframe::CreationContext<'_>, tokio_handle: Handle) -> Self {
-        Self {
+        let controller = ServerController::new(tokio_handle.clone());
+        admin_api::attach(controller.clone());
+        let mut app = Self {
             server_status: ServerStatus::Stopped,
             active_tab: Tab::Dashboard,
-            host: "127.0.0.1".to_string(),
-            port: 8000,
-            protocol: Protocol::HTTP,
-            tokio_handle,
-            shutdown_sender: None,
-            server_stats: ServerStats {
-                active_connections: 0,
-                uptime_seconds: 0,
-            },
+            config: ServerConfig::default(),
+            config_path: None,
+            listener_states: Vec::new(),
+            validation: ValidationReport::default(),
+            validated: None,
+            controller,
+            runtime: tokio_handle,
+            target: Target::Local,
+            remote_form: RemoteSettings::default(),
+            local_config: None,
+            server_stats: ServerStats::default(),
+            history: MetricsHistory::new(),
+            chart_window_secs: history::FINE_WINDOW_SECS,
+            last_stats_update: None,
             logs: Vec::new(),
+            log_filter: String::new(),
+            log_level: None,
+            notifications: NotificationCenter::default(),
+            selected_request: None,
+            connection_sort: (ConnectionColumn::Opened, true),
+            selected_connection: None,
+            new_user: (String::new(), String::new()),
+            password_reset: None,
+            role_edit: None,
+            access_check: ("GET".to_string(), "/".to_string()),
+            new_api_key: NewApiKey::default(),
+            generated_key: None,
+            last_resource_check: None,
+        };
+        app.load_auth_files();
+        app
+    }
+
+    // Users and API keys files of the configuration, also loaded by the server when it starts
+    fn load_auth_files(&mut self) {
+        let path = PathBuf::from(&self.config.auth.users_file);
+        if let Err(e) = users::load_users(&path) {
+            self.report(Severity::Error, &format!("Failed to load users from {}: {}", path.display(), e));
+        }
+        let path = PathBuf::from(&self.config.auth.api_keys.keys_file);
+        if let Err(e) = api_keys::load_keys(&path) {
+            self.report(Severity::Error, &format!("Failed to load API keys from {}: {}", path.display(), e));
         }
     }
+
+    // Log the outcome of a change made in the Users tab
+    fn user_change(&mut self, result: Result<(), users::UserError>, done: String) {
+        match result {
+            Ok(()) => self.add_log(&done),
+            Err(e) => self.report(Severity::Error, &format!("User change failed: {}", e)),
+        }
+    }
+
+    fn show_users(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Users");
+        ui.separator();
+        if let Target::Remote(remote) = &self.target {
+            ui.colored_label(Color32::YELLOW, format!(
+                "The users, sessions and keys below are those of this machine, not of {}.", remote.url()));
+            ui.separator();
+        }
+
+        ui.label("Authentication:");
+        let validation = &self.validation;
+        let mut load = false;
+        egui::Grid::new("auth_grid")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                let auth = &mut self.config.auth;
+                ui.label("Users File:");
+                ui.horizontal(|ui| {
+                    let response = ui.text_edit_singleline(&mut auth.users_file);
+                    mark_field(ui, &response, validation, ConfigField::Auth);
+                    load = ui.button("Load").clicked();
+                });
+                ui.end_row();
+
+                // Kept as typed, including empty pieces, so commas can be entered one at a time
+                ui.label("Protected Paths:");
+                let mut paths = auth.protected_paths.join(",");
+                let response = ui.add(egui::TextEdit::singleline(&mut paths).hint_text("none, or e.g. /private, /admin"));
+                if response.changed() {
+                    auth.protected_paths = if paths.trim().is_empty() {
+                        Vec::new()
+                    } else {
+                        paths.split(',').map(|path| path.trim().to_string()).collect()
+                    };
+                }
+                mark_field(ui, &response, validation, ConfigField::Auth);
+                ui.end_row();
+
+                ui.label("Realm:");
+                ui.text_edit_singleline(&mut auth.realm);
+                ui.end_row();
+
+                ui.label("Lockout:");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut auth.max_failed_logins).clamp_range(0..=100))
+                        .on_hover_text("0 disables the lockout");
+                    ui.label("failed logins lock an account for");
+                    ui.add(egui::DragValue::new(&mut auth.lockout_secs).clamp_range(1..=86400).suffix(" s"));
+                });
+                ui.end_row();
+
+                let session_config = &mut self.config.sessions;
+                ui.label("Session Timeouts:");
+                ui.horizontal(|ui| {
+                    ui.label("idle");
+                    let response = ui.add(egui::DragValue::new(&mut session_config.idle_timeout_secs)
+                        .clamp_range(1..=604800).suffix(" s"));
+                    mark_field(ui, &response, validation, ConfigField::Sessions);
+                    ui.label("absolute");
+                    ui.add(egui::DragValue::new(&mut session_config.absolute_timeout_secs).clamp_range(1..=2592000).suffix(" s"));
+                });
+                ui.end_row();
+
+                ui.label("Session Store:");
+                let response = ui.add(egui::TextEdit::singleline(&mut session_config.store_path).hint_text("memory only"))
+                    .on_hover_text("File sessions are kept in across restarts");
+                mark_field(ui, &response, validation, ConfigField::Sessions);
+                ui.end_row();
+
+                ui.label("Session Key File:");
+                let response = ui.add(egui::TextEdit::singleline(&mut session_config.secret_key_path)
+                    .hint_text("new key on every start"))
+                    .on_hover_text("Created when missing. Changing it needs a restart and logs everybody out.");
+                mark_field(ui, &response, validation, ConfigField::Sessions);
+                ui.end_row();
+            });
+        ui.collapsing("API Tokens (JWT)", |ui| self.show_jwt_settings(ui));
+        ui.collapsing("Single Sign-On (OpenID Connect)", |ui| self.show_oidc_settings(ui));
+        ui.label("Settings apply when the server starts or the configuration is applied.");
+        if load {
+            self.load_auth_files();
+        }
+
+        ui.separator();
+        let path = users::users_path().map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        ui.label(format!("Users in {}:", path));
+        let mut action: Option<(String, UserAction)> = None;
+        egui::Grid::new("users_grid").num_columns(5).striped(true).spacing([20.0, 4.0]).show(ui, |ui| {
+            ui.strong("Username");
+            ui.strong("Roles");
+            ui.strong("Status");
+            ui.strong("Failed Logins");
+            ui.strong("");
+            ui.end_row();
+            for user in users::users() {
+                ui.label(&user.username);
+                ui.label(if user.roles.is_empty() { "-".to_string() } else { user.roles.join(", ") });
+                match users::locked_for(&user.username) {
+                    _ if user.disabled => ui.colored_label(Color32::GRAY, "Disabled"),
+                    Some(remaining) => ui.colored_label(Color32::YELLOW, format!("Locked ({}s)", remaining.as_secs() + 1)),
+                    None => ui.colored_label(Color32::GREEN, "Active"),
+                };
+                ui.label(users::failed_logins(&user.username).to_string());
+                ui.horizontal(|ui| {
+                    if ui.button("Set Password").clicked() {
+                        action = Some((user.username.clone(), UserAction::SetPassword));
+                    }
+                    if ui.button("Roles").clicked() {
+                        action = Some((user.username.clone(), UserAction::EditRoles));
+                    }
+                    let (toggle, label) = if user.disabled { (UserAction::Enable, "Enable") } else { (UserAction::Disable, "Disable") };
+                    if ui.button(label).clicked() {
+                        action = Some((user.username.clone(), toggle));
+                    }
+                    if ui.add_enabled(users::locked_for(&user.username).is_some(), egui::Button::new("Unlock")).clicked() {
+                        action = Some((user.username.clone(), UserAction::Unlock));
+                    }
+                    if ui.button("Delete").clicked() {
+                        action = Some((user.username.clone(), UserAction::Delete));
+                    }
+                });
+                ui.end_row();
+            }
+        });
+
+        if let Some((username, action)) = action {
+            match action {
+                UserAction::SetPassword => self.password_reset = Some((username, String::new())),
+                UserAction::EditRoles => {
+                    let user = users::users().into_iter().find(|user| user.username == username);
+                    let roles = user.map(|user| user.roles.join(", ")).unwrap_or_default();
+                    self.role_edit = Some((username, roles));
+                }
+                UserAction::Enable => self.user_change(users::set_disabled(&username, false), format!("Enabled user '{}'", username)),
+                UserAction::Disable => {
+                    sessions::revoke_user(&username, None);
+                    self.user_change(users::set_disabled(&username, true), format!("Disabled user '{}'", username));
+                }
+                UserAction::Unlock => {
+                    users::unlock(&username);
+                    self.add_log(&format!("Unlocked user '{}'", username));
+                }
+                UserAction::Delete => {
+                    sessions::revoke_user(&username, None);
+                    self.user_change(users::remove_user(&username), format!("Deleted user '{}'", username));
+                }
+            }
+        }
+
+        if let Some((username, password)) = self.password_reset.as_mut() {
+            let username = username.clone();
+            let mut save = false;
+            let mut cancel = false;
+            ui.horizontal(|ui| {
+                ui.label(format!("New password for '{}':", username));
+                ui.add(egui::TextEdit::singleline(password).password(true));
+                save = ui.button("Save").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+            if save {
+                let password = std::mem::take(password);
+                self.password_reset = None;
+                self.user_change(users::set_password(&username, &password), format!("Changed the password of '{}'", username));
+            } else if cancel {
+                self.password_reset = None;
+            }
+        }
+
+        if let Some((username, roles)) = self.role_edit.as_mut() {
+            let username = username.clone();
+            let mut save = false;
+            let mut cancel = false;
+            ui.horizontal(|ui| {
+                ui.label(format!("Roles of '{}':", username));
+                ui.add(egui::TextEdit::singleline(roles).hint_text("e.g. admin, editor"));
+                save = ui.button("Save").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+            if save {
+                let roles = split_list(roles);
+                self.role_edit = None;
+                self.user_change(users::set_roles(&username, roles), format!("Changed the roles of '{}'", username));
+            } else if cancel {
+                self.role_edit = None;
+            }
+        }
+
+        ui.separator();
+        ui.label("Add user:");
+        let mut add = false;
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_user.0).hint_text("username").desired_width(140.0));
+            ui.add(egui::TextEdit::singleline(&mut self.new_user.1).hint_text("password").password(true).desired_width(140.0));
+            add = ui.button("Add").clicked();
+        });
+        if add {
+            let (username, password) = std::mem::take(&mut self.new_user);
+            let result = users::add_user(&username, &password);
+            if result.is_err() {
+                self.new_user.0 = username.clone();
+            }
+            self.user_change(result, format!("Added user '{}'", username));
+        }
+
+        ui.separator();
+        self.show_sessions(ui);
+
+        ui.separator();
+        self.show_api_keys(ui);
+
+        ui.separator();
+        self.show_access_rules(ui);
+    }
+
+    // Bearer token checks for API clients
+    fn show_jwt_settings(&mut self, ui: &mut egui::Ui) {
+        let validation = &self.validation;
+        let jwt = &mut self.config.auth.jwt;
+        egui::Grid::new("jwt_grid").num_columns(2).spacing([40.0, 4.0]).striped(true).show(ui, |ui| {
+            ui.label("Enabled:");
+            let response = ui.checkbox(&mut jwt.enabled, "Require tokens on the path prefixes");
+            mark_field(ui, &response, validation, ConfigField::Jwt);
+            ui.end_row();
+
+            // Kept as typed, including empty pieces, so commas can be entered one at a time
+            ui.label("Path Prefixes:");
+            let mut prefixes = jwt.path_prefixes.join(",");
+            let response = ui.add(egui::TextEdit::singleline(&mut prefixes).hint_text("/api"));
+            if response.changed() {
+                jwt.path_prefixes = prefixes.split(',').map(|prefix| prefix.trim().to_string()).collect();
+            }
+            mark_field(ui, &response, validation, ConfigField::Jwt);
+            ui.end_row();
+
+            ui.label("Issuer:");
+            ui.add(egui::TextEdit::singleline(&mut jwt.issuer).hint_text("not checked"));
+            ui.end_row();
+
+            ui.label("Audience:");
+            ui.add(egui::TextEdit::singleline(&mut jwt.audience).hint_text("not checked"));
+            ui.end_row();
+
+            ui.label("Clock Leeway:");
+            ui.add(egui::DragValue::new(&mut jwt.leeway_secs).clamp_range(0..=600).suffix(" s"));
+            ui.end_row();
+
+            ui.label("Username Claim:");
+            ui.text_edit_singleline(&mut jwt.username_claim);
+            ui.end_row();
+
+            ui.label("Roles Claim:");
+            ui.add(egui::TextEdit::singleline(&mut jwt.roles_claim).hint_text("e.g. roles, scope, realm_access.roles"));
+            ui.end_row();
+
+            ui.label("JWKS File:");
+            let response = ui.add(egui::TextEdit::singleline(&mut jwt.jwks_path).hint_text("none"));
+            mark_field(ui, &response, validation, ConfigField::Jwt);
+            ui.end_row();
+        });
+
+        ui.label("Key files (PEM public key or certificate, the raw secret for HS256):");
+        let mut remove_key = None;
+        for (i, key) in jwt.keys.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source(("jwt_key_algorithm", i))
+                    .selected_text(key.algorithm.to_string())
+                    .show_ui(ui, |ui| {
+                        for algorithm in JwtAlgorithm::ALL {
+                            ui.selectable_value(&mut key.algorithm, algorithm, algorithm.to_string());
+                        }
+                    });
+                let response = ui.add(egui::TextEdit::singleline(&mut key.path).hint_text("public.pem").desired_width(220.0));
+                mark_field(ui, &response, validation, ConfigField::Jwt);
+                ui.add(egui::TextEdit::singleline(&mut key.kid).hint_text("any key ID").desired_width(100.0));
+                if ui.button("Remove").clicked() {
+                    remove_key = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove_key {
+            jwt.keys.remove(i);
+        }
+        if ui.button("Add Key").clicked() {
+            jwt.keys.push(JwtKeyFile::default());
+        }
+
+        show_role_map(ui, &mut jwt.role_map);
+    }
+
+    // Browser logins through an OpenID Connect provider
+    fn show_oidc_settings(&mut self, ui: &mut egui::Ui) {
+        let validation = &self.validation;
+        let oidc = &mut self.config.auth.oidc;
+        egui::Grid::new("oidc_grid").num_columns(2).spacing([40.0, 4.0]).striped(true).show(ui, |ui| {
+            ui.label("Enabled:");
+            let response = ui.checkbox(&mut oidc.enabled, "Offer single sign-on on the login page");
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Issuer:");
+            let response = ui.add(egui::TextEdit::singleline(&mut oidc.issuer).hint_text("https://idp.example.com/realms/main"));
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Client ID:");
+            let response = ui.text_edit_singleline(&mut oidc.client_id);
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Client Secret File:");
+            let response = ui.add(egui::TextEdit::singleline(&mut oidc.client_secret_path).hint_text("none, PKCE only"));
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Redirect URL:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut oidc.redirect_url).hint_text(format!("https://solara.example.com{}", oidc::CALLBACK_PATH)),
+            );
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Scopes:");
+            let mut scopes = oidc.scopes.join(" ");
+            if ui.add(egui::TextEdit::singleline(&mut scopes).hint_text("openid is always asked for")).changed() {
+                oidc.scopes = scopes.split(' ').map(str::to_string).collect();
+            }
+            ui.end_row();
+
+            ui.label("Username Claim:");
+            let response = ui.text_edit_singleline(&mut oidc.username_claim);
+            mark_field(ui, &response, validation, ConfigField::Oidc);
+            ui.end_row();
+
+            ui.label("Roles Claim:");
+            ui.add(egui::TextEdit::singleline(&mut oidc.roles_claim).hint_text("e.g. groups, realm_access.roles"));
+            ui.end_row();
+
+            ui.label("Button Label:");
+            ui.text_edit_singleline(&mut oidc.button_label);
+            ui.end_row();
+        });
+        show_role_map(ui, &mut oidc.role_map);
+    }
+
+    // Logins of the running server, newest activity first
+    fn show_sessions(&mut self, ui: &mut egui::Ui) {
+        let active = sessions::active_sessions();
+        ui.label(format!("Active sessions: {}", active.len()));
+        if active.is_empty() {
+            return;
+        }
+        let now = chrono::Local::now().timestamp().max(0) as u64;
+        let mut revoke = None;
+        egui::Grid::new("sessions_grid").num_columns(7).striped(true).spacing([16.0, 4.0]).show(ui, |ui| {
+            for header in ["Session", "User", "Client", "Started", "Last Active", "Expires In", ""] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for session in &active {
+                ui.monospace(&session.id[..12]).on_hover_text(&session.id);
+                match &session.roles {
+                    Some(roles) => ui.label(format!("{} (SSO)", session.username))
+                        .on_hover_text(format!("Single sign-on, roles: {}", roles.join(", "))),
+                    None => ui.label(&session.username),
+                };
+                ui.label(session.remote.map_or("-".to_string(), |ip| ip.to_string()))
+                    .on_hover_text(session.user_agent.as_deref().unwrap_or("No user agent"));
+                ui.label(local_time(session.created));
+                ui.label(local_time(session.last_seen));
+                ui.label(server::format_duration(session.expires.saturating_sub(now)));
+                if ui.button("Revoke").clicked() {
+                    revoke = Some((session.id.clone(), session.username.clone()));
+                }
+                ui.end_row();
+            }
+        });
+        if let Some((id, username)) = revoke {
+            if sessions::revoke(&id) {
+                self.add_log(&format!("Revoked a session of '{}'", username));
+            }
+        }
+    }
+
+    // Keys for machine clients. Only hashes are stored, so a new key is shown once.
+    fn show_api_keys(&mut self, ui: &mut egui::Ui) {
+        let validation = &self.validation;
+        let key_config = &mut self.config.auth.api_keys;
+        let mut load = false;
+        egui::Grid::new("api_key_grid").num_columns(2).spacing([40.0, 4.0]).striped(true).show(ui, |ui| {
+            ui.label("API Keys:");
+            let response = ui.checkbox(&mut key_config.enabled, "Accept API keys");
+            mark_field(ui, &response, validation, ConfigField::ApiKeys);
+            ui.end_row();
+
+            ui.label("Keys File:");
+            ui.horizontal(|ui| {
+                let response = ui.text_edit_singleline(&mut key_config.keys_file);
+                mark_field(ui, &response, validation, ConfigField::ApiKeys);
+                load = ui.button("Load").clicked();
+            });
+            ui.end_row();
+
+            ui.label("Header:");
+            let response = ui.text_edit_singleline(&mut key_config.header);
+            mark_field(ui, &response, validation, ConfigField::ApiKeys);
+            ui.end_row();
+
+            ui.label("Query Parameter:");
+            ui.add(egui::TextEdit::singleline(&mut key_config.query_param).hint_text("header only"))
+                .on_hover_text("Keys in URLs end up in access logs and browser histories");
+            ui.end_row();
+        });
+        if load {
+            self.load_auth_files();
+        }
+
+        let path = api_keys::keys_path().map_or_else(|| "-".to_string(), |path| path.display().to_string());
+        ui.label(format!("API keys in {}:", path));
+        let now = chrono::Local::now().timestamp().max(0) as u64;
+        let mut revoke = None;
+        egui::Grid::new("api_keys_grid").num_columns(9).striped(true).spacing([16.0, 4.0]).show(ui, |ui| {
+            for header in ["Name", "ID", "Paths", "Methods", "Roles", "Expires", "Requests", "Last Used", ""] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            let list = |items: &[String], all: &str| if items.is_empty() { all.to_string() } else { items.join(", ") };
+            for key in api_keys::keys() {
+                ui.label(&key.name);
+                ui.monospace(&key.id);
+                ui.label(list(&key.path_prefixes, "all"));
+                ui.label(list(&key.methods, "all"));
+                ui.label(list(&key.roles, "-"));
+                match key.expires {
+                    _ if key.is_expired(now) => ui.colored_label(Color32::GRAY, "Expired"),
+                    Some(expires) => ui.label(local_time(expires)),
+                    None => ui.label("Never"),
+                };
+                ui.label(key.requests.to_string());
+                ui.label(key.last_used.map_or_else(|| "Never".to_string(), local_time));
+                if ui.button("Revoke").clicked() {
+                    revoke = Some((key.id.clone(), key.name.clone()));
+                }
+                ui.end_row();
+            }
+        });
+        if let Some((id, name)) = revoke {
+            match api_keys::revoke(&id) {
+                Ok(()) => self.add_log(&format!("Revoked API key '{}'", name)),
+                Err(e) => self.report(Severity::Error, &format!("API key change failed: {}", e)),
+            }
+        }
+
+        let mut dismiss = false;
+        if let Some((name, key)) = &self.generated_key {
+            ui.horizontal(|ui| {
+                ui.label(format!("Key '{}', copy it now as it can't be shown again:", name));
+                ui.add(egui::TextEdit::singleline(&mut key.as_str()).font(egui::TextStyle::Monospace).desired_width(420.0));
+                if ui.button("Copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = key.clone());
+                }
+                dismiss = ui.button("Done").clicked();
+            });
+        }
+        if dismiss {
+            self.generated_key = None;
+        }
+
+        ui.label("Generate key:");
+        let mut generate = false;
+        ui.horizontal(|ui| {
+            let new_key = &mut self.new_api_key;
+            ui.add(egui::TextEdit::singleline(&mut new_key.name).hint_text("name").desired_width(120.0));
+            ui.add(egui::TextEdit::singleline(&mut new_key.path_prefixes).hint_text("all paths, or /api, ...").desired_width(150.0));
+            ui.add(egui::TextEdit::singleline(&mut new_key.methods).hint_text("all methods, or GET, ...").desired_width(150.0));
+            ui.add(egui::TextEdit::singleline(&mut new_key.roles).hint_text("roles").desired_width(100.0));
+            ui.add(egui::DragValue::new(&mut new_key.expires_days).clamp_range(0..=3650).suffix(" days"))
+                .on_hover_text("Days until the key expires, 0 never expires");
+            generate = ui.button("Generate").clicked();
+        });
+        if generate {
+            let new_key = std::mem::take(&mut self.new_api_key);
+            let expires = (new_key.expires_days > 0).then(|| now + u64::from(new_key.expires_days) * 86400);
+            let result = api_keys::generate(
+                &new_key.name,
+                split_list(&new_key.path_prefixes),
+                split_list(&new_key.methods),
+                split_list(&new_key.roles),
+                expires,
+            );
+            match result {
+                Ok(key) => {
+                    self.add_log(&format!("Generated API key '{}'", new_key.name));
+                    self.generated_key = Some((new_key.name, key));
+                }
+                Err(e) => {
+                    self.report(Severity::Error, &format!("API key change failed: {}", e));
+                    self.new_api_key = new_key;
+                }
+            }
+        }
+    }
+
+    // Rule table and the "who can access this URL" simulator
+    fn show_access_rules(&mut self, ui: &mut egui::Ui) {
+        ui.label("Access rules (the first match decides, protected paths apply where none matches):");
+        let validation = &self.validation;
+        let rules = &mut self.config.auth.rules;
+        let rule_count = rules.len();
+        let mut move_rule: Option<(usize, usize)> = None;
+        let mut remove_rule = None;
+        egui::Grid::new("rules_grid").num_columns(5).striped(true).spacing([10.0, 4.0]).show(ui, |ui| {
+            ui.strong("#");
+            ui.strong("Path");
+            ui.strong("Methods");
+            ui.strong("Roles");
+            ui.strong("");
+            ui.end_row();
+            for (i, rule) in rules.iter_mut().enumerate() {
+                ui.label(format!("{}", i + 1));
+                let response = ui.add(egui::TextEdit::singleline(&mut rule.path).hint_text("/admin/**").desired_width(180.0));
+                mark_field(ui, &response, validation, ConfigField::AccessRule(i));
+                // Kept as typed, including empty pieces, so commas can be entered one at a time
+                let mut methods = rule.methods.join(",");
+                let response = ui.add(egui::TextEdit::singleline(&mut methods).hint_text("all").desired_width(120.0));
+                if response.changed() {
+                    rule.methods = if methods.trim().is_empty() {
+                        Vec::new()
+                    } else {
+                        methods.split(',').map(|method| method.trim().to_ascii_uppercase()).collect()
+                    };
+                }
+                let mut roles = rule.roles.join(",");
+                let response = ui.add(egui::TextEdit::singleline(&mut roles).hint_text("admin, authenticated, public")
+                    .desired_width(180.0));
+                if response.changed() {
+                    rule.roles = roles.split(',').map(|role| role.trim().to_string()).filter(|role| !role.is_empty()).collect();
+                }
+                mark_field(ui, &response, validation, ConfigField::AccessRule(i));
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(i > 0, egui::Button::new("⬆")).clicked() {
+                        move_rule = Some((i, i - 1));
+                    }
+                    if ui.add_enabled(i + 1 < rule_count, egui::Button::new("⬇")).clicked() {
+                        move_rule = Some((i, i + 1));
+                    }
+                    if ui.button("Remove").clicked() {
+                        remove_rule = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        if let Some((from, to)) = move_rule {
+            rules.swap(from, to);
+        }
+        if let Some(i) = remove_rule {
+            rules.remove(i);
+        }
+        if ui.button("Add Rule").clicked() {
+            rules.push(AccessRule { path: "/".to_string(), methods: Vec::new(), roles: vec![rbac::AUTHENTICATED_ROLE.to_string()] });
+        }
+
+        ui.separator();
+        ui.label("Who can access this URL?");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("access_check_method")
+                .selected_text(self.access_check.0.clone())
+                .show_ui(ui, |ui| {
+                    for method in rbac::METHODS {
+                        ui.selectable_value(&mut self.access_check.0, method.to_string(), method);
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.access_check.1).hint_text("/path").desired_width(300.0));
+        });
+        // Uses the rules as edited, which may not be applied to the running server yet
+        let (method, path) = &self.access_check;
+        let simulation = rbac::simulate(&self.config.auth, &users::users(), method, path);
+        let decided_by = match simulation.decision {
+            rbac::Decision::Public => "No rule or protected path matches, the URL is public".to_string(),
+            rbac::Decision::LoginRequired => "No rule matches, a protected path needs any logged in user".to_string(),
+            rbac::Decision::Rule(index) => {
+                let rule = &self.config.auth.rules[index];
+                format!("Rule #{} ({} {}) allows: {}", index + 1,
+                    if rule.methods.is_empty() { "all methods".to_string() } else { rule.methods.join(",") },
+                    rule.path, rule.roles.join(", "))
+            }
+        };
+        ui.label(decided_by);
+        let access_label = |ui: &mut egui::Ui, who: &str, allowed: bool| {
+            if allowed {
+                ui.colored_label(Color32::GREEN, format!("✔ {}", who));
+            } else {
+                ui.colored_label(Color32::RED, format!("✖ {}", who));
+            }
+        };
+        ui.horizontal_wrapped(|ui| {
+            access_label(ui, "not logged in", simulation.anonymous);
+            for (username, allowed) in &simulation.users {
+                access_label(ui, username, *allowed);
+            }
+        });
+    }
     
-    // Add a method to add a log entry
-    fn add_log(&mut self, message: &str) {
-        // Add timestamp using std library instead of chrono for now
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        
-        let timestamp = format!("{:02}:{:02}:{:02}", 
-            (now / 3600) % 24, 
-            (now / 60) % 60, 
-            now % 60);
-        
-        let log_entry = format!("[{}] {}", timestamp, message);
-        
-        self.logs.push(log_entry);
-        
+    // Row of the Dashboard that switches between the server in this process and a remote one
+    fn show_target(&mut self, ui: &mut egui::Ui) {
+        if let Target::Remote(remote) = &self.target {
+            let mut disconnect = false;
+            ui.horizontal(|ui| {
+                ui.label("Target:");
+                let (color, state) = match remote.reachable() {
+                    Some(true) => (Color32::GREEN, "connected"),
+                    Some(false) => (Color32::RED, "unreachable"),
+                    None => (Color32::YELLOW, "connecting..."),
+                };
+                ui.label(remote.url());
+                ui.colored_label(color, state);
+                disconnect = ui.button("Disconnect").clicked();
+            });
+            if disconnect {
+                let url = remote.url().to_string();
+                self.switch_target(Target::Local);
+                self.add_log(&format!("Disconnected from {}", url));
+            }
+            return;
+        }
+
+        let mut connect = false;
+        egui::CollapsingHeader::new("Target: this process").id_source("target").show(ui, |ui| {
+            ui.label("Attach to a Solara server elsewhere through the admin API of one of its admin listeners.");
+            egui::Grid::new("remote_grid").num_columns(2).spacing([40.0, 4.0]).show(ui, |ui| {
+                let form = &mut self.remote_form;
+                ui.label("URL:");
+                ui.add(egui::TextEdit::singleline(&mut form.url).hint_text("https://solara.example.com:9443"));
+                ui.end_row();
+                ui.label("Username:");
+                ui.text_edit_singleline(&mut form.username);
+                ui.end_row();
+                ui.label("Password:");
+                ui.add(egui::TextEdit::singleline(&mut form.password).password(true));
+                ui.end_row();
+                ui.label("API key:");
+                ui.add(egui::TextEdit::singleline(&mut form.api_key).password(true).hint_text("instead of a password"));
+                ui.end_row();
+                ui.label("API key header:");
+                ui.add(egui::TextEdit::singleline(&mut form.api_key_header).hint_text("X-API-Key"));
+                ui.end_row();
+                ui.label("");
+                ui.checkbox(&mut form.accept_invalid_certs, "Accept invalid certificates")
+                    .on_hover_text("For admin listeners with a self-signed certificate");
+                ui.end_row();
+            });
+            connect = ui.add_enabled(!self.remote_form.url.trim().is_empty(), egui::Button::new("Connect")).clicked();
+        });
+        if connect {
+            match RemoteClient::connect(&self.remote_form, self.runtime.clone()) {
+                Ok(remote) => {
+                    self.add_log(&format!("Connecting to {}", remote.url()));
+                    self.switch_target(Target::Remote(remote));
+                }
+                Err(e) => self.report(Severity::Error, &format!("Failed to connect: {}", e)),
+            }
+        }
+    }
+
+    // The local configuration is kept while a remote one is shown, and the views start over
+    fn switch_target(&mut self, target: Target) {
+        match (&self.target, &target) {
+            (Target::Local, Target::Remote(_)) => self.local_config = Some(self.config.clone()),
+            (Target::Remote(_), Target::Local) => self.config = self.local_config.take().unwrap_or_default(),
+            _ => {}
+        }
+        self.target = target;
+        self.listener_states.clear();
+        self.server_stats = ServerStats::default();
+        self.history = MetricsHistory::new();
+        self.selected_connection = None;
+        self.selected_request = None;
+        self.validated = None;
+        self.poll_server_status();
+    }
+
+    // Headers, timing and response of the request picked in the Connection Monitor
+    fn show_request_details(&mut self, ctx: &egui::Context) {
+        let Some(request_id) = self.selected_request.clone() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Request Details")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                // Only the most recent requests are kept
+                let record = match &self.target {
+                    Target::Local => trace::find_request(&request_id),
+                    Target::Remote(remote) => remote.find_request(&request_id),
+                };
+                let Some(record) = record else {
+                    ui.label(format!("Request {} is no longer available", request_id));
+                    return;
+                };
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("request_summary").num_columns(2).striped(true).show(ui, |ui| {
+                        let time = chrono::DateTime::<chrono::Local>::from(record.started);
+                        let rows = [
+                            ("Request ID", record.request_id.clone()),
+                            ("Trace ID", record.trace_id.clone()),
+                            ("Time", time.format("%Y-%m-%d %H:%M:%S%.3f %:z").to_string()),
+                            ("Listener", record.listener.clone()),
+                            ("Client", record.remote.map_or("-".to_string(), |remote| remote.to_string())),
+                            ("User", record.user.clone().unwrap_or_else(|| "-".to_string())),
+                            ("Request", format!("{} {}", record.method, record.uri)),
+                            ("Route", record.route.clone().unwrap_or_else(|| "unmatched".to_string())),
+                            ("Status", record.status.to_string()),
+                            ("Body Sent", process::format_bytes(record.response_bytes)),
+                        ];
+                        for (label, value) in rows {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                        if record.aborted {
+                            ui.label("Note");
+                            ui.colored_label(egui::Color32::YELLOW, "Client disconnected before the body was sent");
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.separator();
+                    ui.strong("Timing");
+                    let timing = record.timing;
+                    egui::Grid::new("request_timing").num_columns(2).striped(true).show(ui, |ui| {
+                        for (label, duration) in [
+                            ("Routing", timing.routing),
+                            ("Handler", timing.handler),
+                            ("Response", timing.response),
+                            ("Body", timing.body),
+                            ("Total", timing.total),
+                        ] {
+                            ui.label(label);
+                            ui.label(format!("{:.3} ms", duration.as_secs_f64() * 1000.0));
+                            ui.end_row();
+                        }
+                    });
+
+                    for (title, headers) in [("Request Headers", &record.request_headers), ("Response Headers", &record.response_headers)] {
+                        ui.separator();
+                        ui.strong(title);
+                        egui::Grid::new(title).num_columns(2).striped(true).show(ui, |ui| {
+                            for (name, value) in headers.iter() {
+                                ui.label(name);
+                                ui.label(value);
+                                ui.end_row();
+                            }
+                        });
+                    }
+                });
+            });
+        if !open {
+            self.selected_request = None;
+        }
+    }
+
+    // Log a message, warnings and errors are raised as notifications too
+    fn report(&mut self, severity: Severity, message: &str) {
+        self.logs.push(LogEntry::new(severity, message));
         // Limit the number of logs to prevent memory issues
         if self.logs.len() > 1000 {
             self.logs.remove(0);
         }
+        if severity > Severity::Info {
+            self.notifications.notify(severity, message);
+        }
+    }
+
+    fn add_log(&mut self, message: &str) {
+        self.report(Severity::Info, message);
+    }
+
+    // Save the entries that pass the current filter, in the format of the chosen extension
+    fn export_logs_dialog(&mut self) {
+        let mut dialog = rfd::FileDialog::new().set_file_name("solara-logs.txt");
+        for format in ExportFormat::ALL {
+            dialog = dialog.add_filter(format.to_string(), &[format.extension()]);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        let format = ExportFormat::from_path(&path);
+        let entries: Vec<&LogEntry> = self.logs.iter().filter(|entry| entry.matches(&self.log_filter, self.log_level)).collect();
+        let count = entries.len();
+        match logs::export(&path, &entries, format) {
+            Ok(()) => self.add_log(&format!("Exported {} log entries to {} as {}", count, path.display(), format)),
+            Err(e) => self.report(Severity::Error, &format!("Failed to export logs: {}", e)),
+        }
     }
     
     // Add a method to update stats periodically
     fn update_stats(&mut self) {
-        // Only update if the server is running
-        if let ServerStatus::Running = self.server_status {
-            // This would eventually connect to the server to fetch actual stats
-            // For now we'll just increment the uptime
-            self.server_stats.uptime_seconds += 1;
-            
-            // In the future, this would call server::get_server_stats() 
-            // instead of manually updating
+        // Only update if the server is running, the process itself can always be sampled
+        if let Target::Remote(remote) = &self.target {
+            self.server_stats = remote.stats();
+        } else if let ServerStatus::Running = self.server_status {
+            self.server_stats = server::get_server_stats();
+        } else {
+            self.server_stats.process = process::sample();
+        }
+        self.history.record(&self.server_stats);
+        if self.server_status == ServerStatus::Running {
+            self.notifications.check_traffic(&self.config.alerts, self.history.latest());
+        }
+    }
+
+    // Validate the edited configuration if it changed since the last check. Ports are
+    // only probed while stopped, a running server holds its own ports.
+    // The configuration of a remote target is validated by the remote server when applied.
+    fn refresh_validation(&mut self) {
+        if let Target::Remote(_) = self.target {
+            self.validation = ValidationReport::default();
+            self.validated = None;
+            return;
+        }
+        let probe_ports = matches!(self.server_status, ServerStatus::Stopped | ServerStatus::Error(_));
+        if self.validated.as_ref().is_some_and(|(config, probed)| *config == self.config && *probed == probe_ports) {
+            return;
+        }
+        self.validation = config::validate_config(&self.config);
+        if probe_ports && self.validation.is_valid() {
+            self.validation.errors = config::check_ports_available(&self.config);
+        }
+        self.validated = Some((self.config.clone(), probe_ports));
+    }
+
+    // Pick up the controller's status and any messages since the last frame
+    fn poll_server_status(&mut self) {
+        for (severity, message) in self.controller.take_messages() {
+            self.report(severity, &message);
+        }
+        // Changes made through the admin API replace the edited configuration
+        if let Some(config) = self.controller.take_remote_config() {
+            match self.target {
+                Target::Local => self.config = config,
+                Target::Remote(_) => self.local_config = Some(config),
+            }
+            self.add_log("Configuration changed through the admin API");
+        }
+        let status = match &self.target {
+            Target::Local => {
+                self.listener_states = self.controller.listeners();
+                self.controller.status()
+            }
+            Target::Remote(remote) => {
+                let messages = remote.take_messages();
+                let changed = remote.take_changed_config();
+                self.listener_states = remote.listeners();
+                let status = remote.status();
+                for (severity, message) in messages {
+                    self.report(severity, &message);
+                }
+                if let Some(config) = changed {
+                    self.config = config;
+                }
+                status
+            }
+        };
+        if status != self.server_status && status == ServerStatus::Stopped {
+            self.server_stats = ServerStats::default();
+        }
+        self.server_status = status;
+    }
+
+    fn apply_config(&mut self) {
+        if let Target::Remote(remote) = &self.target {
+            remote.reload(self.config.clone());
+            return;
+        }
+        match self.controller.reload(self.config.clone()) {
+            Ok(kind) => self.add_log(&format!("Configuration {}", kind)),
+            Err(e) => self.report(Severity::Error, &format!("Failed to apply configuration: {}", e)),
+        }
+    }
+
+    fn reload_config_file(&mut self) {
+        let Some(path) = self.config_path.clone() else {
+            return;
+        };
+        match config::load_config(&path) {
+            Ok(config) => {
+                self.config = config;
+                self.add_log(&format!("Configuration re-read from {}", path.display()));
+                self.apply_config();
+            }
+            Err(e) => self.report(Severity::Error, &format!("Failed to reload configuration: {}", e)),
         }
     }
+
+    fn save_config_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("TOML", &["toml"])
+            .set_file_name("solara.toml")
+            .save_file() else {
+            return;
+        };
+        match config::save_config(&path, &self.config) {
+            Ok(()) => {
+                self.add_log(&format!("Configuration saved to {}", path.display()));
+                control_socket::set_config_file(Some(path.clone()));
+                self.config_path = Some(path);
+            }
+            Err(e) => self.report(Severity::Error, &format!("Failed to save configuration: {}", e)),
+        }
+    }
+
+    fn load_config_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("TOML", &["toml"])
+            .pick_file() else {
+            return;
+        };
+        match config::load_config(&path) {
+            Ok(config) => {
+                self.config = config;
+                self.add_log(&format!("Configuration loaded from {}", path.display()));
+                control_socket::set_config_file(Some(path.clone()));
+                self.config_path = Some(path);
+                self.load_auth_files();
+            }
+            Err(e) => self.report(Severity::Error, &format!("Failed to load configuration: {}", e)),
+        }
+    }
+}
+
+// Unix seconds as local time
+fn local_time(secs: u64) -> String {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map_or_else(|| "-".to_string(), |time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+// Comma separated list, without empty entries
+fn split_list(text: &str) -> Vec<String> {
+    text.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect()
+}
+
+// Claim value to role rows, shared by the bearer token and single sign-on settings
+fn show_role_map(ui: &mut egui::Ui, role_map: &mut Vec<RoleMapping>) {
+    ui.label("Role mapping (claim values used as roles when empty):");
+    let mut remove = None;
+    for (i, mapping) in role_map.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut mapping.claim_value).hint_text("claim value").desired_width(160.0));
+            ui.label("→");
+            ui.add(egui::TextEdit::singleline(&mut mapping.role).hint_text("role").desired_width(120.0));
+            if ui.button("Remove").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        role_map.remove(i);
+    }
+    if ui.button("Add Mapping").clicked() {
+        role_map.push(RoleMapping::default());
+    }
+}
+
+// Outline a config input that has validation errors (red) or warnings (yellow)
+// and explain the problem on hover
+fn mark_field(ui: &egui::Ui, response: &egui::Response, report: &ValidationReport, field: ConfigField) {
+    let errors: Vec<String> = report.errors_for(field).map(|e| e.to_string()).collect();
+    let warnings: Vec<String> = report.warnings_for(field).map(|w| w.to_string()).collect();
+    let color = if !errors.is_empty() {
+        egui::Color32::RED
+    } else if !warnings.is_empty() {
+        egui::Color32::YELLOW
+    } else {
+        return;
+    };
+    ui.painter().rect_stroke(response.rect.expand(2.0), 2.0, egui::Stroke::new(1.5, color));
+    response.clone().on_hover_text(errors.into_iter().chain(warnings).collect::<Vec<_>>().join("\n"));
+}
+
+// Report every problem of a configuration file without starting anything, for deploy
+// pipelines. Exits with 1 when there are errors, warnings alone pass.
+fn check_config(path: Option<PathBuf>) -> i32 {
+    let Some(path) = path else {
+        eprintln!("Usage: solara_server --check-config <path>");
+        return 2;
+    };
+    let report = config::check_config_file(&path);
+    for error in &report.errors {
+        eprintln!("{}: error: {}", error.location(), error.message);
+    }
+    for warning in &report.warnings {
+        eprintln!("{}: warning: {}", warning.location(), warning.message);
+    }
+    if report.errors.is_empty() {
+        println!("{}: configuration is valid", path.display());
+        0
+    } else {
+        eprintln!("{}: configuration test failed", path.display());
+        1
+    }
+}
+
+// Run the server without the GUI until interrupted, printing its messages. It is managed
+// through the admin API, e.g. by a GUI attached to it as a remote target.
+fn run_headless(config_path: Option<PathBuf>) -> i32 {
+    let config = match &config_path {
+        Some(path) => match config::load_config(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", path.display(), e);
+                return 2;
+            }
+        },
+        None => ServerConfig::default(),
+    };
+    if !config.admin_api.enabled && !config.control_socket.enabled {
+        eprintln!("Warning: neither the admin API nor the control socket is enabled, the server can only be stopped with a signal");
+    }
+    control_socket::set_config_file(config_path);
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+    let controller = ServerController::new(runtime.handle().clone());
+    admin_api::attach(controller.clone());
+    let print_messages = || {
+        for (severity, message) in controller.take_messages() {
+            println!("{} [{}] {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), severity, message);
+        }
+        controller.take_remote_config();
+    };
+
+    if controller.start(config).is_err() {
+        print_messages();
+        return 1;
+    }
+    let interrupted = runtime.block_on(async {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install the SIGTERM handler");
+        loop {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => return true,
+                _ = terminate.recv() => return true,
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            }
+            print_messages();
+            // Every listener failed, nothing left to manage the server with
+            if matches!(controller.status(), ServerStatus::Error(_)) {
+                return false;
+            }
+        }
+    });
+
+    controller.stop();
+    while !matches!(controller.status(), ServerStatus::Stopped | ServerStatus::Error(_)) {
+        std::thread::sleep(Duration::from_millis(100));
+        print_messages();
+    }
+    print_messages();
+    if interrupted { 0 } else { 1 }
 }
 
 fn main() -> Result<(), eframe::Error> {
+    // solara_server --headless [--config <path>]
+    // solara_server --check-config [<path>] or --check-config --config <path>
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = args.iter().position(|arg| arg == "--config").and_then(|i| args.get(i + 1)).map(PathBuf::from);
+    if let Some(index) = args.iter().position(|arg| arg == "--check-config") {
+        let path = args.get(index + 1).filter(|arg| !arg.starts_with("--")).map(PathBuf::from).or(config_path);
+        std::process::exit(check_config(path));
+    }
+    if args.iter().any(|arg| arg == "--headless") {
+        std::process::exit(run_headless(config_path));
+    }
+
     // Create the Tokio runtime *before* the GUI
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
     let handle = runtime.handle().clone(); // Get a handle to the runtime
@@ -0,0 +1,404 @@
+use rocket::data::{ByteUnit, Capped, Data};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::route::{Handler, Outcome};
+use rocket::{get, routes, Request, Response, Route};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::api_keys;
+use crate::limits;
+use crate::process;
+use crate::trace;
+use crate::upstream::{self, BackendHealth};
+
+// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Request metrics collected by the fairing, across all listeners
+static REQUEST_METRICS: Mutex<RequestMetrics> = Mutex::new(RequestMetrics {
+    requests: BTreeMap::new(),
+    latencies: BTreeMap::new(),
+});
+
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+// Where the Prometheus endpoint is served
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    // Keep /metrics off the public listeners so only the admin network can scrape it
+    pub admin_only: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { enabled: true, admin_only: false }
+    }
+}
+
+impl MetricsConfig {
+    pub fn serve_on(&self, admin: bool) -> bool {
+        self.enabled && (admin || !self.admin_only)
+    }
+}
+
+struct RequestMetrics {
+    // Keyed by route, method and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    // Keyed by route and method
+    latencies: BTreeMap<(String, String), Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+// Request counts across all routes, for the status page
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestTotals {
+    pub total: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    // Share of requests answered with a 5xx status
+    pub server_error_rate: f64,
+}
+
+pub fn request_totals() -> RequestTotals {
+    let metrics = REQUEST_METRICS.lock().unwrap();
+    let mut totals = RequestTotals::default();
+    for ((_, _, status), count) in &metrics.requests {
+        totals.total += count;
+        match status {
+            400..=499 => totals.client_errors += count,
+            500..=599 => totals.server_errors += count,
+            _ => {}
+        }
+    }
+    if totals.total > 0 {
+        totals.server_error_rate = totals.server_errors as f64 / totals.total as f64;
+    }
+    totals
+}
+
+// Latency distribution of all requests so far, the GUI derives percentiles from the
+// difference between two snapshots
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencySnapshot {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+}
+
+impl LatencySnapshot {
+    pub fn since(&self, earlier: &LatencySnapshot) -> LatencySnapshot {
+        let mut buckets = self.buckets;
+        for (bucket, earlier) in buckets.iter_mut().zip(earlier.buckets) {
+            *bucket = bucket.saturating_sub(earlier);
+        }
+        LatencySnapshot { buckets, count: self.count.saturating_sub(earlier.count) }
+    }
+
+    // Estimate a quantile in seconds by interpolating within its bucket. Requests slower
+    // than the last bucket are reported at that bucket's bound.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = q * self.count as f64;
+        let mut cumulative = 0;
+        let mut lower = 0.0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            if count > 0 && (cumulative + count) as f64 >= target {
+                let position = (target - cumulative as f64) / count as f64;
+                return Some(lower + (bound - lower) * position);
+            }
+            cumulative += count;
+            lower = *bound;
+        }
+        Some(lower)
+    }
+}
+
+pub fn latency_snapshot() -> LatencySnapshot {
+    let metrics = REQUEST_METRICS.lock().unwrap();
+    let mut snapshot = LatencySnapshot::default();
+    for histogram in metrics.latencies.values() {
+        for (total, count) in snapshot.buckets.iter_mut().zip(histogram.buckets) {
+            *total += count;
+        }
+        snapshot.count += histogram.count;
+    }
+    snapshot
+}
+
+// Records every request once its response is ready
+pub struct MetricsFairing;
+
+struct RequestStart(Instant);
+
+// Route that produced the response. Rocket keeps the last route it tried even when
+// that route forwarded, so the handlers record it themselves, along with their timing.
+struct HandledRoute(Mutex<Option<String>>);
+
+// Wrap routes so their requests are labelled with the route pattern
+pub fn track_routes(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TrackedHandler(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct TrackedHandler(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for TrackedHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let started = Instant::now();
+        let outcome = self.0.handle(req, data).await;
+        if !matches!(outcome, Outcome::Forward(_)) {
+            let handled = req.local_cache(|| HandledRoute(Mutex::new(None)));
+            *handled.0.lock().unwrap() = req.route().map(|route| route.uri.to_string());
+            trace::record_handler(req, started);
+        }
+        outcome
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info { name: "Request Metrics", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let elapsed = req.local_cache(|| RequestStart(Instant::now())).0.elapsed();
+        if let Some(size) = res.body().preset_size() {
+            BYTES_SENT.fetch_add(size as u64, Ordering::Relaxed);
+        }
+
+        // Label by route pattern rather than path to keep the number of series bounded
+        let handled = req.local_cache(|| HandledRoute(Mutex::new(None)));
+        let route = handled.0.lock().unwrap().clone().unwrap_or_else(|| "unmatched".to_string());
+        let method = req.method().as_str().to_string();
+        let mut metrics = REQUEST_METRICS.lock().unwrap();
+        *metrics.requests.entry((route.clone(), method.clone(), res.status().code)).or_insert(0) += 1;
+        metrics.latencies.entry((route, method)).or_default().observe(elapsed.as_secs_f64());
+    }
+}
+
+// Read a request body for a handler. Bodies are streamed to the handlers, so the bytes
+// received are counted here, also for chunked uploads without a Content-Length.
+pub async fn read_body(data: Data<'_>, limit: ByteUnit) -> io::Result<Capped<Vec<u8>>> {
+    let body = data.open(limit).into_bytes().await?;
+    BYTES_RECEIVED.fetch_add(body.n.written, Ordering::Relaxed);
+    Ok(body)
+}
+
+pub fn metrics_routes() -> Vec<Route> {
+    track_routes(routes![metrics])
+}
+
+#[get("/metrics")]
+fn metrics() -> (ContentType, String) {
+    let content_type = ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]);
+    (content_type, render())
+}
+
+// Render everything in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+
+    {
+        let metrics = REQUEST_METRICS.lock().unwrap();
+        header(&mut out, "solara_http_requests_total", "counter", "HTTP requests by route, method and status.");
+        for ((route, method, status), count) in &metrics.requests {
+            let _ = writeln!(out, "solara_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(route), method, status, count);
+        }
+
+        header(&mut out, "solara_http_request_duration_seconds", "histogram", "Time from receiving a request to its response being ready.");
+        for ((route, method), histogram) in &metrics.latencies {
+            let labels = format!("route=\"{}\",method=\"{}\"", escape(route), method);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "solara_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, cumulative);
+            }
+            let _ = writeln!(out, "solara_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count);
+            let _ = writeln!(out, "solara_http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum);
+            let _ = writeln!(out, "solara_http_request_duration_seconds_count{{{}}} {}", labels, histogram.count);
+        }
+    }
+
+    header(&mut out, "solara_http_received_bytes_total", "counter", "Request body bytes read from clients.");
+    let _ = writeln!(out, "solara_http_received_bytes_total {}", BYTES_RECEIVED.load(Ordering::Relaxed));
+    header(&mut out, "solara_http_sent_bytes_total", "counter", "Response body bytes sent to clients.");
+    let _ = writeln!(out, "solara_http_sent_bytes_total {}", BYTES_SENT.load(Ordering::Relaxed));
+
+    header(&mut out, "solara_in_flight_requests", "gauge", "Requests currently being handled.");
+    let _ = writeln!(out, "solara_in_flight_requests {}", limits::in_flight_requests());
+    header(&mut out, "solara_rejected_requests_total", "counter", "Requests rejected because the connection limit was reached.");
+    let _ = writeln!(out, "solara_rejected_requests_total {}", limits::rejected_requests());
+    header(&mut out, "solara_timed_out_requests_total", "counter", "Requests that exceeded the request timeout.");
+    let _ = writeln!(out, "solara_timed_out_requests_total {}", limits::timed_out_requests());
+
+    let pools = upstream::pool_stats();
+    header(&mut out, "solara_upstream_backend_up", "gauge", "Whether an upstream backend is in rotation.");
+    for pool in &pools {
+        for backend in &pool.backends {
+            let _ = writeln!(out, "solara_upstream_backend_up{{pool=\"{}\",backend=\"{}\"}} {}",
+                escape(&pool.name), escape(&backend.address), (backend.health == BackendHealth::Healthy) as u8);
+        }
+    }
+    header(&mut out, "solara_upstream_backend_requests_total", "counter", "Requests proxied to an upstream backend.");
+    for pool in &pools {
+        for backend in &pool.backends {
+            let _ = writeln!(out, "solara_upstream_backend_requests_total{{pool=\"{}\",backend=\"{}\"}} {}",
+                escape(&pool.name), escape(&backend.address), backend.total_requests);
+        }
+    }
+    header(&mut out, "solara_upstream_backend_failures_total", "counter", "Proxied requests that failed or returned a gateway error.");
+    for pool in &pools {
+        for backend in &pool.backends {
+            let _ = writeln!(out, "solara_upstream_backend_failures_total{{pool=\"{}\",backend=\"{}\"}} {}",
+                escape(&pool.name), escape(&backend.address), backend.failed_requests);
+        }
+    }
+
+    // Labelled by name and ID, since a new key may reuse a revoked key's name
+    let keys = api_keys::keys();
+    header(&mut out, "solara_api_key_requests_total", "counter", "Requests authenticated with an API key.");
+    for key in &keys {
+        let _ = writeln!(out, "solara_api_key_requests_total{{key=\"{}\",id=\"{}\"}} {}", escape(&key.name), key.id, key.requests);
+    }
+    header(&mut out, "solara_api_key_last_used_timestamp_seconds", "gauge", "Last use of an API key since unix epoch in seconds.");
+    for key in &keys {
+        if let Some(last_used) = key.last_used {
+            let _ = writeln!(out, "solara_api_key_last_used_timestamp_seconds{{key=\"{}\",id=\"{}\"}} {}", escape(&key.name), key.id, last_used);
+        }
+    }
+
+    // Standard process metrics, named like the official client libraries export them
+    if let Some(stats) = process::sample() {
+        header(&mut out, "process_cpu_seconds_total", "counter", "Total user and system CPU time spent in seconds.");
+        let _ = writeln!(out, "process_cpu_seconds_total {}", stats.cpu_seconds);
+        header(&mut out, "process_resident_memory_bytes", "gauge", "Resident memory size in bytes.");
+        let _ = writeln!(out, "process_resident_memory_bytes {}", stats.resident_memory_bytes);
+        header(&mut out, "process_virtual_memory_bytes", "gauge", "Virtual memory size in bytes.");
+        let _ = writeln!(out, "process_virtual_memory_bytes {}", stats.virtual_memory_bytes);
+        header(&mut out, "process_open_fds", "gauge", "Number of open file descriptors.");
+        let _ = writeln!(out, "process_open_fds {}", stats.open_fds);
+        header(&mut out, "process_threads", "gauge", "Number of OS threads in the process.");
+        let _ = writeln!(out, "process_threads {}", stats.threads);
+        header(&mut out, "process_start_time_seconds", "gauge", "Start time of the process since unix epoch in seconds.");
+        let _ = writeln!(out, "process_start_time_seconds {}", stats.start_time_seconds);
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// Label values may not contain raw backslashes, quotes or newlines
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = Histogram::default();
+        for seconds in [0.001, 0.005, 0.3, 20.0] {
+            histogram.observe(seconds);
+        }
+        assert_eq!(histogram.buckets, [2, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(histogram.count, 4);
+        assert!((histogram.sum - 20.306).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quantiles_interpolate_within_buckets() {
+        let mut snapshot = LatencySnapshot { count: 10, ..LatencySnapshot::default() };
+        snapshot.buckets[1] = 10;
+        assert_eq!(snapshot.quantile(0.5), Some(0.0075));
+        assert_eq!(LatencySnapshot::default().quantile(0.5), None);
+
+        let earlier = LatencySnapshot { count: 4, ..snapshot.clone() };
+        assert_eq!(snapshot.since(&snapshot).count, 0);
+        assert_eq!(snapshot.since(&earlier).count, 6);
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape("line\nbreak"), "line\\nbreak");
+    }
+
+    #[test]
+    fn renders_the_text_format() {
+        {
+            let mut metrics = REQUEST_METRICS.lock().unwrap();
+            let route = "/render-test/<id>".to_string();
+            metrics.requests.insert((route.clone(), "GET".to_string(), 200), 3);
+            let histogram = metrics.latencies.entry((route, "GET".to_string())).or_default();
+            histogram.observe(0.02);
+            histogram.observe(0.2);
+        }
+        let out = render();
+        let lines: Vec<&str> = out.lines().collect();
+        for expected in [
+            "# HELP solara_http_requests_total HTTP requests by route, method and status.",
+            "# TYPE solara_http_requests_total counter",
+            r#"solara_http_requests_total{route="/render-test/<id>",method="GET",status="200"} 3"#,
+            "# TYPE solara_http_request_duration_seconds histogram",
+            r#"solara_http_request_duration_seconds_bucket{route="/render-test/<id>",method="GET",le="0.01"} 0"#,
+            r#"solara_http_request_duration_seconds_bucket{route="/render-test/<id>",method="GET",le="0.025"} 1"#,
+            r#"solara_http_request_duration_seconds_bucket{route="/render-test/<id>",method="GET",le="0.25"} 2"#,
+            r#"solara_http_request_duration_seconds_bucket{route="/render-test/<id>",method="GET",le="+Inf"} 2"#,
+            r#"solara_http_request_duration_seconds_count{route="/render-test/<id>",method="GET"} 2"#,
+            "# TYPE solara_http_received_bytes_total counter",
+            "# TYPE solara_in_flight_requests gauge",
+        ] {
+            assert!(lines.contains(&expected), "missing line: {}", expected);
+        }
+        // Every sample follows the HELP and TYPE lines of its metric family
+        for line in lines.iter().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let family = ["_bucket", "_sum", "_count"].iter().fold(name, |name, suffix| name.strip_suffix(suffix).unwrap_or(name));
+            assert!(out.contains(&format!("# TYPE {} ", family)), "no TYPE line for {}", name);
+        }
+    }
+}
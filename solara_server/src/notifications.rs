@@ -0,0 +1,234 @@
+use eframe::egui::{self, Color32};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use crate::history::Sample;
+use crate::process;
+use crate::server::{Protocol, ServerConfig};
+
+// How long a toast stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+// Oldest notifications are dropped past this
+const MAX_NOTIFICATIONS: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "Info"),
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Error => write!(f, "Error"),
+        }
+    }
+}
+
+impl Severity {
+    pub fn color(&self) -> Color32 {
+        match self {
+            Severity::Info => Color32::LIGHT_BLUE,
+            Severity::Warning => Color32::YELLOW,
+            Severity::Error => Color32::LIGHT_RED,
+        }
+    }
+}
+
+// Limits that raise a notification when crossed, saved with the configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertThresholds {
+    // Share of 5xx responses, in percent
+    pub error_rate_percent: f64,
+    pub cert_expiry_days: u32,
+    // Free space left on the disk holding the configuration, in percent
+    pub disk_free_percent: f64,
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            error_rate_percent: 5.0,
+            cert_expiry_days: 14,
+            disk_free_percent: 10.0,
+        }
+    }
+}
+
+pub struct Notification {
+    pub id: u64,
+    pub severity: Severity,
+    pub message: String,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub acknowledged: bool,
+    shown_at: Instant,
+    toast_dismissed: bool,
+}
+
+// Notifications raised in the GUI, shown as toasts and kept until cleared
+#[derive(Default)]
+pub struct NotificationCenter {
+    notifications: Vec<Notification>,
+    next_id: u64,
+    // Threshold alerts currently firing, so each fires once until it clears
+    active_alerts: HashSet<String>,
+}
+
+impl NotificationCenter {
+    pub fn notify(&mut self, severity: Severity, message: impl Into<String>) {
+        self.notifications.push(Notification {
+            id: self.next_id,
+            severity,
+            message: message.into(),
+            time: chrono::Local::now(),
+            acknowledged: false,
+            shown_at: Instant::now(),
+            toast_dismissed: false,
+        });
+        self.next_id += 1;
+        if self.notifications.len() > MAX_NOTIFICATIONS {
+            self.notifications.remove(0);
+        }
+    }
+
+    pub fn notifications(&self) -> &[Notification] {
+        &self.notifications
+    }
+
+    pub fn unacknowledged(&self) -> usize {
+        self.notifications.iter().filter(|n| !n.acknowledged).count()
+    }
+
+    // Most severe level among the unacknowledged notifications, for the badge colour
+    pub fn highest_unacknowledged(&self) -> Option<Severity> {
+        self.notifications.iter().filter(|n| !n.acknowledged).map(|n| n.severity).max()
+    }
+
+    pub fn acknowledge(&mut self, id: u64) {
+        if let Some(notification) = self.notifications.iter_mut().find(|n| n.id == id) {
+            notification.acknowledged = true;
+            notification.toast_dismissed = true;
+        }
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for notification in &mut self.notifications {
+            notification.acknowledged = true;
+            notification.toast_dismissed = true;
+        }
+    }
+
+    pub fn clear_acknowledged(&mut self) {
+        self.notifications.retain(|n| !n.acknowledged);
+    }
+
+    // Raise `message` when `firing` turns true, and allow it again once it turned false
+    fn alert(&mut self, key: &str, firing: bool, severity: Severity, message: impl FnOnce() -> String) {
+        if !firing {
+            self.active_alerts.remove(key);
+        } else if self.active_alerts.insert(key.to_string()) {
+            self.notify(severity, message());
+        }
+    }
+
+    // Compare the latest traffic sample against the thresholds
+    pub fn check_traffic(&mut self, thresholds: &AlertThresholds, sample: Option<&Sample>) {
+        let error_rate = sample.filter(|s| s.requests_per_sec > 0.0).map_or(0.0, |s| s.error_rate);
+        self.alert("error_rate", error_rate > thresholds.error_rate_percent, Severity::Error, || {
+            format!("{:.1}% of requests failed with a server error (threshold {:.1}%)",
+                error_rate, thresholds.error_rate_percent)
+        });
+    }
+
+    // Checks that touch the file system, run less often than the traffic check
+    pub fn check_resources(&mut self, config: &ServerConfig, config_path: Option<&Path>) {
+        let thresholds = &config.alerts;
+        for listener in config.listeners.iter().filter(|l| l.protocol == Protocol::Https) {
+            let path = &listener.tls.cert_path;
+            let key = format!("cert:{}", path);
+            match certificate_expiry(Path::new(path)) {
+                Ok(expiry) => {
+                    let days_left = expiry
+                        .duration_since(SystemTime::now())
+                        .map_or(0, |left| left.as_secs() / 86_400);
+                    let firing = days_left < thresholds.cert_expiry_days as u64;
+                    let severity = if days_left == 0 { Severity::Error } else { Severity::Warning };
+                    self.alert(&key, firing, severity, || if days_left == 0 {
+                        format!("Certificate of listener '{}' has expired ({})", listener.name, path)
+                    } else {
+                        format!("Certificate of listener '{}' expires in {} day{} ({})",
+                            listener.name, days_left, if days_left == 1 { "" } else { "s" }, path)
+                    });
+                }
+                // Missing files are already reported by validation
+                Err(_) if path.is_empty() || !Path::new(path).exists() => {}
+                Err(e) => self.alert(&key, true, Severity::Warning, || {
+                    format!("Certificate of listener '{}' can't be read: {}", listener.name, e)
+                }),
+            }
+        }
+
+        let dir = config_path.and_then(Path::parent).filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if let Some(disk) = process::disk_usage(dir) {
+            let free = disk.free_percent();
+            self.alert("disk", free < thresholds.disk_free_percent, Severity::Warning, || {
+                format!("Disk holding {} is nearly full, {:.1}% free ({} left)",
+                    dir.display(), free, process::format_bytes(disk.available_bytes))
+            });
+        }
+    }
+
+    // Show recent notifications in the bottom right corner of the window
+    pub fn show_toasts(&mut self, ctx: &egui::Context) {
+        let mut dismissed = Vec::new();
+        let toasts: Vec<&Notification> = self
+            .notifications
+            .iter()
+            .filter(|n| !n.toast_dismissed && n.severity > Severity::Info && n.shown_at.elapsed() < TOAST_DURATION)
+            .collect();
+        if toasts.is_empty() {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("notification_toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for notification in toasts.iter().rev().take(4) {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_max_width(320.0);
+                        ui.horizontal(|ui| {
+                            ui.colored_label(notification.severity.color(), notification.severity.to_string());
+                            if ui.small_button("✕").clicked() {
+                                dismissed.push(notification.id);
+                            }
+                        });
+                        ui.label(&notification.message);
+                    });
+                }
+            });
+
+        for id in dismissed {
+            if let Some(notification) = self.notifications.iter_mut().find(|n| n.id == id) {
+                notification.toast_dismissed = true;
+            }
+        }
+    }
+}
+
+// When the first certificate in a PEM file stops being valid
+fn certificate_expiry(path: &Path) -> Result<SystemTime, String> {
+    let pem = fs::read(path).map_err(|e| e.to_string())?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem).map_err(|e| e.to_string())?;
+    let certificate = pem.parse_x509().map_err(|e| e.to_string())?;
+    let not_after = certificate.validity().not_after.timestamp();
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(not_after.max(0) as u64))
+}
@@ -0,0 +1,702 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rocket::http::{ContentType, Cookie, CookieJar, SameSite, Status};
+use rocket::response::Redirect;
+use rocket::route::Route;
+use rocket::time::Duration as CookieDuration;
+use rocket::{get, routes};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use crate::auth::{self, Client};
+use crate::jwt::{self, RoleMapping, TokenError, VerifyKey};
+
+// Settings of the running server, None while it is stopped
+static OIDC_CONFIG: RwLock<Option<Arc<OidcConfig>>> = RwLock::new(None);
+
+// Discovery document and keys of the provider, fetched on the first login
+static PROVIDER: RwLock<Option<Arc<Provider>>> = RwLock::new(None);
+
+// Private cookie tying the callback to the browser that started the login
+const FLOW_COOKIE: &str = "solara_oidc";
+pub const CALLBACK_PATH: &str = "/oidc/callback";
+
+// Time allowed at the provider's login page
+const FLOW_TIMEOUT_SECS: i64 = 600;
+// Discovery and keys are fetched again after this, and whenever a token names an unknown key
+const PROVIDER_TTL: Duration = Duration::from_secs(3600);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+// Clock difference tolerated for the ID token
+const LEEWAY_SECS: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    pub enabled: bool,
+    // Issuer URL, the discovery document is read from <issuer>/.well-known/openid-configuration
+    pub issuer: String,
+    pub client_id: String,
+    // File with the client secret, empty for a public client relying on PKCE alone
+    pub client_secret_path: String,
+    // Callback address registered with the provider, ending in /oidc/callback
+    pub redirect_url: String,
+    // Scopes asked for besides "openid"
+    pub scopes: Vec<String>,
+    // ID token claim used as the username
+    pub username_claim: String,
+    // Claim with the roles, as for bearer tokens
+    pub roles_claim: String,
+    // Roles granted per claim value. When empty the values are used as roles.
+    pub role_map: Vec<RoleMapping>,
+    // Text of the link on the login page
+    pub button_label: String,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret_path: String::new(),
+            redirect_url: String::new(),
+            scopes: vec!["profile".to_string(), "email".to_string()],
+            username_claim: "preferred_username".to_string(),
+            roles_claim: "groups".to_string(),
+            role_map: Vec::new(),
+            button_label: "Log in with single sign-on".to_string(),
+        }
+    }
+}
+
+// Why a single sign-on login failed
+#[derive(Debug)]
+pub enum OidcError {
+    Disabled,
+    Discovery(String),
+    // The provider turned the login down, e.g. "access_denied"
+    Denied(String),
+    // The callback does not belong to a login started in this browser
+    UnknownState,
+    TokenExchange(String),
+    IdToken(TokenError),
+    WrongNonce,
+    MissingUsername(String),
+}
+
+impl OidcError {
+    fn status(&self) -> Status {
+        match self {
+            OidcError::Disabled => Status::NotFound,
+            OidcError::Discovery(_) | OidcError::TokenExchange(_) => Status::BadGateway,
+            OidcError::Denied(_) => Status::Forbidden,
+            OidcError::UnknownState | OidcError::IdToken(_) | OidcError::WrongNonce | OidcError::MissingUsername(_) => {
+                Status::BadRequest
+            }
+        }
+    }
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Disabled => write!(f, "Single sign-on is not enabled"),
+            OidcError::Discovery(e) => write!(f, "The identity provider could not be reached: {}", e),
+            OidcError::Denied(e) => write!(f, "The identity provider refused the login: {}", e),
+            OidcError::UnknownState => write!(f, "The login expired or was started in another browser"),
+            OidcError::TokenExchange(e) => write!(f, "The identity provider did not issue tokens: {}", e),
+            OidcError::IdToken(e) => write!(f, "The ID token was refused: {}", e),
+            OidcError::WrongNonce => write!(f, "The ID token belongs to another login"),
+            OidcError::MissingUsername(claim) => write!(f, "The ID token has no '{}' claim", claim),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+struct Provider {
+    // Issuer the document was fetched for
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    keys: Vec<VerifyKey>,
+    fetched: Instant,
+}
+
+// Kept in the flow cookie between the redirect to the provider and the callback
+#[derive(Serialize, Deserialize)]
+struct Flow {
+    state: String,
+    nonce: String,
+    verifier: String,
+    next: String,
+}
+
+pub fn apply_oidc(config: &OidcConfig) {
+    let mut provider = PROVIDER.write().unwrap();
+    if provider.as_ref().is_some_and(|provider| provider.issuer != config.issuer) {
+        *provider = None;
+    }
+    *OIDC_CONFIG.write().unwrap() = Some(Arc::new(config.clone()));
+}
+
+pub fn clear_oidc() {
+    *OIDC_CONFIG.write().unwrap() = None;
+    *PROVIDER.write().unwrap() = None;
+}
+
+fn current_config() -> Option<Arc<OidcConfig>> {
+    OIDC_CONFIG.read().unwrap().clone().filter(|config| config.enabled)
+}
+
+// Label of the login page link, None while single sign-on is off
+pub fn button_label() -> Option<String> {
+    current_config().map(|config| config.button_label.clone())
+}
+
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder().timeout(HTTP_TIMEOUT).build().map_err(|e| e.to_string())
+}
+
+// The cached provider, fetched again when stale or when `refresh` is set
+async fn load_provider(config: &OidcConfig, refresh: bool) -> Result<Arc<Provider>, OidcError> {
+    if !refresh {
+        let cached = PROVIDER.read().unwrap().clone();
+        if let Some(provider) = cached.filter(|provider| provider.issuer == config.issuer && provider.fetched.elapsed() < PROVIDER_TTL) {
+            return Ok(provider);
+        }
+    }
+    let provider = Arc::new(discover(config).await.map_err(OidcError::Discovery)?);
+    *PROVIDER.write().unwrap() = Some(provider.clone());
+    Ok(provider)
+}
+
+async fn discover(config: &OidcConfig) -> Result<Provider, String> {
+    let client = http_client()?;
+    let url = format!("{}/.well-known/openid-configuration", config.issuer.trim_end_matches('/'));
+    let document: Value = fetch(&client, &url).await?.json().await.map_err(|e| format!("{}: {}", url, e))?;
+    // The document has to name the issuer it was fetched for, or tokens could come from anywhere
+    if document["issuer"].as_str() != Some(config.issuer.as_str()) {
+        return Err(format!("{} names issuer {} instead of {}", url, document["issuer"], config.issuer));
+    }
+    let endpoint = |name: &str| {
+        document[name].as_str().map(str::to_string).ok_or_else(|| format!("{} has no \"{}\"", url, name))
+    };
+    let jwks_uri = endpoint("jwks_uri")?;
+    let jwks = fetch(&client, &jwks_uri).await?.bytes().await.map_err(|e| format!("{}: {}", jwks_uri, e))?;
+    let keys = jwt::parse_jwks(&jwks).map_err(|e| format!("{}: {}", jwks_uri, e))?;
+    Ok(Provider {
+        issuer: config.issuer.clone(),
+        authorization_endpoint: endpoint("authorization_endpoint")?,
+        token_endpoint: endpoint("token_endpoint")?,
+        keys,
+        fetched: Instant::now(),
+    })
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<reqwest::Response, String> {
+    client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("{}: {}", url, e))
+}
+
+fn client_secret(config: &OidcConfig) -> Result<Option<String>, String> {
+    if config.client_secret_path.is_empty() {
+        return Ok(None);
+    }
+    let secret = fs::read_to_string(&config.client_secret_path).map_err(|e| format!("{}: {}", config.client_secret_path, e))?;
+    Ok(Some(secret.trim().to_string()))
+}
+
+// Check the settings for configuration validation, returning whether the issuer is
+// reached securely. Plain HTTP is only expected for a provider on this machine.
+pub fn check_config(config: &OidcConfig) -> Result<bool, String> {
+    let issuer = reqwest::Url::parse(&config.issuer).map_err(|e| format!("issuer '{}' is not a URL: {}", config.issuer, e))?;
+    if !matches!(issuer.scheme(), "http" | "https") {
+        return Err(format!("issuer '{}' must be an http or https URL", config.issuer));
+    }
+    if config.client_id.is_empty() {
+        return Err("no client ID is set".to_string());
+    }
+    let redirect = reqwest::Url::parse(&config.redirect_url)
+        .map_err(|e| format!("redirect URL '{}' is not a URL: {}", config.redirect_url, e))?;
+    if redirect.path() != CALLBACK_PATH {
+        return Err(format!("redirect URL '{}' must end in {}", config.redirect_url, CALLBACK_PATH));
+    }
+    if config.username_claim.is_empty() {
+        return Err("no username claim is set".to_string());
+    }
+    if client_secret(config)?.is_some_and(|secret| secret.is_empty()) {
+        return Err(format!("client secret file {} is empty", config.client_secret_path));
+    }
+    let host = issuer.host_str().unwrap_or_default().trim_matches(['[', ']']);
+    let loopback = host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    Ok(issuer.scheme() == "https" || loopback)
+}
+
+pub fn oidc_routes() -> Vec<Route> {
+    routes![oidc_login, oidc_callback]
+}
+
+// Send the browser to the provider, with PKCE and a nonce for the ID token
+#[get("/oidc/login?<next>")]
+async fn oidc_login(next: Option<String>, client: Client, cookies: &CookieJar<'_>) -> Result<Redirect, (Status, (ContentType, String))> {
+    start_login(next.as_deref(), &client, cookies).await.map_err(failure)
+}
+
+async fn start_login(next: Option<&str>, client: &Client, cookies: &CookieJar<'_>) -> Result<Redirect, OidcError> {
+    let config = current_config().ok_or(OidcError::Disabled)?;
+    let provider = load_provider(&config, false).await?;
+    let flow = Flow {
+        state: random_token(),
+        nonce: random_token(),
+        verifier: random_token(),
+        next: auth::safe_redirect(next).to_string(),
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(flow.verifier.as_bytes()));
+    let scope = std::iter::once("openid")
+        .chain(config.scopes.iter().map(String::as_str).filter(|scope| !scope.is_empty() && *scope != "openid"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let url = reqwest::Url::parse_with_params(&provider.authorization_endpoint, [
+        ("response_type", "code"),
+        ("client_id", &config.client_id),
+        ("redirect_uri", &config.redirect_url),
+        ("scope", &scope),
+        ("state", &flow.state),
+        ("nonce", &flow.nonce),
+        ("code_challenge", &challenge),
+        ("code_challenge_method", "S256"),
+    ])
+    .map_err(|e| OidcError::Discovery(format!("authorization endpoint: {}", e)))?;
+
+    // Lax, as the provider sends the browser back with a top-level GET
+    let flow = serde_json::to_string(&flow).map_err(|e| OidcError::Discovery(e.to_string()))?;
+    cookies.add_private(
+        Cookie::build((FLOW_COOKIE, flow))
+            .path("/oidc")
+            .http_only(true)
+            .secure(client.secure())
+            .same_site(SameSite::Lax)
+            .max_age(CookieDuration::seconds(FLOW_TIMEOUT_SECS)),
+    );
+    Ok(Redirect::to(url.to_string()))
+}
+
+#[get("/oidc/callback?<code>&<state>&<error>&<error_description>")]
+async fn oidc_callback(
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+    client: Client,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, (Status, (ContentType, String))> {
+    // The flow cookie is good for one attempt only
+    let flow = cookies.get_private(FLOW_COOKIE).and_then(|cookie| serde_json::from_str::<Flow>(cookie.value()).ok());
+    cookies.remove_private(Cookie::build(FLOW_COOKIE).path("/oidc"));
+    if let Some(error) = error {
+        let reason = error_description.map_or(error.clone(), |description| format!("{} ({})", error, description));
+        return Err(failure(OidcError::Denied(reason)));
+    }
+    let flow = flow.filter(|flow| state.as_deref() == Some(flow.state.as_str()));
+    let (Some(flow), Some(code)) = (flow, code) else {
+        return Err(failure(OidcError::UnknownState));
+    };
+    let (username, roles) = finish_login(&flow, &code).await.map_err(failure)?;
+    if !auth::start_session(cookies, &username, Some(roles), &client) {
+        return Err((Status::ServiceUnavailable, auth::page("Login", "<p>Logins are not available right now.</p>")));
+    }
+    Ok(Redirect::to(flow.next))
+}
+
+// Trade the code for tokens and check the ID token, returning the user and their roles
+async fn finish_login(flow: &Flow, code: &str) -> Result<(String, Vec<String>), OidcError> {
+    let config = current_config().ok_or(OidcError::Disabled)?;
+    let provider = load_provider(&config, false).await?;
+    let secret = client_secret(&config).map_err(OidcError::TokenExchange)?;
+    let client = http_client().map_err(OidcError::TokenExchange)?;
+    let mut request = client.post(&provider.token_endpoint).form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("code_verifier", &flow.verifier),
+    ]);
+    if let Some(secret) = secret {
+        request = request.basic_auth(&config.client_id, Some(secret));
+    }
+    let response = request.send().await.map_err(|e| OidcError::TokenExchange(e.to_string()))?;
+    let status = response.status();
+    let tokens: Value = response.json().await.map_err(|e| OidcError::TokenExchange(e.to_string()))?;
+    if !status.is_success() {
+        let error = tokens["error"].as_str().unwrap_or("unknown error");
+        let description = tokens["error_description"].as_str().map_or(String::new(), |description| format!(" ({})", description));
+        return Err(OidcError::TokenExchange(format!("{} {}{}", status, error, description)));
+    }
+    let id_token = tokens["id_token"].as_str().ok_or_else(|| OidcError::TokenExchange("no ID token in the response".to_string()))?;
+
+    // A key we don't know may have been rotated in since the keys were fetched
+    let claims = match jwt::verified_claims(&provider.keys, id_token) {
+        Err(TokenError::UnknownKey) => jwt::verified_claims(&load_provider(&config, true).await?.keys, id_token),
+        result => result,
+    }
+    .map_err(OidcError::IdToken)?;
+    jwt::check_lifetime(&claims, jwt::unix_now(), LEEWAY_SECS).map_err(OidcError::IdToken)?;
+    if claims["iss"].as_str() != Some(provider.issuer.as_str()) {
+        return Err(OidcError::IdToken(TokenError::WrongIssuer));
+    }
+    if !jwt::has_audience(&claims, &config.client_id)
+        || claims["azp"].as_str().is_some_and(|azp| azp != config.client_id)
+    {
+        return Err(OidcError::IdToken(TokenError::WrongAudience));
+    }
+    if claims["nonce"].as_str() != Some(flow.nonce.as_str()) {
+        return Err(OidcError::WrongNonce);
+    }
+
+    let username = jwt::claim(&claims, &config.username_claim)
+        .and_then(Value::as_str)
+        .filter(|username| !username.is_empty())
+        .ok_or_else(|| OidcError::MissingUsername(config.username_claim.clone()))?
+        .to_string();
+    Ok((username, jwt::mapped_roles(&claims, &config.roles_claim, &config.role_map)))
+}
+
+fn failure(e: OidcError) -> (Status, (ContentType, String)) {
+    eprintln!("Single sign-on failed: {}", e);
+    let body = format!("<p>{}</p>\n<p><a href=\"/login\">Back to the login page</a></p>", auth::escape_html(&e.to_string()));
+    (e.status(), auth::page("Login", &body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use rocket::local::blocking::Client as TestClient;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Mutex;
+    use std::thread;
+
+    // The configuration and provider cache are global, so the tests take turns
+    static TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    const CLIENT_ID: &str = "solara";
+    const VERIFIER: &str = "the-pkce-verifier";
+
+    #[derive(Default)]
+    struct ProviderState {
+        // Discovery names this issuer, the provider's own address unless changed
+        issuer: String,
+        keys: HashMap<String, Arc<EcdsaKeyPair>>,
+        // Key IDs in the JWKS
+        published: Vec<String>,
+        jwks_fetches: usize,
+        // Authorization codes with the PKCE challenge they were issued for and the ID token
+        codes: HashMap<String, (String, String)>,
+    }
+
+    // Discovery, JWKS and token endpoints on a local port
+    struct MockProvider {
+        url: String,
+        state: Arc<Mutex<ProviderState>>,
+    }
+
+    impl MockProvider {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let state = Arc::new(Mutex::new(ProviderState { issuer: url.clone(), ..ProviderState::default() }));
+            let thread_state = state.clone();
+            let base = url.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    serve(stream, &base, &thread_state);
+                }
+            });
+            Self { url, state }
+        }
+
+        fn add_key(&self, kid: &str, publish: bool) {
+            let rng = SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+            let mut state = self.state.lock().unwrap();
+            state.keys.insert(kid.to_string(), Arc::new(pair));
+            if publish {
+                state.published.push(kid.to_string());
+            }
+        }
+
+        fn publish_only(&self, kid: &str) {
+            self.state.lock().unwrap().published = vec![kid.to_string()];
+        }
+
+        fn jwks_fetches(&self) -> usize {
+            self.state.lock().unwrap().jwks_fetches
+        }
+
+        // Issue a code for the ID token, redeemable with the PKCE verifier
+        fn issue_code(&self, kid: &str, claims: &Value) -> String {
+            let pair = self.state.lock().unwrap().keys[kid].clone();
+            let header = json!({ "alg": "ES256", "typ": "JWT", "kid": kid });
+            let signed = format!(
+                "{}.{}",
+                URL_SAFE_NO_PAD.encode(header.to_string()),
+                URL_SAFE_NO_PAD.encode(claims.to_string())
+            );
+            let signature = pair.sign(&SystemRandom::new(), signed.as_bytes()).unwrap();
+            let id_token = format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature.as_ref()));
+            let code = random_token();
+            let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(VERIFIER.as_bytes()));
+            self.state.lock().unwrap().codes.insert(code.clone(), (challenge, id_token));
+            code
+        }
+
+        fn config(&self) -> OidcConfig {
+            OidcConfig {
+                enabled: true,
+                issuer: self.url.clone(),
+                client_id: CLIENT_ID.to_string(),
+                redirect_url: format!("https://solara.example{}", CALLBACK_PATH),
+                ..OidcConfig::default()
+            }
+        }
+
+        fn claims(&self, nonce: &str) -> Value {
+            json!({
+                "iss": self.url,
+                "aud": CLIENT_ID,
+                "sub": "1234",
+                "preferred_username": "alice",
+                "groups": ["ops"],
+                "nonce": nonce,
+                "exp": jwt::unix_now() + 300,
+            })
+        }
+    }
+
+    fn serve(mut stream: TcpStream, base: &str, state: &Mutex<ProviderState>) {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        let (head, body) = loop {
+            let read = stream.read(&mut buffer).unwrap_or(0);
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).into_owned();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse().unwrap_or(0)))
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break (head.to_string(), body.to_string());
+                }
+            }
+        };
+        let path = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+        let mut state = state.lock().unwrap();
+        let (status, response) = match path.as_str() {
+            "/.well-known/openid-configuration" => (200, json!({
+                "issuer": state.issuer,
+                "authorization_endpoint": format!("{}/authorize", base),
+                "token_endpoint": format!("{}/token", base),
+                "jwks_uri": format!("{}/jwks", base),
+            })),
+            "/jwks" => {
+                state.jwks_fetches += 1;
+                let keys: Vec<Value> = state
+                    .published
+                    .iter()
+                    .map(|kid| {
+                        let point = state.keys[kid].public_key().as_ref().to_vec();
+                        json!({
+                            "kty": "EC",
+                            "crv": "P-256",
+                            "use": "sig",
+                            "kid": kid,
+                            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+                        })
+                    })
+                    .collect();
+                (200, json!({ "keys": keys }))
+            }
+            "/token" => {
+                let form: HashMap<String, String> = reqwest::Url::parse(&format!("http://form/?{}", body))
+                    .unwrap()
+                    .query_pairs()
+                    .into_owned()
+                    .collect();
+                let challenge = form.get("code_verifier").map(|verifier| URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())));
+                match form.get("code").and_then(|code| state.codes.remove(code)) {
+                    Some((expected, id_token)) if challenge.as_ref() == Some(&expected) && form["client_id"] == CLIENT_ID => {
+                        (200, json!({ "access_token": "access", "token_type": "Bearer", "id_token": id_token }))
+                    }
+                    _ => (400, json!({ "error": "invalid_grant", "error_description": "code or verifier rejected" })),
+                }
+            }
+            _ => (404, json!({ "error": "not_found" })),
+        };
+        let body = response.to_string();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            if status == 200 { "OK" } else { "Error" },
+            body.len(),
+            body
+        );
+    }
+
+    fn flow(nonce: &str, verifier: &str) -> Flow {
+        Flow { state: "state".to_string(), nonce: nonce.to_string(), verifier: verifier.to_string(), next: "/".to_string() }
+    }
+
+    fn setup() -> MockProvider {
+        let provider = MockProvider::start();
+        provider.add_key("one", true);
+        apply_oidc(&provider.config());
+        provider
+    }
+
+    #[tokio::test]
+    async fn login_completes_with_a_valid_id_token() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        let mut claims = provider.claims("nonce");
+        claims["aud"] = json!(["other", CLIENT_ID]);
+        claims["azp"] = json!(CLIENT_ID);
+        let code = provider.issue_code("one", &claims);
+        let (username, roles) = finish_login(&flow("nonce", VERIFIER), &code).await.unwrap();
+        assert_eq!(username, "alice");
+        assert_eq!(roles, vec!["ops"]);
+        clear_oidc();
+    }
+
+    #[tokio::test]
+    async fn provider_refuses_the_wrong_pkce_verifier() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        let code = provider.issue_code("one", &provider.claims("nonce"));
+        let result = finish_login(&flow("nonce", "another-verifier"), &code).await;
+        assert!(matches!(result, Err(OidcError::TokenExchange(e)) if e.contains("invalid_grant")));
+        clear_oidc();
+    }
+
+    #[tokio::test]
+    async fn nonce_must_match_the_flow() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        let code = provider.issue_code("one", &provider.claims("other-nonce"));
+        assert!(matches!(finish_login(&flow("nonce", VERIFIER), &code).await, Err(OidcError::WrongNonce)));
+        clear_oidc();
+    }
+
+    #[tokio::test]
+    async fn issuer_audience_and_azp_are_checked() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        let cases = [
+            ("iss", json!("https://evil.example"), TokenError::WrongIssuer),
+            ("aud", json!("another-client"), TokenError::WrongAudience),
+            ("azp", json!("another-client"), TokenError::WrongAudience),
+            ("exp", json!(jwt::unix_now() - 3600), TokenError::Expired),
+        ];
+        for (name, value, expected) in cases {
+            let mut claims = provider.claims("nonce");
+            claims[name] = value;
+            let code = provider.issue_code("one", &claims);
+            match finish_login(&flow("nonce", VERIFIER), &code).await {
+                Err(OidcError::IdToken(e)) => assert_eq!(e, expected, "{}", name),
+                other => panic!("{}: {:?}", name, other),
+            }
+        }
+        clear_oidc();
+    }
+
+    #[tokio::test]
+    async fn rotated_keys_are_fetched_again() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        let code = provider.issue_code("one", &provider.claims("nonce"));
+        finish_login(&flow("nonce", VERIFIER), &code).await.unwrap();
+        assert_eq!(provider.jwks_fetches(), 1);
+
+        // Known keys come from the cache
+        let code = provider.issue_code("one", &provider.claims("nonce"));
+        finish_login(&flow("nonce", VERIFIER), &code).await.unwrap();
+        assert_eq!(provider.jwks_fetches(), 1);
+
+        provider.add_key("two", false);
+        provider.publish_only("two");
+        let code = provider.issue_code("two", &provider.claims("nonce"));
+        finish_login(&flow("nonce", VERIFIER), &code).await.unwrap();
+        assert_eq!(provider.jwks_fetches(), 2);
+
+        // A key the provider never published stays unknown after fetching again
+        provider.add_key("three", false);
+        let code = provider.issue_code("three", &provider.claims("nonce"));
+        let result = finish_login(&flow("nonce", VERIFIER), &code).await;
+        assert!(matches!(result, Err(OidcError::IdToken(TokenError::UnknownKey))));
+        assert_eq!(provider.jwks_fetches(), 3);
+        clear_oidc();
+    }
+
+    #[tokio::test]
+    async fn discovery_must_name_the_configured_issuer() {
+        let _lock = TEST_LOCK.lock().await;
+        let provider = setup();
+        provider.state.lock().unwrap().issuer = "https://evil.example".to_string();
+        let code = provider.issue_code("one", &provider.claims("nonce"));
+        let result = finish_login(&flow("nonce", VERIFIER), &code).await;
+        assert!(matches!(result, Err(OidcError::Discovery(_))));
+        clear_oidc();
+    }
+
+    #[test]
+    fn callback_state_must_match_the_login() {
+        let _lock = TEST_LOCK.blocking_lock();
+        let provider = setup();
+        let client = TestClient::tracked(rocket::build().mount("/", oidc_routes())).unwrap();
+
+        let response = client.get("/oidc/login?next=/admin").dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = reqwest::Url::parse(response.headers().get_one("Location").unwrap()).unwrap();
+        assert_eq!(location.as_str().split('?').next(), Some(format!("{}/authorize", provider.url).as_str()));
+        let params: HashMap<String, String> = location.query_pairs().into_owned().collect();
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["scope"], "openid profile email");
+        assert!(!params["state"].is_empty() && !params["nonce"].is_empty() && !params["code_challenge"].is_empty());
+
+        // A mismatched state ends the flow, so the real state is refused afterwards too
+        let response = client.get("/oidc/callback?code=abc&state=forged").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get(format!("/oidc/callback?code=abc&state={}", params["state"])).dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client.get("/oidc/callback?error=access_denied").dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        clear_oidc();
+    }
+}
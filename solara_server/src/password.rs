@@ -0,0 +1,128 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+// Password hashing with Argon2id (RFC 9106), stored as PHC strings like
+// "$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>"
+
+// OWASP's recommended minimum: 19 MiB of memory, two passes, one lane
+const MEMORY_KIB: u32 = 19 * 1024;
+const PASSES: u32 = 2;
+const LANES: u32 = 1;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+// Stored hashes come from a file, don't let one make a login allocate gigabytes
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_PASSES: u32 = 16;
+const MAX_LANES: u32 = 16;
+
+// Hash a password with a fresh random salt
+pub fn hash_password(password: &str) -> String {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let salt = SaltString::encode_b64(&salt).expect("salt length is valid");
+    let params = Params::new(MEMORY_KIB, PASSES, LANES, Some(HASH_LEN)).expect("parameters are valid");
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.as_bytes(), &salt)
+        .expect("hashing with valid parameters succeeds")
+        .to_string()
+}
+
+// Check a password against a PHC string, with the parameters stored in it
+pub fn verify_password(password: &str, phc: &str) -> bool {
+    let Some(hash) = parse_phc(phc) else {
+        return false;
+    };
+    Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()
+}
+
+// Whether a stored hash can be read, for validating user files
+pub fn is_valid_hash(phc: &str) -> bool {
+    parse_phc(phc).is_some()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Only Argon2id version 19 with parameters within bounds is accepted
+fn parse_phc(phc: &str) -> Option<PasswordHash<'_>> {
+    let hash = PasswordHash::new(phc).ok()?;
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into()) {
+        return None;
+    }
+    let params = Params::try_from(&hash).ok()?;
+    let valid = params.m_cost() <= MAX_MEMORY_KIB
+        && params.t_cost() <= MAX_PASSES
+        && params.p_cost() <= MAX_LANES
+        && hash.salt.is_some_and(|salt| salt.decode_b64(&mut [0; 64]).is_ok_and(|salt| salt.len() >= 8))
+        && hash.hash.is_some_and(|output| (4..=64).contains(&output.len()));
+    valid.then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_9106_argon2id_vector() {
+        // Section 5.3: password, salt, secret and associated data of repeated bytes
+        let params = argon2::ParamsBuilder::new()
+            .m_cost(32)
+            .t_cost(3)
+            .p_cost(4)
+            .data(argon2::AssociatedData::new(&[4; 12]).unwrap())
+            .output_len(32)
+            .build()
+            .unwrap();
+        let argon2 = Argon2::new_with_secret(&[3; 8], Algorithm::Argon2id, Version::V0x13, params).unwrap();
+        let mut tag = [0u8; 32];
+        argon2.hash_password_into(&[1; 32], &[2; 16], &mut tag).unwrap();
+        assert_eq!(tag, [
+            0x0d, 0x64, 0x0d, 0xf5, 0x8d, 0x78, 0x76, 0x6c, 0x08, 0xc0, 0x37, 0xa3, 0x4a, 0x8b, 0x53, 0xc9,
+            0xd0, 0x1e, 0xf0, 0x45, 0x2d, 0x75, 0xb6, 0x5e, 0xb5, 0x25, 0x20, 0xe9, 0x6b, 0x01, 0xe6, 0x59,
+        ]);
+    }
+
+    #[test]
+    fn hash_and_verify() {
+        let hash = hash_password("correct horse battery staple");
+        assert!(hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+        assert!(is_valid_hash(&hash));
+        assert!(verify_password("correct horse battery staple", &hash));
+        assert!(!verify_password("correct horse battery stapler", &hash));
+        // Every hash gets its own salt
+        assert_ne!(hash, hash_password("correct horse battery staple"));
+    }
+
+    #[test]
+    fn hashes_in_existing_user_files_still_verify() {
+        let stored = "$argon2id$v=19$m=19456,t=2,p=1$7HH6vwyiW6ZeHi0yX+3yYQ$SWVkkcctQgCMlHB3HSOaCZ4TtRrGdQ9U8ONz/17FxQw";
+        assert!(verify_password("correct horse battery staple", stored));
+        assert!(!verify_password("Correct horse battery staple", stored));
+    }
+
+    #[test]
+    fn rejects_other_algorithms_and_excessive_parameters() {
+        let salt_and_hash = "$7HH6vwyiW6ZeHi0yX+3yYQ$SWVkkcctQgCMlHB3HSOaCZ4TtRrGdQ9U8ONz/17FxQw";
+        for phc in [
+            format!("$argon2i$v=19$m=19456,t=2,p=1{}", salt_and_hash),
+            format!("$argon2id$v=16$m=19456,t=2,p=1{}", salt_and_hash),
+            format!("$argon2id$v=19$m=4194304,t=2,p=1{}", salt_and_hash),
+            format!("$argon2id$v=19$m=19456,t=100,p=1{}", salt_and_hash),
+            format!("$argon2id$v=19$m=19456,t=2,p=1,x=1{}", salt_and_hash),
+            "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$SWVkkcctQgCMlHB3HSOaCZ4TtRrGdQ9U8ONz/17FxQw".to_string(),
+            "$argon2id$v=19$m=19456,t=2,p=1$7HH6vwyiW6ZeHi0yX+3yYQ".to_string(),
+            "plaintext".to_string(),
+        ] {
+            assert!(!is_valid_hash(&phc), "{}", phc);
+            assert!(!verify_password("correct horse battery staple", &phc), "{}", phc);
+        }
+    }
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+}
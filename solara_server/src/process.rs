@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Previous CPU reading, to turn the CPU time into a usage percentage
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+static LAST_CPU_SAMPLE: Mutex<Option<CpuSample>> = Mutex::new(None);
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct CpuSample {
+    at: Instant,
+    cpu_seconds: f64,
+    percent: f64,
+}
+
+// Resource usage of the server process
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessStats {
+    pub cpu_seconds: f64,
+    // Usage since the previous sample, 100 is one fully busy core
+    pub cpu_percent: f64,
+    pub resident_memory_bytes: u64,
+    pub virtual_memory_bytes: u64,
+    // Physical memory of the machine, 0 when unknown
+    pub system_memory_bytes: u64,
+    pub open_fds: usize,
+    pub threads: usize,
+    // Unix timestamp of when the process started
+    pub start_time_seconds: u64,
+}
+
+// Read the current usage from /proc/self. Returns None where procfs is not available.
+#[cfg(target_os = "linux")]
+pub fn sample() -> Option<ProcessStats> {
+    use std::fs;
+
+    // The command name is in parentheses and may contain spaces, so split after it
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    // Indexes are the field numbers from proc(5) minus three
+    let field = |n: usize| fields.get(n - 3).and_then(|value| value.parse::<u64>().ok());
+
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+
+    let boot_time = fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime ")?.trim().parse::<u64>().ok())?;
+
+    // Reported in kB
+    let system_memory_bytes = fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| {
+            meminfo.lines().find_map(|line| line.strip_prefix("MemTotal:")?.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        })
+        .map_or(0, |kb| kb * 1024);
+
+    let cpu_seconds = (field(14)? + field(15)?) as f64 / ticks;
+    Some(ProcessStats {
+        cpu_seconds,
+        cpu_percent: cpu_percent(cpu_seconds),
+        resident_memory_bytes: field(24)? * page_size,
+        virtual_memory_bytes: field(23)?,
+        system_memory_bytes,
+        open_fds: fs::read_dir("/proc/self/fd").map(|dir| dir.count()).unwrap_or(0),
+        threads: field(20)? as usize,
+        start_time_seconds: boot_time + (field(22)? as f64 / ticks) as u64,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn sample() -> Option<ProcessStats> {
+    None
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn cpu_percent(cpu_seconds: f64) -> f64 {
+    let mut last = LAST_CPU_SAMPLE.lock().unwrap();
+    let now = Instant::now();
+    let percent = match last.as_ref() {
+        // Readings taken close together are too coarse, repeat the previous value
+        Some(previous) if now.duration_since(previous.at) < Duration::from_millis(500) => return previous.percent,
+        Some(previous) => {
+            (cpu_seconds - previous.cpu_seconds).max(0.0) / now.duration_since(previous.at).as_secs_f64() * 100.0
+        }
+        None => 0.0,
+    };
+    *last = Some(CpuSample { at: now, cpu_seconds, percent });
+    percent
+}
+
+// Size of the file system holding a path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    // Space available to unprivileged users
+    pub available_bytes: u64,
+}
+
+impl DiskUsage {
+    pub fn free_percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 100.0;
+        }
+        self.available_bytes as f64 / self.total_bytes as f64 * 100.0
+    }
+}
+
+#[cfg(unix)]
+pub fn disk_usage(path: &Path) -> Option<DiskUsage> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block_size = stat.f_frsize as u64;
+    Some(DiskUsage {
+        total_bytes: stat.f_blocks as u64 * block_size,
+        available_bytes: stat.f_bavail as u64 * block_size,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn disk_usage(_path: &Path) -> Option<DiskUsage> {
+    None
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,230 @@
+use rocket::http::uri::Origin;
+use rocket::http::Method;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::auth::{self, AuthConfig};
+use crate::users::{self, User};
+
+// Roles with a meaning of their own in rules
+pub const PUBLIC_ROLE: &str = "public";
+pub const AUTHENTICATED_ROLE: &str = "authenticated";
+
+pub const METHODS: [&str; 7] = ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"];
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessRule {
+    // Exact path or a pattern like "/admin/**" or "/api/*/items". "*" stays within one
+    // path segment, "**" spans segments, and a trailing "/**" also covers the bare prefix.
+    pub path: String,
+    // Methods the rule applies to, empty for all
+    pub methods: Vec<String>,
+    // Roles let through. "public" lets everyone in, "authenticated" any logged in user.
+    pub roles: Vec<String>,
+}
+
+impl AccessRule {
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        let method_matches = self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method));
+        method_matches && path_matches(&self.path, path)
+    }
+
+    // Whether a caller with these roles may pass, None for someone not logged in
+    pub fn allows(&self, user_roles: Option<&[String]>) -> bool {
+        self.roles.iter().any(|role| match role.as_str() {
+            PUBLIC_ROLE => true,
+            AUTHENTICATED_ROLE => user_roles.is_some(),
+            role => user_roles.is_some_and(|roles| roles.iter().any(|held| held == role)),
+        })
+    }
+}
+
+// Check a rule, for configuration validation
+pub fn check_rule(rule: &AccessRule) -> Result<(), String> {
+    if !rule.path.starts_with('/') {
+        return Err(format!("path '{}' must start with '/'", rule.path));
+    }
+    if let Some(method) = rule.methods.iter().find(|method| Method::from_str(&method.to_ascii_uppercase()).is_err()) {
+        return Err(format!("'{}' is not an HTTP method", method));
+    }
+    if rule.roles.is_empty() {
+        return Err("no roles are allowed, the rule would lock everyone out".to_string());
+    }
+    if let Some(role) = rule.roles.iter().find(|role| !users::valid_role(role)) {
+        return Err(format!("'{}' is not a valid role name", role));
+    }
+    Ok(())
+}
+
+// What a request needs before its handler runs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Public,
+    // Under a protected path without a rule, any logged in user
+    LoginRequired,
+    // Decided by the rule at this index
+    Rule(usize),
+}
+
+// The first rule matching the method and path decides, protected paths cover the rest
+pub fn decide(config: &AuthConfig, method: &str, path: &str) -> Decision {
+    match config.rules.iter().position(|rule| rule.matches(method, path)) {
+        Some(index) => Decision::Rule(index),
+        None if config.is_protected(path) => Decision::LoginRequired,
+        None => Decision::Public,
+    }
+}
+
+pub fn allows(config: &AuthConfig, decision: Decision, user_roles: Option<&[String]>) -> bool {
+    match decision {
+        Decision::Public => true,
+        Decision::LoginRequired => user_roles.is_some(),
+        Decision::Rule(index) => config.rules.get(index).is_some_and(|rule| rule.allows(user_roles)),
+    }
+}
+
+// Answer of the "who can access this URL" simulator
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub decision: Decision,
+    pub anonymous: bool,
+    // Every user and whether they get through. Disabled users never do.
+    pub users: Vec<(String, bool)>,
+}
+
+pub fn simulate(config: &AuthConfig, users: &[User], method: &str, path: &str) -> Simulation {
+    // Paths are normalized and query strings don't take part in matching, as for real requests
+    let path = match Origin::parse(path) {
+        Ok(origin) => auth::normalized_path(origin.path()),
+        Err(_) => path.split(['?', '#']).next().unwrap_or_default().to_string(),
+    };
+    let decision = decide(config, method, &path);
+    Simulation {
+        decision,
+        anonymous: allows(config, decision, None),
+        users: users
+            .iter()
+            .map(|user| (user.username.clone(), !user.disabled && allows(config, decision, Some(&user.roles))))
+            .collect(),
+    }
+}
+
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix("/**") {
+        if path == prefix || (prefix.is_empty() && path == "/") {
+            return true;
+        }
+    }
+    glob(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob(rest, &path[i..])),
+        [c, rest @ ..] => path.first() == Some(c) && glob(rest, &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str, methods: &[&str], roles: &[&str]) -> AccessRule {
+        AccessRule {
+            path: path.to_string(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn roles(roles: &[&str]) -> Vec<String> {
+        roles.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn exact_paths() {
+        assert!(path_matches("/admin", "/admin"));
+        assert!(!path_matches("/admin", "/admin/"));
+        assert!(!path_matches("/admin", "/admins"));
+    }
+
+    #[test]
+    fn single_star_stays_within_a_segment() {
+        assert!(path_matches("/api/*/items", "/api/v1/items"));
+        assert!(path_matches("/api/*/items", "/api//items"));
+        assert!(!path_matches("/api/*/items", "/api/v1/x/items"));
+        assert!(path_matches("/files/*.txt", "/files/notes.txt"));
+        assert!(!path_matches("/files/*.txt", "/files/a/notes.txt"));
+        assert!(path_matches("/api/*", "/api/"));
+        assert!(!path_matches("/api/*", "/api"));
+    }
+
+    #[test]
+    fn double_star_spans_segments() {
+        assert!(path_matches("/admin/**", "/admin/users/1"));
+        assert!(path_matches("/admin/**", "/admin/"));
+        // A trailing "/**" also covers the bare prefix
+        assert!(path_matches("/admin/**", "/admin"));
+        assert!(!path_matches("/admin/**", "/administrator"));
+        assert!(path_matches("/**", "/"));
+        assert!(path_matches("/**", "/anything/at/all"));
+        assert!(path_matches("/**/edit", "/docs/a/b/edit"));
+        assert!(!path_matches("/**/edit", "/docs/a/b/editor"));
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let config = AuthConfig {
+            rules: vec![
+                rule("/admin/login", &["GET"], &[PUBLIC_ROLE]),
+                rule("/admin/**", &[], &["admin"]),
+                rule("/reports/**", &["get"], &[AUTHENTICATED_ROLE]),
+            ],
+            protected_paths: vec!["/private".to_string()],
+            ..AuthConfig::default()
+        };
+        assert_eq!(decide(&config, "GET", "/admin/login"), Decision::Rule(0));
+        assert_eq!(decide(&config, "POST", "/admin/login"), Decision::Rule(1));
+        assert_eq!(decide(&config, "GET", "/reports/q3"), Decision::Rule(2));
+        assert_eq!(decide(&config, "DELETE", "/reports/q3"), Decision::Public);
+        assert_eq!(decide(&config, "GET", "/private/file"), Decision::LoginRequired);
+        assert_eq!(decide(&config, "GET", "/privateer"), Decision::Public);
+
+        let admin = roles(&["admin"]);
+        let editor = roles(&["editor"]);
+        assert!(allows(&config, Decision::Rule(0), None));
+        assert!(!allows(&config, Decision::Rule(1), None));
+        assert!(!allows(&config, Decision::Rule(1), Some(&editor)));
+        assert!(allows(&config, Decision::Rule(1), Some(&admin)));
+        assert!(allows(&config, Decision::Rule(2), Some(&[])));
+        assert!(!allows(&config, Decision::LoginRequired, None));
+        assert!(allows(&config, Decision::Public, None));
+    }
+
+    #[test]
+    fn simulation_sees_paths_like_requests() {
+        let config = AuthConfig { rules: vec![rule("/proxy/admin/**", &[], &["admin"])], ..AuthConfig::default() };
+        let users = vec![
+            User { username: "ann".to_string(), roles: roles(&["admin"]), ..User::default() },
+            User { username: "bob".to_string(), roles: roles(&["admin"]), disabled: true, ..User::default() },
+        ];
+        for path in ["/proxy/admin/x", "/proxy/%61dmin/x", "/proxy//admin/x", "/proxy/x/../admin/x?debug=1"] {
+            let simulation = simulate(&config, &users, "GET", path);
+            assert_eq!(simulation.decision, Decision::Rule(0), "{}", path);
+            assert!(!simulation.anonymous);
+            assert_eq!(simulation.users, [("ann".to_string(), true), ("bob".to_string(), false)]);
+        }
+    }
+
+    #[test]
+    fn rules_are_checked() {
+        assert!(check_rule(&rule("/admin/**", &["get", "POST"], &["admin"])).is_ok());
+        assert!(check_rule(&rule("admin", &[], &["admin"])).is_err());
+        assert!(check_rule(&rule("/admin", &["FETCH"], &["admin"])).is_err());
+        assert!(check_rule(&rule("/admin", &[], &[])).is_err());
+    }
+}
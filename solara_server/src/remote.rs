@@ -0,0 +1,358 @@
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use crate::connections::ConnectionInfo;
+use crate::control::ListenerState;
+use crate::notifications::Severity;
+use crate::server::{ListenerConfig, ListenerSummary, ServerConfig, ServerStats, ServerStatus};
+use crate::trace::RequestRecord;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connection form of the GUI
+#[derive(Clone, Debug, Default)]
+pub struct RemoteSettings {
+    // Base URL of an admin listener, e.g. "https://solara.example.com:9443"
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    // Used instead of the username and password when set
+    pub api_key: String,
+    pub api_key_header: String,
+    // For admin listeners with a self-signed certificate
+    pub accept_invalid_certs: bool,
+}
+
+// A Solara instance elsewhere, polled through its admin API. Dropping the client stops the polling.
+pub struct RemoteClient {
+    api: Api,
+    runtime: Handle,
+    state: Arc<Mutex<RemoteState>>,
+    _stop: oneshot::Sender<()>,
+}
+
+struct RemoteState {
+    // None until the first poll has been answered
+    reachable: Option<bool>,
+    status: ServerStatus,
+    listeners: Vec<ListenerState>,
+    stats: ServerStats,
+    config: Option<ServerConfig>,
+    // Configuration that changed on the remote side, picked up by the GUI
+    changed_config: Option<ServerConfig>,
+    connections: Vec<ConnectionInfo>,
+    requests: Vec<Arc<RequestRecord>>,
+    // Sequence number of the last server message fetched
+    log_seq: u64,
+    messages: Vec<(Severity, String)>,
+}
+
+#[derive(Clone)]
+struct Api {
+    http: reqwest::Client,
+    base: String,
+    settings: RemoteSettings,
+}
+
+#[derive(Deserialize)]
+struct StatusReply {
+    status: String,
+    listeners: Vec<ListenerReply>,
+}
+
+#[derive(Deserialize)]
+struct ListenerReply {
+    #[serde(flatten)]
+    summary: ListenerSummary,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct LogsReply {
+    entries: Vec<LogReply>,
+}
+
+#[derive(Deserialize)]
+struct LogReply {
+    seq: u64,
+    level: String,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ConnectionsReply {
+    connections: Vec<ConnectionInfo>,
+}
+
+#[derive(Deserialize)]
+struct RequestsReply {
+    requests: Vec<RequestRecord>,
+}
+
+impl Api {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let builder = self.http.request(method, format!("{}{}", self.base, path));
+        if !self.settings.api_key.is_empty() {
+            builder.header(self.settings.api_key_header.as_str(), self.settings.api_key.as_str())
+        } else if !self.settings.username.is_empty() {
+            builder.basic_auth(&self.settings.username, Some(&self.settings.password))
+        } else {
+            builder
+        }
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<Value, String> {
+        let response = builder.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body: Value = if status == StatusCode::NO_CONTENT {
+            Value::Null
+        } else {
+            response.json().await.map_err(|e| format!("{}: {}", status, e))?
+        };
+        if !status.is_success() {
+            return Err(error_message(status, &body));
+        }
+        Ok(body)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let body = self.send(self.request(Method::GET, path)).await?;
+        serde_json::from_value(body).map_err(|e| format!("unexpected answer from {}: {}", path, e))
+    }
+}
+
+// The API answers {"error": ...}, or a validation report with a list of errors
+fn error_message(status: StatusCode, body: &Value) -> String {
+    if let Some(error) = body["error"].as_str() {
+        return error.to_string();
+    }
+    match body["errors"].as_array() {
+        Some(errors) => errors.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("; "),
+        None => status.to_string(),
+    }
+}
+
+// Statuses come in their display form
+fn parse_status(text: &str) -> ServerStatus {
+    match text {
+        "Stopped" => ServerStatus::Stopped,
+        "Starting..." => ServerStatus::Starting,
+        "Running" => ServerStatus::Running,
+        "Stopping..." => ServerStatus::Stopping,
+        other => ServerStatus::Error(other.strip_prefix("Error: ").unwrap_or(other).to_string()),
+    }
+}
+
+fn parse_severity(level: &str) -> Severity {
+    match level {
+        "Error" => Severity::Error,
+        "Warning" => Severity::Warning,
+        _ => Severity::Info,
+    }
+}
+
+impl RemoteClient {
+    pub fn connect(settings: &RemoteSettings, runtime: Handle) -> Result<Self, String> {
+        let base = settings.url.trim().trim_end_matches('/').to_string();
+        if !base.starts_with("http://") && !base.starts_with("https://") {
+            return Err("the URL has to start with http:// or https://".to_string());
+        }
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .danger_accept_invalid_certs(settings.accept_invalid_certs)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let mut settings = settings.clone();
+        if settings.api_key_header.trim().is_empty() {
+            settings.api_key_header = "X-API-Key".to_string();
+        }
+        let api = Api { http, base, settings };
+        let state = Arc::new(Mutex::new(RemoteState {
+            reachable: None,
+            status: ServerStatus::Stopped,
+            listeners: Vec::new(),
+            stats: ServerStats::default(),
+            config: None,
+            changed_config: None,
+            connections: Vec::new(),
+            requests: Vec::new(),
+            log_seq: 0,
+            messages: Vec::new(),
+        }));
+
+        let (stop, mut stopped) = oneshot::channel::<()>();
+        let poll_api = api.clone();
+        let poll_state = state.clone();
+        runtime.spawn(async move {
+            loop {
+                poll(&poll_api, &poll_state).await;
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        });
+        Ok(Self { api, runtime, state, _stop: stop })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.api.base
+    }
+
+    pub fn reachable(&self) -> Option<bool> {
+        self.state.lock().unwrap().reachable
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        self.state.lock().unwrap().status.clone()
+    }
+
+    pub fn listeners(&self) -> Vec<ListenerState> {
+        self.state.lock().unwrap().listeners.clone()
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.state.lock().unwrap().connections.clone()
+    }
+
+    pub fn recent_requests(&self) -> Vec<Arc<RequestRecord>> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn find_request(&self, request_id: &str) -> Option<Arc<RequestRecord>> {
+        self.state.lock().unwrap().requests.iter().find(|record| record.request_id == request_id).cloned()
+    }
+
+    pub fn take_messages(&self) -> Vec<(Severity, String)> {
+        std::mem::take(&mut self.state.lock().unwrap().messages)
+    }
+
+    pub fn take_changed_config(&self) -> Option<ServerConfig> {
+        self.state.lock().unwrap().changed_config.take()
+    }
+
+    pub fn start(&self) {
+        self.perform(self.api.request(Method::POST, "/api/server/start"), None);
+    }
+
+    pub fn stop(&self) {
+        self.perform(self.api.request(Method::POST, "/api/server/stop"), None);
+    }
+
+    pub fn reload(&self, config: ServerConfig) {
+        let request = self.api.request(Method::PUT, "/api/config").json(&config);
+        self.perform(request, Some(config));
+    }
+
+    pub fn close_connection(&self, id: u64) {
+        self.perform(self.api.request(Method::POST, &format!("/api/connections/{}/close", id)), None);
+    }
+
+    // Send an action in the background. The server logs what it did, so only failures are
+    // reported here. An applied configuration is remembered so the next poll doesn't offer it back.
+    fn perform(&self, request: RequestBuilder, applied: Option<ServerConfig>) {
+        let api = self.api.clone();
+        let state = self.state.clone();
+        self.runtime.spawn(async move {
+            let result = api.send(request).await;
+            let mut state = state.lock().unwrap();
+            match result {
+                Ok(_) if applied.is_some() => state.config = applied,
+                Ok(_) => {}
+                Err(e) => state.messages.push((Severity::Error, format!("Remote request to {} failed: {}", api.base, e))),
+            }
+        });
+    }
+}
+
+// Fetch everything the GUI shows. The status goes first, it tells whether the server is
+// reachable and lets the server cache the verified password before the other requests.
+async fn poll(api: &Api, state: &Mutex<RemoteState>) {
+    let status = match api.get::<StatusReply>("/api/status").await {
+        Ok(status) => status,
+        Err(e) => {
+            let mut state = state.lock().unwrap();
+            if state.reachable != Some(false) {
+                state.messages.push((Severity::Error, format!("Lost connection to {}: {}", api.base, e)));
+            }
+            state.reachable = Some(false);
+            state.status = ServerStatus::Error(format!("{} is unreachable", api.base));
+            return;
+        }
+    };
+    let log_seq = state.lock().unwrap().log_seq;
+    let logs_path = format!("/api/logs?after={}", log_seq);
+    let (stats, config, connections, requests, logs) = tokio::join!(
+        api.get::<ServerStats>("/api/stats"),
+        api.get::<ServerConfig>("/api/config"),
+        api.get::<ConnectionsReply>("/api/connections"),
+        api.get::<RequestsReply>("/api/requests"),
+        api.get::<LogsReply>(&logs_path),
+    );
+
+    let mut state = state.lock().unwrap();
+    if state.reachable != Some(true) {
+        state.messages.push((Severity::Info, format!("Connected to {}", api.base)));
+    }
+    state.reachable = Some(true);
+    state.status = parse_status(&status.status);
+
+    if let Ok(config) = config {
+        if state.config.as_ref() != Some(&config) {
+            state.changed_config = Some(config.clone());
+            state.config = Some(config);
+        }
+    }
+    // The listener table shows the configured address of each listener
+    let configured = state.config.as_ref().map(|config| config.listeners.clone()).unwrap_or_default();
+    state.listeners = status
+        .listeners
+        .into_iter()
+        .map(|listener| ListenerState {
+            config: configured.iter().find(|config| config.name == listener.summary.name).cloned().unwrap_or_else(|| {
+                ListenerConfig {
+                    name: listener.summary.name,
+                    protocol: listener.summary.protocol,
+                    admin: listener.summary.admin,
+                    ..ListenerConfig::default()
+                }
+            }),
+            status: parse_status(&listener.status),
+        })
+        .collect();
+
+    let failures = [
+        stats.as_ref().err(),
+        connections.as_ref().err(),
+        requests.as_ref().err(),
+        logs.as_ref().err(),
+    ];
+    if let Some(e) = failures.into_iter().flatten().next() {
+        eprintln!("Polling {} failed: {}", api.base, e);
+    }
+    if let Ok(stats) = stats {
+        state.stats = stats;
+    }
+    if let Ok(reply) = connections {
+        state.connections = reply.connections;
+    }
+    if let Ok(reply) = requests {
+        state.requests = reply.requests.into_iter().map(Arc::new).collect();
+    }
+    if let Ok(reply) = logs {
+        for entry in reply.entries {
+            state.log_seq = state.log_seq.max(entry.seq);
+            state.messages.push((parse_severity(&entry.level), entry.message));
+        }
+    }
+}
@@ -1,14 +1,37 @@
-use rocket::{get, routes, Config, Ignite, Rocket}; 
-use rocket::http::Status;
+use rocket::{catch, catchers, get, routes, Config, Ignite, Request, Rocket};
+use rocket::config::TlsConfig;
+use rocket::http::{ContentType, Status};
 use rocket::response::status as rocket_status;
-use std::net::IpAddr;
-use std::str::FromStr;
+use rocket::fairing::AdHoc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::sync::oneshot;
 use std::fmt;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::access_log::{self, AccessLogConfig};
+use crate::admin_api::{self, AdminApiConfig};
+use crate::control_socket::ControlSocketConfig;
+use crate::auth::{self, AuthConfig};
+use crate::config;
+use crate::connections::{self, ConnectionFairing};
+use crate::limits;
+use crate::log_forward::{self, LogForwardingConfig};
+use crate::metrics::{self, MetricsConfig, MetricsFairing, RequestTotals};
+use crate::notifications::AlertThresholds;
+use crate::oidc;
+use crate::process::{self, ProcessStats};
+use crate::sessions::{self, SessionConfig};
+use crate::trace::{self, TraceFairing, TracingConfig};
+use crate::upstream::{self, BackendHealth, PoolStats, UpstreamConfig};
 
-// Track active connections for monitoring
-static ACTIVE_CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+// Configuration the listeners are serving, with the hash reported on the status page
+static RUNNING_CONFIG: RwLock<Option<Arc<(ServerConfig, String)>>> = RwLock::new(None);
+
+// When the first listener started, cleared once they have all stopped
+static STARTED_AT: RwLock<Option<SystemTime>> = RwLock::new(None);
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ServerStatus {
@@ -31,39 +54,173 @@ impl fmt::Display for ServerStatus {
     }
 }
 
-// Configuration struct to pass to the server thread
-#[derive(Clone, Debug)]
-pub struct ServerConfig {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Http,
+    Https,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Http => write!(f, "HTTP"),
+            Protocol::Https => write!(f, "HTTPS"),
+        }
+    }
+}
+
+// Certificate chain and private key (PEM files) for an HTTPS listener
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+// An address the server accepts connections on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    pub name: String,
     pub host: String,
     pub port: u16,
-    pub max_connections: usize,
-    // pub protocol: String, // Add later if needed for HTTPS config
+    pub protocol: Protocol,
+    // Only used by HTTPS listeners
+    pub tls: TlsSettings,
+    // Admin listeners only serve the monitoring endpoints
+    pub admin: bool,
 }
 
-impl Default for ServerConfig {
+impl Default for ListenerConfig {
     fn default() -> Self {
         Self {
+            name: "main".to_string(),
             host: "127.0.0.1".to_string(),
             port: 8000,
+            protocol: Protocol::Http,
+            tls: TlsSettings::default(),
+            admin: false,
+        }
+    }
+}
+
+impl fmt::Display for ListenerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Bracket IPv6 addresses so the port stays readable
+        if self.host.contains(':') {
+            write!(f, "{}://[{}]:{}", self.protocol.to_string().to_lowercase(), self.host, self.port)
+        } else {
+            write!(f, "{}://{}:{}", self.protocol.to_string().to_lowercase(), self.host, self.port)
+        }
+    }
+}
+
+// Configuration struct to pass to the server thread
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub listeners: Vec<ListenerConfig>,
+    // Requests handled at the same time, further requests queue and then get 503
+    pub max_connections: usize,
+    // How long a request waits for a free slot before it is rejected
+    pub queue_timeout_ms: u64,
+    pub workers: usize,
+    // Idle time before a keep-alive connection is closed, 0 disables keep-alive
+    pub keep_alive_secs: u32,
+    // Longest a handler may run before the request fails with 503, 0 disables
+    pub request_timeout_secs: u64,
+    // Upstream pools that requests are proxied to, keyed by path prefix
+    pub upstreams: Vec<UpstreamConfig>,
+    pub metrics: MetricsConfig,
+    // Addresses or CIDR networks allowed to read /server-status, empty allows everyone
+    pub status_trusted_ips: Vec<String>,
+    pub tracing: TracingConfig,
+    pub access_log: AccessLogConfig,
+    // Syslog or journald, for both the server and the access log
+    pub log_forwarding: LogForwardingConfig,
+    // Users and the paths they have to log in for
+    pub auth: AuthConfig,
+    // Timeouts and storage of login sessions
+    pub sessions: SessionConfig,
+    // Remote control through a JSON API on the admin listeners
+    pub admin_api: AdminApiConfig,
+    // Local control through solaractl
+    pub control_socket: ControlSocketConfig,
+    // Only used by the GUI to decide when to raise notifications
+    pub alerts: AlertThresholds,
+}
+
+impl ServerConfig {
+    // Whether moving from `self` to `other` needs every listener restarted
+    pub fn requires_restart(&self, other: &ServerConfig) -> bool {
+        self.workers != other.workers
+            || self.keep_alive_secs != other.keep_alive_secs
+            || self.metrics != other.metrics
+            || self.sessions.secret_key_path != other.sessions.secret_key_path
+            || self.admin_api.enabled != other.admin_api.enabled
+    }
+}
+
+// Errors that stop a listener from starting
+#[derive(Debug)]
+pub enum ServerError {
+    InvalidHost(String),
+    SessionKey(String),
+    Rocket(rocket::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::InvalidHost(e) => write!(f, "Invalid host: {}", e),
+            ServerError::SessionKey(e) => write!(f, "Session key unavailable: {}", e),
+            ServerError::Rocket(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+impl From<rocket::Error> for ServerError {
+    fn from(e: rocket::Error) -> Self {
+        ServerError::Rocket(e)
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listeners: vec![ListenerConfig::default()],
             max_connections: 100,
+            queue_timeout_ms: 500,
+            // One worker thread per core, like Rocket's own default
+            workers: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            keep_alive_secs: 5,
+            request_timeout_secs: 30,
+            upstreams: Vec::new(),
+            metrics: MetricsConfig::default(),
+            status_trusted_ips: Vec::new(),
+            tracing: TracingConfig::default(),
+            access_log: AccessLogConfig::default(),
+            log_forwarding: LogForwardingConfig::default(),
+            auth: AuthConfig::default(),
+            sessions: SessionConfig::default(),
+            admin_api: AdminApiConfig::default(),
+            control_socket: ControlSocketConfig::default(),
+            alerts: AlertThresholds::default(),
         }
     }
 }
 
 #[get("/")]
 fn index() -> &'static str {
-    ACTIVE_CONNECTIONS.fetch_add(1, Ordering::SeqCst);
-    let result = "Hello, world from Solara Server!";
-    ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
-    result
+    "Hello, world from Solara Server!"
 }
 
 #[get("/hello/<name>")]
 fn hello(name: &str) -> String {
-    ACTIVE_CONNECTIONS.fetch_add(1, Ordering::SeqCst);
-    let result = format!("Hello, {}!", name);
-    ACTIVE_CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
-    result
+    format!("Hello, {}!", name)
 }
 
 // Added error response route
@@ -72,41 +229,157 @@ fn error() -> rocket_status::Custom<String> {
     rocket_status::Custom(Status::InternalServerError, "Server error simulation".to_string())
 }
 
-// Added monitoring route
-#[get("/server-status")]
-fn server_status() -> String {
-    let connections = ACTIVE_CONNECTIONS.load(Ordering::SeqCst);
-    format!("{{\"status\": \"online\", \"active_connections\": {}}}", connections)
+// Monitoring route, JSON by default or a plain text summary with ?format=text
+#[get("/server-status?<format>")]
+fn server_status(format: Option<&str>, remote: Option<SocketAddr>) -> Result<(ContentType, String), Status> {
+    let trusted = RUNNING_CONFIG
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|running| running.0.status_trusted_ips.clone())
+        .unwrap_or_default();
+    // Judge by the socket peer, forwarding headers are easy to forge
+    if !remote.is_some_and(|remote| config::is_trusted(&trusted, remote.ip())) {
+        return Err(Status::Forbidden);
+    }
+
+    let stats = get_server_stats();
+    match format {
+        Some("text") => Ok((ContentType::Plain, stats.to_text())),
+        Some("json") | None => {
+            let document = StatusDocument { status: stats.health(), stats: &stats };
+            serde_json::to_string_pretty(&document)
+                .map(|json| (ContentType::JSON, json))
+                .map_err(|_| Status::InternalServerError)
+        }
+        Some(_) => Err(Status::BadRequest),
+    }
+}
+
+// Error pages carry the request ID so users can quote it when reporting a problem
+#[catch(default)]
+fn default_catcher(status: Status, req: &Request<'_>) -> (Status, (ContentType, String)) {
+    let reason = status.reason().unwrap_or("Error");
+    let page = format!(
+        "<!DOCTYPE html>\n<html>\n<head><title>{code} {reason}</title></head>\n<body>\n\
+         <h1>{code} {reason}</h1>\n<hr>\n<p>Solara Server - Request ID: {id}</p>\n</body>\n</html>\n",
+        code = status.code,
+        reason = reason,
+        id = trace::request_id(req),
+    );
+    (status, (ContentType::HTML, page))
+}
+
+#[derive(Serialize)]
+struct StatusDocument<'a> {
+    status: &'static str,
+    #[serde(flatten)]
+    stats: &'a ServerStats,
 }
 
-// Start server function with improved error handling
+// Start server function with improved error handling. Every listener runs
+// its own server instance; `liftoff` is signalled once it is bound and serving.
 pub async fn start_server(
     config: ServerConfig,
-) -> Result<Rocket<Ignite>, rocket::Error> {
-    println!("Configuring server for {}:{} with max {} connections", 
-        config.host, config.port, config.max_connections);
+    listener: ListenerConfig,
+    liftoff: oneshot::Sender<()>,
+) -> Result<Rocket<Ignite>, ServerError> {
+    println!("Configuring listener '{}' for {} with max {} connections",
+        listener.name, listener, config.max_connections);
 
-    // Attempt to parse the IP address with improved error handling
-    let ip_addr = match IpAddr::from_str(&config.host) {
-        Ok(addr) => addr,
-        Err(e) => {
-            eprintln!("Failed to parse host '{}': {}. Defaulting to 127.0.0.1", config.host, e);
-            IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1))
-        }
+    // Resolve the host, refusing to guess when it is not a valid address
+    let ip_addr = config::resolve_host(&listener.host).map_err(|e| {
+        eprintln!("Failed to resolve host '{}': {}", listener.host, e);
+        ServerError::InvalidHost(format!("'{}': {}", listener.host, e))
+    })?;
+
+    let tls = match listener.protocol {
+        Protocol::Https => Some(TlsConfig::from_paths(&listener.tls.cert_path, &listener.tls.key_path)),
+        Protocol::Http => None,
     };
 
+    // Every listener signs session cookies with the same key
+    let secret_key = sessions::secret_key(&config.sessions.secret_key_path).map_err(ServerError::SessionKey)?;
+
     let rocket_config = Config {
         address: ip_addr,
-        port: config.port,
-        workers: config.max_connections.min(16), // Cap at 16 workers
+        port: listener.port,
+        workers: config.workers,
+        keep_alive: config.keep_alive_secs,
+        tls,
+        secret_key,
         ..Config::default()
     };
 
-    // Build and ignite the server with additional routes
-    rocket::custom(rocket_config)
-        .mount("/", routes![index, hello, error, server_status])
+    // Build the server with additional routes. Admin listeners only expose monitoring
+    // and the admin API. Every route sits behind the concurrency limit and request
+    // timeout, and asks for a login on protected paths.
+    let rocket = if listener.admin {
+        let rocket = rocket::custom(rocket_config)
+            .mount("/", metrics::track_routes(limits::limit_routes(auth::protect_routes(routes![server_status]))));
+        if config.admin_api.enabled {
+            rocket.mount("/", metrics::track_routes(limits::limit_routes(auth::protect_routes(admin_api::api_routes()))))
+        } else {
+            rocket
+        }
+    } else {
+        // Upstream pools are looked up by the proxy routes at request time so a reload can swap them
+        rocket::custom(rocket_config)
+            .mount("/", metrics::track_routes(limits::limit_routes(auth::protect_routes(routes![index, hello, error, server_status]))))
+            .mount("/", metrics::track_routes(limits::limit_routes(auth::protect_routes(upstream::proxy_routes()))))
+            .mount("/", metrics::track_routes(limits::limit_routes(auth::auth_routes())))
+            .mount("/", metrics::track_routes(limits::limit_routes(oidc::oidc_routes())))
+    };
+
+    // Scrapes bypass the connection limit so the server can still be observed while saturated
+    let rocket = if config.metrics.serve_on(listener.admin) {
+        rocket.mount("/", auth::protect_routes(metrics::metrics_routes()))
+    } else {
+        rocket
+    };
+
+    // Tracing goes first so its clock starts before the other fairings run
+    let rocket = rocket
+        .register("/", catchers![default_catcher])
+        .attach(TraceFairing { listener: listener.name.clone() })
+        .attach(ConnectionFairing { listener: listener.name.clone(), secure: listener.protocol == Protocol::Https })
+        .attach(MetricsFairing)
+        .attach(AdHoc::on_liftoff("Liftoff Notification", |_| Box::pin(async move {
+            let _ = liftoff.send(());
+        })))
         .ignite()
-        .await
+        .await?;
+    Ok(rocket)
+}
+
+// Swap the parts of the configuration that can change while the server runs
+pub fn apply_live_config(config: &ServerConfig) {
+    let hash = toml::to_string(config)
+        .map(|toml| format!("{:x}", Sha256::digest(toml.as_bytes())))
+        .unwrap_or_default();
+    *RUNNING_CONFIG.write().unwrap() = Some(Arc::new((config.clone(), hash)));
+    STARTED_AT.write().unwrap().get_or_insert_with(SystemTime::now);
+    limits::apply_limits(config);
+    connections::apply_connections(config.keep_alive_secs);
+    trace::apply_tracing(&config.tracing);
+    access_log::apply_access_log(&config.access_log);
+    log_forward::apply_forwarding(&config.log_forwarding);
+    auth::apply_auth(&config.auth);
+    sessions::apply_sessions(&config.sessions);
+    upstream::install_pools(&config.upstreams);
+}
+
+pub fn clear_live_config() {
+    *RUNNING_CONFIG.write().unwrap() = None;
+    *STARTED_AT.write().unwrap() = None;
+    limits::clear_limits();
+    connections::clear_connections();
+    trace::clear_tracing();
+    access_log::clear_access_log();
+    log_forward::clear_forwarding();
+    auth::clear_auth();
+    sessions::clear_sessions();
+    upstream::clear_pools();
 }
 
 // Improved launch function with better error handling
@@ -149,15 +422,116 @@ pub async fn launch_ignited_server(
 
 // New function to get current server stats
 pub fn get_server_stats() -> ServerStats {
+    let running = RUNNING_CONFIG.read().unwrap().clone();
+    let started_at = *STARTED_AT.read().unwrap();
     ServerStats {
-        active_connections: ACTIVE_CONNECTIONS.load(Ordering::SeqCst),
-        uptime_seconds: 0, // You could track this with SystemTime
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        start_time: started_at.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_secs()),
+        uptime_seconds: started_at.and_then(|time| time.elapsed().ok()).map_or(0, |uptime| uptime.as_secs()),
+        listeners: running
+            .as_ref()
+            .map(|running| running.0.listeners.iter().map(ListenerSummary::from).collect())
+            .unwrap_or_default(),
+        active_connections: limits::in_flight_requests(),
+        rejected_requests: limits::rejected_requests(),
+        timed_out_requests: limits::timed_out_requests(),
+        requests: metrics::request_totals(),
+        process: process::sample(),
+        config_hash: running.map(|running| running.1.clone()),
+        upstreams: upstream::pool_stats(),
     }
 }
 
 // Stats structure for monitoring
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ServerStats {
-    pub active_connections: usize,
+    pub version: String,
+    // Unix timestamp of when the server started
+    pub start_time: Option<u64>,
     pub uptime_seconds: u64,
+    pub listeners: Vec<ListenerSummary>,
+    pub active_connections: usize,
+    pub rejected_requests: u64,
+    pub timed_out_requests: u64,
+    pub requests: RequestTotals,
+    pub process: Option<ProcessStats>,
+    // SHA-256 of the running configuration, to tell instances apart after a reload
+    pub config_hash: Option<String>,
+    pub upstreams: Vec<PoolStats>,
+}
+
+impl ServerStats {
+    // Degraded while an upstream pool has no backend left to serve requests
+    pub fn health(&self) -> &'static str {
+        let pool_down = self.upstreams.iter().any(|pool| {
+            !pool.backends.is_empty() && pool.backends.iter().all(|backend| backend.health != BackendHealth::Healthy)
+        });
+        if pool_down { "degraded" } else { "online" }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Solara Server {} - {}", self.version, self.health());
+        if let Some(start_time) = self.start_time.and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0)) {
+            let _ = writeln!(out, "Started: {} (up {})", start_time.format("%Y-%m-%d %H:%M:%S UTC"), format_duration(self.uptime_seconds));
+        }
+        let _ = writeln!(out, "Listeners:");
+        for listener in &self.listeners {
+            let _ = writeln!(out, "  {} {}{}", listener.name, listener.address, if listener.admin { " (admin)" } else { "" });
+        }
+        let _ = writeln!(out, "Requests: {} total, {} client errors, {} server errors ({:.2}% error rate)",
+            self.requests.total, self.requests.client_errors, self.requests.server_errors,
+            self.requests.server_error_rate * 100.0);
+        let _ = writeln!(out, "In flight: {}, rejected: {}, timed out: {}",
+            self.active_connections, self.rejected_requests, self.timed_out_requests);
+        if let Some(process) = &self.process {
+            let _ = writeln!(out, "Process: {:.1}% CPU ({:.2}s total), {} resident, {} open files, {} threads",
+                process.cpu_percent, process.cpu_seconds, process::format_bytes(process.resident_memory_bytes),
+                process.open_fds, process.threads);
+        }
+        for pool in &self.upstreams {
+            let _ = writeln!(out, "Upstream '{}' ({}, {}):", pool.name, pool.path_prefix, pool.strategy);
+            for backend in &pool.backends {
+                let _ = writeln!(out, "  {} {} - {} requests, {} failed", backend.address, backend.health,
+                    backend.total_requests, backend.failed_requests);
+            }
+        }
+        if let Some(hash) = &self.config_hash {
+            let _ = writeln!(out, "Config: {}", hash);
+        }
+        out
+    }
+}
+
+// Public view of a listener, without certificate paths
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ListenerSummary {
+    pub name: String,
+    pub address: String,
+    pub protocol: Protocol,
+    pub admin: bool,
+}
+
+impl From<&ListenerConfig> for ListenerSummary {
+    fn from(listener: &ListenerConfig) -> Self {
+        Self {
+            name: listener.name.clone(),
+            address: listener.to_string(),
+            protocol: listener.protocol,
+            admin: listener.admin,
+        }
+    }
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let (days, hours, minutes, seconds) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m {}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
 }
@@ -0,0 +1,356 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rocket::config::SecretKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::file_writer::{self, FileWriter};
+
+// Private cookie holding the session token, encrypted and signed by Rocket
+pub const SESSION_COOKIE: &str = "solara_session";
+
+// Sessions of the running server, None while it is stopped
+static SESSIONS: Mutex<Option<SessionStore>> = Mutex::new(None);
+
+// Cookie key and the file it came from, shared by every listener
+static SECRET_KEY: Mutex<Option<(String, SecretKey)>> = Mutex::new(None);
+
+// Activity alone is written to the store file at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    // Inactivity after which a session ends
+    pub idle_timeout_secs: u64,
+    // Longest a session lasts, however active
+    pub absolute_timeout_secs: u64,
+    // File sessions are kept in so they survive restarts, empty keeps them in memory only
+    pub store_path: String,
+    // Key the cookies are encrypted and signed with, created when missing. Empty
+    // uses a new key on every start, which logs everybody out.
+    pub secret_key_path: String,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: 1800,
+            absolute_timeout_secs: 86400,
+            store_path: String::new(),
+            secret_key_path: "session.key".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    // SHA-256 of the token, so neither the store file nor the GUI reveal it
+    pub id: String,
+    pub username: String,
+    // Roles from the identity provider of a single sign-on login, None for users
+    // of the users file, whose roles are looked up on every request
+    #[serde(default)]
+    pub roles: Option<Vec<String>>,
+    pub remote: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    // Unix seconds
+    pub created: u64,
+    pub last_seen: u64,
+    // When the session ends unless it is used, filled in for listings
+    #[serde(skip)]
+    pub expires: u64,
+}
+
+impl Session {
+    fn expiry(&self, config: &SessionConfig) -> u64 {
+        (self.last_seen + config.idle_timeout_secs).min(self.created + config.absolute_timeout_secs)
+    }
+}
+
+struct SessionStore {
+    config: SessionConfig,
+    sessions: HashMap<String, Session>,
+    // Activity not written to the store file yet
+    dirty: bool,
+    last_save: Instant,
+    // None keeps the sessions in memory only
+    writer: Option<FileWriter>,
+}
+
+impl SessionStore {
+    fn prune(&mut self) {
+        let now = unix_now();
+        let config = &self.config;
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| session.expiry(config) > now);
+        if self.sessions.len() != before {
+            self.save();
+        }
+    }
+
+    fn save(&mut self) {
+        self.dirty = false;
+        self.last_save = Instant::now();
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let sessions: Vec<&Session> = self.sessions.values().collect();
+        match serde_json::to_vec_pretty(&sessions) {
+            Ok(contents) => writer.write(contents),
+            Err(e) => eprintln!("Failed to save sessions to {}: {}", self.config.store_path, e),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs())
+}
+
+fn token_id(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn read_store(path: &str) -> io::Result<Vec<Session>> {
+    match fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Start or update the store. Moving to another store file keeps the sessions in memory
+// and adds the ones in the file.
+pub fn apply_sessions(config: &SessionConfig) {
+    let mut store = SESSIONS.lock().unwrap();
+    let (mut sessions, mut writer, reload) = match store.take() {
+        Some(store) => {
+            let reload = store.config.store_path != config.store_path;
+            (store.sessions, store.writer, reload)
+        }
+        None => (HashMap::new(), None, true),
+    };
+    if reload {
+        if let Some(writer) = writer.take() {
+            writer.finish();
+        }
+        if !config.store_path.is_empty() {
+            match FileWriter::start("sessions", Path::new(&config.store_path), file_writer::write_private) {
+                Ok(started) => writer = Some(started),
+                Err(e) => eprintln!("Failed to start writing sessions to {}: {}", config.store_path, e),
+            }
+        }
+    }
+    if reload && !config.store_path.is_empty() {
+        match read_store(&config.store_path) {
+            Ok(stored) => sessions.extend(stored.into_iter().map(|session| (session.id.clone(), session))),
+            Err(e) => eprintln!("Failed to read sessions from {}: {}", config.store_path, e),
+        }
+    }
+    let mut new_store = SessionStore { config: config.clone(), sessions, dirty: false, last_save: Instant::now(), writer };
+    new_store.prune();
+    if reload {
+        new_store.save();
+    }
+    *store = Some(new_store);
+}
+
+// Write pending activity before the server stops. Sessions without a store file end here.
+pub fn clear_sessions() {
+    let store = SESSIONS.lock().unwrap().take();
+    if let Some(mut store) = store {
+        if store.dirty {
+            store.save();
+        }
+        if let Some(writer) = store.writer.take() {
+            writer.finish();
+        }
+    }
+}
+
+pub fn timeouts() -> Option<(Duration, Duration)> {
+    let store = SESSIONS.lock().unwrap();
+    let config = &store.as_ref()?.config;
+    Some((Duration::from_secs(config.idle_timeout_secs), Duration::from_secs(config.absolute_timeout_secs)))
+}
+
+// Start a session, returning the token for the cookie
+pub fn create(username: &str, roles: Option<Vec<String>>, remote: Option<IpAddr>, user_agent: Option<&str>) -> Option<String> {
+    let mut store = SESSIONS.lock().unwrap();
+    let store = store.as_mut()?;
+    let token: String = rand::random::<[u8; 32]>().iter().map(|b| format!("{:02x}", b)).collect();
+    let now = unix_now();
+    let session = Session {
+        id: token_id(&token),
+        username: username.to_string(),
+        roles,
+        remote,
+        user_agent: user_agent.map(|agent| agent.chars().take(200).collect()),
+        created: now,
+        last_seen: now,
+        expires: 0,
+    };
+    store.sessions.insert(session.id.clone(), session);
+    store.prune();
+    store.save();
+    Some(token)
+}
+
+// A live session, recording the activity
+pub fn touch(token: &str) -> Option<Session> {
+    let mut store = SESSIONS.lock().unwrap();
+    let store = store.as_mut()?;
+    let id = token_id(token);
+    let now = unix_now();
+    let session = store.sessions.get_mut(&id)?;
+    if session.expiry(&store.config) <= now {
+        store.sessions.remove(&id);
+        store.save();
+        return None;
+    }
+    session.last_seen = now;
+    let session = session.clone();
+    store.dirty = true;
+    if store.last_save.elapsed() >= SAVE_INTERVAL {
+        store.save();
+    }
+    Some(session)
+}
+
+pub fn active_sessions() -> Vec<Session> {
+    let mut store = SESSIONS.lock().unwrap();
+    let Some(store) = store.as_mut() else {
+        return Vec::new();
+    };
+    store.prune();
+    let mut sessions: Vec<Session> = store
+        .sessions
+        .values()
+        .map(|session| Session { expires: session.expiry(&store.config), ..session.clone() })
+        .collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+    sessions
+}
+
+// End a session by its ID, as listed by `active_sessions`
+pub fn revoke(id: &str) -> bool {
+    let mut store = SESSIONS.lock().unwrap();
+    let Some(store) = store.as_mut() else {
+        return false;
+    };
+    let removed = store.sessions.remove(id).is_some();
+    if removed {
+        store.save();
+    }
+    removed
+}
+
+pub fn revoke_token(token: &str) {
+    revoke(&token_id(token));
+}
+
+// End every session of a user except the one holding `keep`, returning how many ended
+pub fn revoke_user(username: &str, keep: Option<&str>) -> usize {
+    let mut store = SESSIONS.lock().unwrap();
+    let Some(store) = store.as_mut() else {
+        return 0;
+    };
+    let keep = keep.map(token_id);
+    let before = store.sessions.len();
+    store.sessions.retain(|id, session| session.username != username || Some(id) == keep.as_ref());
+    let removed = before - store.sessions.len();
+    if removed > 0 {
+        store.save();
+    }
+    removed
+}
+
+// Key for the private cookies. Loaded once per path, so every listener uses the same one.
+pub fn secret_key(path: &str) -> Result<SecretKey, String> {
+    let mut cached = SECRET_KEY.lock().unwrap();
+    if let Some((cached_path, key)) = cached.as_ref() {
+        if cached_path == path {
+            return Ok(key.clone());
+        }
+    }
+    let key = if path.is_empty() {
+        SecretKey::generate().ok_or("no random source for a session key")?
+    } else {
+        match read_key(path) {
+            Ok(key) => key,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let material: Vec<u8> = (0..64).map(|_| rand::random::<u8>()).collect();
+                file_writer::write_private(Path::new(path), STANDARD.encode(&material).as_bytes())
+                    .map_err(|e| format!("failed to write session key {}: {}", path, e))?;
+                println!("Created session key {}", path);
+                SecretKey::from(&material)
+            }
+            Err(e) => return Err(format!("failed to read session key {}: {}", path, e)),
+        }
+    };
+    *cached = Some((path.to_string(), key.clone()));
+    Ok(key)
+}
+
+// The key file holds 64 random bytes in base64
+fn read_key(path: &str) -> io::Result<SecretKey> {
+    let contents = fs::read_to_string(path)?;
+    let material = STANDARD
+        .decode(contents.trim())
+        .ok()
+        .filter(|material| material.len() == 64)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected 64 bytes in base64"))?;
+    Ok(SecretKey::from(&material))
+}
+
+// Check an existing key file, for configuration validation. A missing one is created at start.
+pub fn check_key_file(path: &str) -> Result<(), String> {
+    if path.is_empty() {
+        return Ok(());
+    }
+    match read_key(path) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_survive_a_restart_through_the_store_file() {
+        let path = std::env::temp_dir().join(format!("solara-sessions-store-{}.json", std::process::id()));
+        let config = SessionConfig {
+            store_path: path.to_string_lossy().into_owned(),
+            idle_timeout_secs: 60,
+            ..SessionConfig::default()
+        };
+
+        apply_sessions(&config);
+        let token = create("alice", None, None, Some("test")).unwrap();
+        let revoked = create("bob", None, None, None).unwrap();
+        revoke_token(&revoked);
+        clear_sessions();
+        assert!(touch(&token).is_none());
+
+        apply_sessions(&config);
+        assert_eq!(touch(&token).map(|session| session.username), Some("alice".to_string()));
+        assert!(touch(&revoked).is_none());
+        clear_sessions();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
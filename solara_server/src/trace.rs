@@ -0,0 +1,504 @@
+use rocket::data::Data;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::response::Body;
+use rocket::{Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use tokio::task::JoinHandle;
+use crate::api_keys;
+use crate::auth;
+use crate::access_log;
+use crate::connections;
+
+// Finished requests kept for the Connection Monitor
+const RECENT_CAPACITY: usize = 200;
+
+// Spans waiting for export are dropped past this, e.g. while the collector is down
+const MAX_QUEUED_SPANS: usize = 10_000;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Header values that should not end up in the GUI or a trace backend
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "cookie", "set-cookie", "proxy-authorization"];
+
+static RECENT_REQUESTS: Mutex<VecDeque<Arc<RequestRecord>>> = Mutex::new(VecDeque::new());
+
+static EXPORT_ENABLED: AtomicBool = AtomicBool::new(false);
+static SPAN_QUEUE: Mutex<Vec<Arc<RequestRecord>>> = Mutex::new(Vec::new());
+static DROPPED_SPANS: AtomicU64 = AtomicU64::new(0);
+
+// Running export task and the settings it was started with
+static EXPORTER: Mutex<Option<(TracingConfig, JoinHandle<()>)>> = Mutex::new(None);
+
+// Where finished request spans are exported to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    pub otlp_enabled: bool,
+    // OTLP/HTTP traces endpoint of the collector, JSON encoded
+    pub otlp_endpoint: String,
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            otlp_enabled: false,
+            otlp_endpoint: "http://127.0.0.1:4318/v1/traces".to_string(),
+            service_name: "solara".to_string(),
+        }
+    }
+}
+
+// Where the time of a request went
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timing {
+    // From receiving the request to its handler being called, including queueing
+    pub routing: Duration,
+    pub handler: Duration,
+    // Catchers and response fairings after the handler returned
+    pub response: Duration,
+    // Writing the body to the client
+    pub body: Duration,
+    pub total: Duration,
+}
+
+// A finished request, for the Connection Monitor and the trace export
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub request_id: String,
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub listener: String,
+    pub remote: Option<SocketAddr>,
+    // Logged in user, for requests that needed one
+    pub user: Option<String>,
+    pub method: String,
+    pub uri: String,
+    pub route: Option<String>,
+    pub request_headers: Vec<(String, String)>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    // Body bytes written to the client
+    pub response_bytes: u64,
+    // Whether the client went away before the body was fully sent
+    pub aborted: bool,
+    pub started: SystemTime,
+    pub timing: Timing,
+}
+
+// Per-request state, set up when the request arrives
+struct RequestContext {
+    request_id: String,
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    started: SystemTime,
+    start: Instant,
+    // Start and end of the handler that produced the response
+    handler: Mutex<Option<(Instant, Instant)>>,
+}
+
+impl RequestContext {
+    fn new(req: &Request<'_>) -> Self {
+        let parent = req.headers().get_one("traceparent").and_then(parse_traceparent);
+        let request_id = req
+            .headers()
+            .get_one("X-Request-Id")
+            .filter(|id| valid_request_id(id))
+            .map(str::to_string);
+        let trace_id = parent
+            .as_ref()
+            .map(|(trace_id, _)| trace_id.clone())
+            .or_else(|| request_id.clone().filter(|id| is_hex(id, 32)))
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+        Self {
+            request_id: request_id.unwrap_or_else(|| trace_id.clone()),
+            trace_id,
+            span_id: format!("{:016x}", rand::random::<u64>()),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            started: SystemTime::now(),
+            start: Instant::now(),
+            handler: Mutex::new(None),
+        }
+    }
+}
+
+// ID of the request being handled, for error pages and access logs
+pub fn request_id<'r>(req: &'r Request<'_>) -> &'r str {
+    &req.local_cache(|| RequestContext::new(req)).request_id
+}
+
+// Called by the route wrappers once a handler produced a response
+pub fn record_handler(req: &Request<'_>, started: Instant) {
+    let context = req.local_cache(|| RequestContext::new(req));
+    *context.handler.lock().unwrap() = Some((started, Instant::now()));
+}
+
+pub fn recent_requests() -> Vec<Arc<RequestRecord>> {
+    RECENT_REQUESTS.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn find_request(request_id: &str) -> Option<Arc<RequestRecord>> {
+    RECENT_REQUESTS.lock().unwrap().iter().rev().find(|record| record.request_id == request_id).cloned()
+}
+
+// Assigns request IDs and records the timing of every request
+pub struct TraceFairing {
+    pub listener: String,
+}
+
+#[rocket::async_trait]
+impl Fairing for TraceFairing {
+    fn info(&self) -> Info {
+        Info { name: "Request Tracing", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestContext::new(req));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let context = req.local_cache(|| RequestContext::new(req));
+        res.set_header(Header::new("X-Request-Id", context.request_id.clone()));
+
+        let now = Instant::now();
+        let handler = *context.handler.lock().unwrap();
+        let (handler_start, handler_end) = handler.unwrap_or((now, now));
+        let record = RequestRecord {
+            request_id: context.request_id.clone(),
+            trace_id: context.trace_id.clone(),
+            span_id: context.span_id.clone(),
+            parent_span_id: context.parent_span_id.clone(),
+            listener: self.listener.clone(),
+            remote: req.remote(),
+            user: auth::request_user(req),
+            method: req.method().as_str().to_string(),
+            uri: api_keys::redacted_uri(req.uri()),
+            route: handler.and(req.route()).map(|route| route.uri.to_string()),
+            request_headers: redact(req.headers().iter().map(|h| (h.name().to_string(), h.value().to_string()))),
+            status: res.status().code,
+            response_headers: redact(res.headers().iter().map(|h| (h.name().to_string(), h.value().to_string()))),
+            response_bytes: 0,
+            aborted: false,
+            started: context.started,
+            timing: Timing {
+                routing: handler_start.duration_since(context.start),
+                handler: handler_end.duration_since(handler_start),
+                response: now.duration_since(handler_end),
+                ..Timing::default()
+            },
+        };
+
+        // The request is only finished once the body has been written
+        if res.body().is_none() {
+            finish(record, context.start, None, 0, true);
+            return;
+        }
+        let body = res.body_mut().take();
+        let max_chunk = body.max_chunk_size();
+        let timed = TimedBody { inner: body, start: context.start, first_read: None, bytes: 0, record: Some(record) };
+        match timed.inner.preset_size() {
+            Some(size) => res.set_sized_body(size, timed),
+            None => res.set_streamed_body(timed),
+        }
+        res.set_max_chunk_size(max_chunk);
+    }
+}
+
+// Response body that finishes the request record once it was fully read or dropped
+struct TimedBody<'r> {
+    inner: Body<'r>,
+    start: Instant,
+    first_read: Option<Instant>,
+    bytes: u64,
+    record: Option<RequestRecord>,
+}
+
+impl TimedBody<'_> {
+    fn finish(&mut self, complete: bool) {
+        if let Some(record) = self.record.take() {
+            finish(record, self.start, self.first_read, self.bytes, complete);
+        }
+    }
+}
+
+impl AsyncRead for TimedBody<'_> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        self.first_read.get_or_insert_with(Instant::now);
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = buf.filled().len() - filled;
+            self.bytes += read as u64;
+            if read == 0 {
+                self.finish(true);
+            }
+        }
+        poll
+    }
+}
+
+// Only needed to keep the preset size. Rocket does not seek bodies whose size is known.
+impl AsyncSeek for TimedBody<'_> {
+    fn start_seek(self: Pin<&mut Self>, _: io::SeekFrom) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "response body can't be seeked"))
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+impl Drop for TimedBody<'_> {
+    fn drop(&mut self) {
+        self.finish(false);
+    }
+}
+
+fn finish(mut record: RequestRecord, start: Instant, first_read: Option<Instant>, bytes: u64, complete: bool) {
+    let now = Instant::now();
+    record.timing.body = first_read.map_or(Duration::ZERO, |first| now.duration_since(first));
+    record.timing.total = now.duration_since(start);
+    record.response_bytes = bytes;
+    record.aborted = !complete;
+    access_log::log_request(&record);
+    connections::request_finished(&record.listener, record.remote, record.response_bytes);
+    let record = Arc::new(record);
+
+    {
+        let mut recent = RECENT_REQUESTS.lock().unwrap();
+        if recent.len() == RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(record.clone());
+    }
+
+    if EXPORT_ENABLED.load(Ordering::Relaxed) {
+        let mut queue = SPAN_QUEUE.lock().unwrap();
+        if queue.len() < MAX_QUEUED_SPANS {
+            queue.push(record);
+        } else {
+            DROPPED_SPANS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Start, restart or stop the OTLP exporter. Must run inside the Tokio runtime.
+pub fn apply_tracing(config: &TracingConfig) {
+    let mut exporter = EXPORTER.lock().unwrap();
+    if exporter.as_ref().is_some_and(|(current, _)| current == config) {
+        return;
+    }
+    if let Some((_, task)) = exporter.take() {
+        task.abort();
+    }
+    EXPORT_ENABLED.store(config.otlp_enabled, Ordering::Relaxed);
+    if !config.otlp_enabled {
+        SPAN_QUEUE.lock().unwrap().clear();
+        return;
+    }
+    let task = tokio::spawn(export_loop(config.clone()));
+    *exporter = Some((config.clone(), task));
+}
+
+pub fn clear_tracing() {
+    if let Some((_, task)) = EXPORTER.lock().unwrap().take() {
+        task.abort();
+    }
+    EXPORT_ENABLED.store(false, Ordering::Relaxed);
+    SPAN_QUEUE.lock().unwrap().clear();
+}
+
+async fn export_loop(config: TracingConfig) {
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(EXPORT_INTERVAL);
+    loop {
+        interval.tick().await;
+        let spans = std::mem::take(&mut *SPAN_QUEUE.lock().unwrap());
+        let dropped = DROPPED_SPANS.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!("Dropped {} spans because the trace collector is not keeping up", dropped);
+        }
+        if spans.is_empty() {
+            continue;
+        }
+        let result = client
+            .post(&config.otlp_endpoint)
+            .json(&otlp_request(&config, &spans))
+            .timeout(EXPORT_INTERVAL)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            eprintln!("Failed to export {} spans to {}: {}", spans.len(), config.otlp_endpoint, e);
+        }
+    }
+}
+
+// ExportTraceServiceRequest in the OTLP/HTTP JSON encoding
+fn otlp_request(config: &TracingConfig, spans: &[Arc<RequestRecord>]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", json!({ "stringValue": config.service_name }))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans.iter().map(|record| otlp_span(record)).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn otlp_span(record: &RequestRecord) -> Value {
+    let start = record.started.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let nanos = |offset: Duration| ((start + offset).as_nanos() as u64).to_string();
+    let timing = &record.timing;
+    let handler_start = timing.routing;
+    let handler_end = handler_start + timing.handler;
+    let body_start = timing.total.saturating_sub(timing.body);
+    let (path, query) = record.uri.split_once('?').map_or((record.uri.as_str(), None), |(path, query)| (path, Some(query)));
+
+    let mut attributes = vec![
+        attribute("http.request.method", json!({ "stringValue": record.method })),
+        attribute("url.path", json!({ "stringValue": path })),
+        attribute("http.response.status_code", json!({ "intValue": record.status.to_string() })),
+        attribute("solara.request_id", json!({ "stringValue": record.request_id })),
+        attribute("solara.listener", json!({ "stringValue": record.listener })),
+    ];
+    if let Some(query) = query {
+        attributes.push(attribute("url.query", json!({ "stringValue": query })));
+    }
+    if let Some(route) = &record.route {
+        attributes.push(attribute("http.route", json!({ "stringValue": route })));
+    }
+    if let Some(remote) = record.remote {
+        attributes.push(attribute("client.address", json!({ "stringValue": remote.ip().to_string() })));
+    }
+
+    json!({
+        "traceId": record.trace_id,
+        "spanId": record.span_id,
+        "parentSpanId": record.parent_span_id.clone().unwrap_or_default(),
+        "name": format!("{} {}", record.method, record.route.as_deref().unwrap_or("unmatched")),
+        // SPAN_KIND_SERVER
+        "kind": 2,
+        "startTimeUnixNano": nanos(Duration::ZERO),
+        "endTimeUnixNano": nanos(timing.total),
+        "attributes": attributes,
+        "events": [
+            { "name": "handler.start", "timeUnixNano": nanos(handler_start) },
+            { "name": "handler.end", "timeUnixNano": nanos(handler_end) },
+            { "name": "body.start", "timeUnixNano": nanos(body_start) },
+        ],
+        // STATUS_CODE_ERROR for server errors, unset otherwise
+        "status": { "code": if record.status >= 500 { 2 } else { 0 } },
+    })
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn redact(headers: impl Iterator<Item = (String, String)>) -> Vec<(String, String)> {
+    headers
+        .map(|(name, value)| {
+            if REDACTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) || api_keys::is_key_header(&name) {
+                (name, "[redacted]".to_string())
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+// Accept client supplied IDs that are safe to echo back and log
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+// W3C trace context, "00-<trace id>-<parent span id>-<flags>"
+fn parse_traceparent(value: &str) -> Option<(String, String)> {
+    let mut parts = value.trim().split('-');
+    let (_version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+    if !is_hex(trace_id, 32) || !is_hex(span_id, 16) || trace_id.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    Some((trace_id.to_ascii_lowercase(), span_id.to_ascii_lowercase()))
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uri: &str) -> RequestRecord {
+        RequestRecord {
+            request_id: "id".to_string(),
+            trace_id: "0".repeat(32),
+            span_id: "0".repeat(16),
+            parent_span_id: None,
+            listener: "main".to_string(),
+            remote: None,
+            user: None,
+            method: "GET".to_string(),
+            uri: uri.to_string(),
+            route: None,
+            request_headers: Vec::new(),
+            status: 200,
+            response_headers: Vec::new(),
+            response_bytes: 0,
+            aborted: false,
+            started: SystemTime::now(),
+            timing: Timing::default(),
+        }
+    }
+
+    fn string_attribute(span: &Value, key: &str) -> Option<String> {
+        span["attributes"]
+            .as_array()?
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .and_then(|attribute| attribute["value"]["stringValue"].as_str())
+            .map(str::to_string)
+    }
+
+    #[test]
+    fn span_url_path_leaves_out_the_query() {
+        let span = otlp_span(&record("/reports/1?page=2&api_key=[redacted]"));
+        assert_eq!(string_attribute(&span, "url.path").as_deref(), Some("/reports/1"));
+        assert_eq!(string_attribute(&span, "url.query").as_deref(), Some("page=2&api_key=[redacted]"));
+
+        let span = otlp_span(&record("/reports"));
+        assert_eq!(string_attribute(&span, "url.path").as_deref(), Some("/reports"));
+        assert_eq!(string_attribute(&span, "url.query"), None);
+    }
+
+    #[test]
+    fn credential_headers_are_redacted() {
+        let headers = redact(
+            [("Authorization", "Bearer token"), ("Accept", "text/html"), ("Cookie", "a=b")]
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        assert_eq!(headers[0].1, "[redacted]");
+        assert_eq!(headers[1].1, "text/html");
+        assert_eq!(headers[2].1, "[redacted]");
+    }
+}
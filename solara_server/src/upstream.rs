@@ -0,0 +1,734 @@
+use rocket::http::{Header, HeaderMap, Method, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::route::{Handler, Outcome, Route};
+use rocket::data::{Data, ToByteUnit};
+use rocket::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use crate::api_keys;
+use crate::metrics;
+use crate::sessions::SESSION_COOKIE;
+
+// Pools registered by the running server, looked up per request so they can be swapped live
+static UPSTREAM_POOLS: RwLock<Vec<Arc<UpstreamPool>>> = RwLock::new(Vec::new());
+
+// Active health check tasks for the registered pools
+static HEALTH_CHECK_TASKS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+// Maximum request body forwarded to a backend
+const MAX_PROXY_BODY_MIB: u64 = 32;
+
+// Headers that only apply to a single connection and must not be forwarded
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    RoundRobin,
+    LeastConnections,
+    Weighted,
+    IpHash,
+}
+
+impl fmt::Display for BalanceStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BalanceStrategy::RoundRobin => write!(f, "Round Robin"),
+            BalanceStrategy::LeastConnections => write!(f, "Least Connections"),
+            BalanceStrategy::Weighted => write!(f, "Weighted"),
+            BalanceStrategy::IpHash => write!(f, "IP Hash"),
+        }
+    }
+}
+
+// A pool of backends that requests under `path_prefix` are proxied to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    pub name: String,
+    pub path_prefix: String,
+    pub strategy: BalanceStrategy,
+    pub backends: Vec<BackendConfig>,
+    pub health_check: Option<HealthCheckConfig>,
+    // Passive ejection: consecutive failures before a backend is taken out
+    pub max_fails: u32,
+    // How long an ejected backend stays out of rotation
+    pub fail_timeout_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            path_prefix: "/proxy".to_string(),
+            strategy: BalanceStrategy::RoundRobin,
+            backends: Vec::new(),
+            health_check: None,
+            max_fails: 3,
+            fail_timeout_secs: 30,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackendConfig {
+    // host:port of the backend, plain HTTP
+    pub address: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+// Active health check, an HTTP GET sent to every backend on an interval
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckConfig {
+    pub path: String,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            path: "/health".to_string(),
+            interval_secs: 10,
+            timeout_secs: 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendHealth {
+    Healthy,
+    // Failed its last active health check
+    Unhealthy,
+    // Taken out after too many consecutive proxy failures
+    Ejected,
+}
+
+impl fmt::Display for BackendHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendHealth::Healthy => write!(f, "Healthy"),
+            BackendHealth::Unhealthy => write!(f, "Unhealthy"),
+            BackendHealth::Ejected => write!(f, "Ejected"),
+        }
+    }
+}
+
+// Snapshot of a single backend for monitoring
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackendStats {
+    pub address: String,
+    pub weight: u32,
+    pub health: BackendHealth,
+    pub in_flight: usize,
+    pub total_requests: u64,
+    pub failed_requests: u64,
+    pub avg_latency_ms: f64,
+}
+
+// Snapshot of a pool for monitoring
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub name: String,
+    pub path_prefix: String,
+    pub strategy: BalanceStrategy,
+    pub backends: Vec<BackendStats>,
+}
+
+struct Backend {
+    address: String,
+    weight: u32,
+    healthy: AtomicBool,
+    in_flight: AtomicUsize,
+    total_requests: AtomicU64,
+    failed_requests: AtomicU64,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    // Exponentially weighted moving average of the response latency
+    latency_micros: AtomicU64,
+}
+
+struct BackendInFlight<'a>(&'a Backend);
+
+impl Drop for BackendInFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Backend {
+    fn new(config: &BackendConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            weight: config.weight.max(1),
+            healthy: AtomicBool::new(true),
+            in_flight: AtomicUsize::new(0),
+            total_requests: AtomicU64::new(0),
+            failed_requests: AtomicU64::new(0),
+            consecutive_failures: AtomicU32::new(0),
+            ejected_until: Mutex::new(None),
+            latency_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn health(&self) -> BackendHealth {
+        let mut ejected_until = self.ejected_until.lock().unwrap();
+        if let Some(until) = *ejected_until {
+            if Instant::now() < until {
+                return BackendHealth::Ejected;
+            }
+            // Ejection expired, give the backend another chance
+            *ejected_until = None;
+            self.consecutive_failures.store(0, Ordering::SeqCst);
+        }
+        if self.healthy.load(Ordering::SeqCst) {
+            BackendHealth::Healthy
+        } else {
+            BackendHealth::Unhealthy
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        self.health() == BackendHealth::Healthy
+    }
+
+    fn record_latency(&self, elapsed: Duration) {
+        let sample = elapsed.as_micros() as u64;
+        let previous = self.latency_micros.load(Ordering::SeqCst);
+        let updated = if previous == 0 { sample } else { (previous * 7 + sample) / 8 };
+        self.latency_micros.store(updated, Ordering::SeqCst);
+    }
+}
+
+pub struct UpstreamPool {
+    config: UpstreamConfig,
+    backends: Vec<Backend>,
+    next_index: AtomicUsize,
+    // Current weights for smooth weighted round-robin
+    current_weights: Mutex<Vec<i64>>,
+}
+
+impl UpstreamPool {
+    pub fn new(config: UpstreamConfig) -> Self {
+        let backends: Vec<Backend> = config.backends.iter().map(Backend::new).collect();
+        let current_weights = Mutex::new(vec![0; backends.len()]);
+        Self {
+            config,
+            backends,
+            next_index: AtomicUsize::new(0),
+            current_weights,
+        }
+    }
+
+    // Pick a backend for the next request, None if every backend is down
+    pub fn select(&self, client: Option<IpAddr>) -> Option<usize> {
+        let available: Vec<usize> = (0..self.backends.len())
+            .filter(|&i| self.backends[i].is_available())
+            .collect();
+        if available.is_empty() {
+            return None;
+        }
+
+        let index = match self.config.strategy {
+            BalanceStrategy::RoundRobin => {
+                let n = self.next_index.fetch_add(1, Ordering::SeqCst);
+                available[n % available.len()]
+            }
+            BalanceStrategy::LeastConnections => *available
+                .iter()
+                .min_by_key(|&&i| {
+                    let backend = &self.backends[i];
+                    // Scale by weight so heavier backends take proportionally more
+                    backend.in_flight.load(Ordering::SeqCst) * 1000 / backend.weight as usize
+                })
+                .unwrap(),
+            BalanceStrategy::Weighted => {
+                let mut current = self.current_weights.lock().unwrap();
+                let total: i64 = available.iter().map(|&i| self.backends[i].weight as i64).sum();
+                let mut best = available[0];
+                for &i in &available {
+                    current[i] += self.backends[i].weight as i64;
+                    if current[i] > current[best] {
+                        best = i;
+                    }
+                }
+                current[best] -= total;
+                best
+            }
+            BalanceStrategy::IpHash => {
+                let mut hasher = DefaultHasher::new();
+                client.hash(&mut hasher);
+                // Hash over all backends so a client keeps its backend while it is up
+                let preferred = hasher.finish() as usize % self.backends.len();
+                (0..self.backends.len())
+                    .map(|offset| (preferred + offset) % self.backends.len())
+                    .find(|i| available.contains(i))
+                    .unwrap()
+            }
+        };
+        Some(index)
+    }
+
+    // The returned guard keeps the backend's in-flight count up until it is dropped,
+    // so requests cancelled by a timeout are still released
+    fn begin_request(&self, index: usize) -> BackendInFlight<'_> {
+        let backend = &self.backends[index];
+        backend.in_flight.fetch_add(1, Ordering::SeqCst);
+        backend.total_requests.fetch_add(1, Ordering::SeqCst);
+        BackendInFlight(backend)
+    }
+
+    fn finish_request(&self, index: usize, elapsed: Duration, success: bool) {
+        let backend = &self.backends[index];
+        backend.record_latency(elapsed);
+        if success {
+            backend.consecutive_failures.store(0, Ordering::SeqCst);
+            return;
+        }
+
+        backend.failed_requests.fetch_add(1, Ordering::SeqCst);
+        let failures = backend.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= self.config.max_fails.max(1) {
+            println!("Ejecting backend {} from pool '{}' after {} consecutive failures",
+                backend.address, self.config.name, failures);
+            let until = Instant::now() + Duration::from_secs(self.config.fail_timeout_secs);
+            *backend.ejected_until.lock().unwrap() = Some(until);
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.config.name.clone(),
+            path_prefix: self.config.path_prefix.clone(),
+            strategy: self.config.strategy,
+            backends: self.backends.iter().map(|backend| BackendStats {
+                address: backend.address.clone(),
+                weight: backend.weight,
+                health: backend.health(),
+                in_flight: backend.in_flight.load(Ordering::SeqCst),
+                total_requests: backend.total_requests.load(Ordering::SeqCst),
+                failed_requests: backend.failed_requests.load(Ordering::SeqCst),
+                avg_latency_ms: backend.latency_micros.load(Ordering::SeqCst) as f64 / 1000.0,
+            }).collect(),
+        }
+    }
+
+    // Probe every backend once and update its health flag
+    async fn check_health(&self, client: &reqwest::Client, check: &HealthCheckConfig) {
+        for backend in &self.backends {
+            let url = format!("http://{}{}", backend.address, check.path);
+            let started = Instant::now();
+            let result = client
+                .get(&url)
+                .timeout(Duration::from_secs(check.timeout_secs))
+                .send()
+                .await;
+            let healthy = matches!(&result, Ok(response) if response.status().is_success());
+            if healthy {
+                backend.record_latency(started.elapsed());
+            }
+            if backend.healthy.swap(healthy, Ordering::SeqCst) != healthy {
+                println!("Backend {} in pool '{}' is now {}", backend.address, self.config.name,
+                    if healthy { "healthy" } else { "unhealthy" });
+            }
+        }
+    }
+}
+
+// Build pools from the configuration and swap them in, restarting health checks.
+// Pools whose configuration did not change are kept so their statistics survive a reload.
+// Must be called from within the Tokio runtime.
+pub fn install_pools(configs: &[UpstreamConfig]) {
+    let mut registered = UPSTREAM_POOLS.write().unwrap();
+    let pools: Vec<Arc<UpstreamPool>> = configs
+        .iter()
+        .map(|config| {
+            registered
+                .iter()
+                .find(|pool| pool.config == *config)
+                .cloned()
+                .unwrap_or_else(|| Arc::new(UpstreamPool::new(config.clone())))
+        })
+        .collect();
+
+    let mut tasks = HEALTH_CHECK_TASKS.lock().unwrap();
+    for task in tasks.drain(..) {
+        task.abort();
+    }
+    for pool in &pools {
+        println!("Proxying {} to pool '{}' ({}, {} backends)", pool.config.path_prefix,
+            pool.config.name, pool.config.strategy, pool.config.backends.len());
+        tasks.push(tokio::spawn(run_health_checks(pool.clone())));
+    }
+    *registered = pools;
+}
+
+pub fn clear_pools() {
+    for task in HEALTH_CHECK_TASKS.lock().unwrap().drain(..) {
+        task.abort();
+    }
+    UPSTREAM_POOLS.write().unwrap().clear();
+}
+
+fn find_pool(path: &str) -> Option<Arc<UpstreamPool>> {
+    longest_prefix(&UPSTREAM_POOLS.read().unwrap(), path).cloned()
+}
+
+// Pool with the longest prefix covering the path, if any
+fn longest_prefix<'a>(pools: &'a [Arc<UpstreamPool>], path: &str) -> Option<&'a Arc<UpstreamPool>> {
+    pools
+        .iter()
+        .filter(|pool| path_has_prefix(path, &pool.config.path_prefix))
+        .max_by_key(|pool| pool.config.path_prefix.len())
+}
+
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+pub fn pool_stats() -> Vec<PoolStats> {
+    UPSTREAM_POOLS.read().unwrap().iter().map(|pool| pool.stats()).collect()
+}
+
+// Run active health checks for a pool until the task is aborted
+async fn run_health_checks(pool: Arc<UpstreamPool>) {
+    let Some(check) = pool.config.health_check.clone() else {
+        return;
+    };
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(check.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        pool.check_health(&client, &check).await;
+    }
+}
+
+// Catch-all routes for every method. They rank below the built-in routes and pick the
+// pool at request time, forwarding to the next route when no pool covers the path.
+pub fn proxy_routes() -> Vec<Route> {
+    let handler = ProxyHandler {
+        client: reqwest::Client::new(),
+    };
+    [Method::Get, Method::Put, Method::Post, Method::Delete,
+     Method::Options, Method::Head, Method::Patch]
+        .into_iter()
+        .map(|method| Route::ranked(20, method, "/<path..>", handler.clone()))
+        .collect()
+}
+
+#[derive(Clone)]
+struct ProxyHandler {
+    client: reqwest::Client,
+}
+
+#[rocket::async_trait]
+impl Handler for ProxyHandler {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let Some(pool) = find_pool(req.uri().path().as_str()) else {
+            return Outcome::Forward((data, Status::NotFound));
+        };
+
+        let body = match metrics::read_body(data, MAX_PROXY_BODY_MIB.mebibytes()).await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => return Outcome::from(req, Status::PayloadTooLarge),
+            Err(_) => return Outcome::from(req, Status::BadRequest),
+        };
+
+        let Some(index) = pool.select(req.client_ip()) else {
+            eprintln!("No healthy backend available in pool '{}'", pool.config.name);
+            return Outcome::from(req, Status::ServiceUnavailable);
+        };
+        let backend = &pool.backends[index];
+        let url = format!("http://{}{}", backend.address, api_keys::uri_without_key(req.uri()));
+
+        let method = match reqwest::Method::from_bytes(req.method().as_str().as_bytes()) {
+            Ok(method) => method,
+            Err(_) => return Outcome::from(req, Status::MethodNotAllowed),
+        };
+        let mut request = self.client
+            .request(method, &url)
+            .timeout(Duration::from_secs(pool.config.timeout_secs))
+            .body(body);
+        for (name, value) in forwarded_headers(req.headers()) {
+            request = request.header(name, value);
+        }
+        if let Some(host) = req.host() {
+            request = request.header("X-Forwarded-Host", host.to_string());
+        }
+        if let Some(ip) = req.client_ip() {
+            request = request.header("X-Forwarded-For", ip.to_string());
+        }
+
+        let in_flight = pool.begin_request(index);
+        let started = Instant::now();
+        let result = forward(request).await;
+        let success = matches!(&result, Ok(response) if response.status.code < 502);
+        pool.finish_request(index, started.elapsed(), success);
+        drop(in_flight);
+
+        match result {
+            Ok(response) => Outcome::from(req, response),
+            Err(e) => {
+                eprintln!("Proxy request to {} failed: {}", backend.address, e);
+                let status = if e.is_timeout() { Status::GatewayTimeout } else { Status::BadGateway };
+                Outcome::from(req, status)
+            }
+        }
+    }
+}
+
+async fn forward(request: reqwest::RequestBuilder) -> Result<ProxyResponse, reqwest::Error> {
+    let response = request.send().await?;
+    let status = Status::new(response.status().as_u16());
+    let headers = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !is_hop_by_hop(name.as_str()) && name.as_str() != "content-length")
+        .filter_map(|(name, value)| {
+            value.to_str().ok().map(|value| (name.to_string(), value.to_string()))
+        })
+        .collect();
+    let body = response.bytes().await?.to_vec();
+    Ok(ProxyResponse { status, headers, body })
+}
+
+// Request headers passed on to a backend. Credentials the server checked itself stay
+// behind, other cookies go through.
+fn forwarded_headers(headers: &HeaderMap<'_>) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|header| {
+            let name = header.name().as_str();
+            !is_hop_by_hop(name) && !name.eq_ignore_ascii_case("authorization") && !api_keys::is_key_header(name)
+        })
+        .filter_map(|header| {
+            let name = header.name().to_string();
+            if !name.eq_ignore_ascii_case("cookie") {
+                return Some((name, header.value().to_string()));
+            }
+            let cookies = header
+                .value()
+                .split(';')
+                .map(str::trim)
+                .filter(|cookie| !cookie.is_empty() && cookie.split('=').next() != Some(SESSION_COOKIE))
+                .collect::<Vec<_>>();
+            (!cookies.is_empty()).then(|| (name, cookies.join("; ")))
+        })
+        .collect()
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+// Response received from a backend, replayed to the client
+struct ProxyResponse {
+    status: Status,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for ProxyResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut builder = Response::build();
+        builder.status(self.status);
+        for (name, value) in self.headers {
+            builder.header_adjoin(Header::new(name, value));
+        }
+        builder.sized_body(self.body.len(), Cursor::new(self.body)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(strategy: BalanceStrategy, weights: &[u32]) -> UpstreamPool {
+        UpstreamPool::new(UpstreamConfig {
+            strategy,
+            backends: weights
+                .iter()
+                .enumerate()
+                .map(|(i, &weight)| BackendConfig { address: format!("10.0.0.{}:80", i + 1), weight })
+                .collect(),
+            ..UpstreamConfig::default()
+        })
+    }
+
+    fn picks(pool: &UpstreamPool, count: usize) -> Vec<usize> {
+        (0..count).map(|_| pool.select(None).unwrap()).collect()
+    }
+
+    #[test]
+    fn round_robin_skips_unavailable_backends() {
+        let pool = pool(BalanceStrategy::RoundRobin, &[1, 1, 1]);
+        assert_eq!(picks(&pool, 6), [0, 1, 2, 0, 1, 2]);
+
+        pool.backends[1].healthy.store(false, Ordering::SeqCst);
+        let picked = picks(&pool, 4);
+        assert!(!picked.contains(&1));
+        assert!(picked.contains(&0) && picked.contains(&2));
+
+        for backend in &pool.backends {
+            backend.healthy.store(false, Ordering::SeqCst);
+        }
+        assert_eq!(pool.select(None), None);
+    }
+
+    #[test]
+    fn least_connections_prefers_idle_backends() {
+        let pool = pool(BalanceStrategy::LeastConnections, &[1, 1]);
+        let first = pool.begin_request(0);
+        assert_eq!(pool.select(None), Some(1));
+        let second = pool.begin_request(1);
+        let third = pool.begin_request(1);
+        assert_eq!(pool.select(None), Some(0));
+        drop((first, second, third));
+        assert_eq!(pool.backends[0].in_flight.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.backends[1].in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn least_connections_scales_by_weight() {
+        let pool = pool(BalanceStrategy::LeastConnections, &[3, 1]);
+        // Two requests on the heavier backend still count for less than one on the lighter
+        let _guards = [pool.begin_request(0), pool.begin_request(0), pool.begin_request(1)];
+        assert_eq!(pool.select(None), Some(0));
+    }
+
+    #[test]
+    fn weighted_is_smooth() {
+        let pool = pool(BalanceStrategy::Weighted, &[5, 1, 1]);
+        assert_eq!(picks(&pool, 7), [0, 0, 1, 0, 2, 0, 0]);
+        assert_eq!(picks(&pool, 7), [0, 0, 1, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn weighted_shares_follow_weights() {
+        let pool = pool(BalanceStrategy::Weighted, &[3, 2, 1]);
+        let picked = picks(&pool, 600);
+        let shares: Vec<usize> = (0..3).map(|i| picked.iter().filter(|&&p| p == i).count()).collect();
+        assert_eq!(shares, [300, 200, 100]);
+    }
+
+    #[test]
+    fn ip_hash_keeps_clients_on_their_backend() {
+        let pool = pool(BalanceStrategy::IpHash, &[1, 1, 1, 1]);
+        let client: IpAddr = "192.0.2.7".parse().unwrap();
+        let preferred = pool.select(Some(client)).unwrap();
+        for _ in 0..10 {
+            assert_eq!(pool.select(Some(client)), Some(preferred));
+        }
+
+        // Moves to the next backend while its own is down and comes back afterwards
+        pool.backends[preferred].healthy.store(false, Ordering::SeqCst);
+        assert_eq!(pool.select(Some(client)), Some((preferred + 1) % 4));
+        pool.backends[preferred].healthy.store(true, Ordering::SeqCst);
+        assert_eq!(pool.select(Some(client)), Some(preferred));
+    }
+
+    #[test]
+    fn passive_ejection_and_recovery() {
+        let pool = pool(BalanceStrategy::RoundRobin, &[1, 1]);
+        for _ in 0..2 {
+            pool.finish_request(0, Duration::from_millis(5), false);
+        }
+        assert_eq!(pool.backends[0].health(), BackendHealth::Healthy);
+
+        // A success resets the count of consecutive failures
+        pool.finish_request(0, Duration::from_millis(5), true);
+        for _ in 0..2 {
+            pool.finish_request(0, Duration::from_millis(5), false);
+        }
+        assert_eq!(pool.backends[0].health(), BackendHealth::Healthy);
+
+        pool.finish_request(0, Duration::from_millis(5), false);
+        assert_eq!(pool.backends[0].health(), BackendHealth::Ejected);
+        assert_eq!(picks(&pool, 3), [1, 1, 1]);
+
+        // Back in rotation once fail_timeout_secs have passed
+        *pool.backends[0].ejected_until.lock().unwrap() = Some(Instant::now() - Duration::from_secs(1));
+        assert_eq!(pool.backends[0].health(), BackendHealth::Healthy);
+        assert_eq!(pool.backends[0].consecutive_failures.load(Ordering::SeqCst), 0);
+        assert!(picks(&pool, 2).contains(&0));
+
+        let stats = pool.stats();
+        assert_eq!(stats.backends[0].failed_requests, 5);
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        assert!(path_has_prefix("/proxy", "/proxy"));
+        assert!(path_has_prefix("/proxy/", "/proxy"));
+        assert!(path_has_prefix("/proxy/a/b", "/proxy/"));
+        assert!(!path_has_prefix("/proxyfoo", "/proxy"));
+        assert!(!path_has_prefix("/prox", "/proxy"));
+        assert!(path_has_prefix("/anything", "/"));
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let config = |name: &str, path_prefix: &str| UpstreamConfig {
+            name: name.to_string(),
+            path_prefix: path_prefix.to_string(),
+            ..UpstreamConfig::default()
+        };
+        let pools = vec![
+            Arc::new(UpstreamPool::new(config("api", "/api"))),
+            Arc::new(UpstreamPool::new(config("api-v2", "/api/v2/"))),
+        ];
+        let name = |path: &str| longest_prefix(&pools, path).map(|pool| pool.config.name.clone());
+        assert_eq!(name("/api/v1/users").as_deref(), Some("api"));
+        assert_eq!(name("/api/v2/users").as_deref(), Some("api-v2"));
+        assert_eq!(name("/api/v2").as_deref(), Some("api-v2"));
+        assert_eq!(name("/api/v20").as_deref(), Some("api"));
+        assert_eq!(name("/apis"), None);
+    }
+
+    #[test]
+    fn credentials_are_not_forwarded() {
+        let mut headers = HeaderMap::new();
+        headers.add_raw("Accept", "text/html");
+        headers.add_raw("Authorization", "Bearer secret");
+        headers.add_raw("Connection", "keep-alive");
+        headers.add_raw("Cookie", "theme=dark; solara_session=secret; lang=en");
+        headers.add_raw("Cookie", "solara_session=secret");
+        let mut forwarded = forwarded_headers(&headers);
+        forwarded.sort();
+        assert_eq!(forwarded, vec![
+            ("Accept".to_string(), "text/html".to_string()),
+            ("Cookie".to_string(), "theme=dark; lang=en".to_string()),
+        ]);
+    }
+}
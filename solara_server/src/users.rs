@@ -0,0 +1,333 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use crate::file_writer;
+use crate::password;
+
+// Users loaded from the users file, shared by the GUI and the server
+static USER_STORE: RwLock<Option<UserStore>> = RwLock::new(None);
+
+// Failed logins per username, for the lockout
+static FAILED_LOGINS: Mutex<Option<HashMap<String, FailedLogins>>> = Mutex::new(None);
+
+// Credentials that passed Argon2 recently, so Basic auth doesn't hash on every request
+static VERIFIED: Mutex<Option<HashMap<String, Verified>>> = Mutex::new(None);
+const VERIFIED_TTL: Duration = Duration::from_secs(300);
+
+// SHA-256 of the credentials and when they were checked
+type Verified = (Vec<u8>, Instant);
+
+// Failure counts kept before unlocked entries are dropped, bounds memory under a password spray
+const MAX_TRACKED_FAILURES: usize = 10_000;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct User {
+    pub username: String,
+    // Argon2id PHC string
+    pub password_hash: String,
+    pub disabled: bool,
+    // Checked against the access rules
+    pub roles: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct UsersFile {
+    users: Vec<User>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct UserStore {
+    path: PathBuf,
+    users: Vec<User>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FailedLogins {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+// Lockout settings, taken from the auth configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lockout {
+    // Failures in a row before the account is locked, 0 disables the lockout
+    pub max_failures: u32,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub enum UserError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    NoStore,
+    InvalidUsername,
+    InvalidRole(String),
+    UserExists(String),
+    UnknownUser(String),
+    PasswordTooShort,
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserError::Io(e) => write!(f, "I/O error: {}", e),
+            UserError::Parse(e) => write!(f, "Invalid users file: {}", e),
+            UserError::Serialize(e) => write!(f, "Failed to serialize users: {}", e),
+            UserError::NoStore => write!(f, "No users file is loaded"),
+            UserError::InvalidUsername => write!(f, "Usernames must be non-empty and can't contain ':' or whitespace"),
+            UserError::InvalidRole(role) => write!(f, "Role '{}' is invalid, roles can't contain ',' or whitespace", role),
+            UserError::UserExists(name) => write!(f, "User '{}' already exists", name),
+            UserError::UnknownUser(name) => write!(f, "User '{}' does not exist", name),
+            UserError::PasswordTooShort => write!(f, "Passwords need at least {} characters", MIN_PASSWORD_LEN),
+        }
+    }
+}
+
+impl std::error::Error for UserError {}
+
+// Outcome of checking a username and password
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoginResult {
+    Success,
+    Invalid,
+    // Too many failures, retry after the given time
+    Locked(Duration),
+}
+
+// Read the users file. A missing file is an empty store, created on the first change.
+pub fn read_users(path: &Path) -> Result<Vec<User>, UserError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str::<UsersFile>(&contents).map(|file| file.users).map_err(UserError::Parse),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(UserError::Io(e)),
+    }
+}
+
+// Load the users file into the shared store, replacing what was loaded before
+pub fn load_users(path: &Path) -> Result<(), UserError> {
+    let users = read_users(path)?;
+    *USER_STORE.write().unwrap() = Some(UserStore { path: path.to_path_buf(), users });
+    VERIFIED.lock().unwrap().take();
+    Ok(())
+}
+
+pub fn users_path() -> Option<PathBuf> {
+    USER_STORE.read().unwrap().as_ref().map(|store| store.path.clone())
+}
+
+pub fn users() -> Vec<User> {
+    USER_STORE.read().unwrap().as_ref().map_or_else(Vec::new, |store| store.users.clone())
+}
+
+pub fn valid_username(username: &str) -> bool {
+    !username.is_empty() && !username.contains(|c: char| c == ':' || c.is_whitespace())
+}
+
+// Roles are listed comma separated in the GUI
+pub fn valid_role(role: &str) -> bool {
+    !role.is_empty() && !role.contains(|c: char| c == ',' || c.is_whitespace())
+}
+
+// Roles of an active user, None for unknown or disabled users
+pub fn roles(username: &str) -> Option<Vec<String>> {
+    let store = USER_STORE.read().unwrap();
+    let user = store.as_ref()?.users.iter().find(|user| user.username == username && !user.disabled)?;
+    Some(user.roles.clone())
+}
+
+// Apply a change to the store and write it back to the file
+fn modify(change: impl FnOnce(&mut Vec<User>) -> Result<(), UserError>) -> Result<(), UserError> {
+    let mut guard = USER_STORE.write().unwrap();
+    let store = guard.as_mut().ok_or(UserError::NoStore)?;
+    let mut users = store.users.clone();
+    change(&mut users)?;
+    let contents = toml::to_string_pretty(&UsersFile { users: users.clone() }).map_err(UserError::Serialize)?;
+    file_writer::write_private(&store.path, contents.as_bytes()).map_err(UserError::Io)?;
+    store.users = users;
+    Ok(())
+}
+
+pub fn add_user(username: &str, password: &str) -> Result<(), UserError> {
+    if !valid_username(username) {
+        return Err(UserError::InvalidUsername);
+    }
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(UserError::PasswordTooShort);
+    }
+    let password_hash = password::hash_password(password);
+    modify(|users| {
+        if users.iter().any(|user| user.username == username) {
+            return Err(UserError::UserExists(username.to_string()));
+        }
+        users.push(User { username: username.to_string(), password_hash, disabled: false, roles: Vec::new() });
+        Ok(())
+    })
+}
+
+pub fn remove_user(username: &str) -> Result<(), UserError> {
+    modify(|users| {
+        let before = users.len();
+        users.retain(|user| user.username != username);
+        if users.len() == before {
+            return Err(UserError::UnknownUser(username.to_string()));
+        }
+        Ok(())
+    })?;
+    forget_credentials(username);
+    Ok(())
+}
+
+pub fn set_password(username: &str, password: &str) -> Result<(), UserError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(UserError::PasswordTooShort);
+    }
+    let password_hash = password::hash_password(password);
+    update_user(username, |user| user.password_hash = password_hash)?;
+    forget_credentials(username);
+    Ok(())
+}
+
+pub fn set_disabled(username: &str, disabled: bool) -> Result<(), UserError> {
+    update_user(username, |user| user.disabled = disabled)?;
+    forget_credentials(username);
+    Ok(())
+}
+
+pub fn set_roles(username: &str, roles: Vec<String>) -> Result<(), UserError> {
+    if let Some(role) = roles.iter().find(|role| !valid_role(role)) {
+        return Err(UserError::InvalidRole(role.clone()));
+    }
+    update_user(username, |user| user.roles = roles)
+}
+
+fn update_user(username: &str, change: impl FnOnce(&mut User)) -> Result<(), UserError> {
+    modify(|users| {
+        let user = users
+            .iter_mut()
+            .find(|user| user.username == username)
+            .ok_or_else(|| UserError::UnknownUser(username.to_string()))?;
+        change(user);
+        Ok(())
+    })
+}
+
+fn forget_credentials(username: &str) {
+    if let Some(verified) = VERIFIED.lock().unwrap().as_mut() {
+        verified.remove(username);
+    }
+}
+
+// Check a username and password. Slow on purpose, call it off the async runtime.
+pub fn authenticate(username: &str, password: &str, lockout: Lockout) -> LoginResult {
+    if let Some(remaining) = locked_for(username) {
+        return LoginResult::Locked(remaining);
+    }
+
+    let fingerprint = Sha256::digest(format!("{}:{}", username, password).as_bytes()).to_vec();
+    let cached = VERIFIED
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|verified| verified.get(username).cloned())
+        .is_some_and(|(known, at)| at.elapsed() < VERIFIED_TTL && password::constant_time_eq(&known, &fingerprint));
+    if cached {
+        return LoginResult::Success;
+    }
+
+    let user = users().into_iter().find(|user| user.username == username);
+    // Unknown users take as long as known ones so usernames can't be probed by timing
+    let valid = match &user {
+        Some(user) => password::verify_password(password, &user.password_hash) && !user.disabled,
+        None => {
+            password::verify_password(password, DUMMY_HASH);
+            false
+        }
+    };
+
+    let mut failures = FAILED_LOGINS.lock().unwrap();
+    let failures = failures.get_or_insert_with(HashMap::new);
+    if valid {
+        failures.remove(username);
+        VERIFIED
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(username.to_string(), (fingerprint, Instant::now()));
+        return LoginResult::Success;
+    }
+
+    if failures.len() >= MAX_TRACKED_FAILURES {
+        let now = Instant::now();
+        failures.retain(|_, entry| entry.locked_until.is_some_and(|until| until > now));
+    }
+    let entry = failures.entry(username.to_string()).or_default();
+    entry.count += 1;
+    if lockout.max_failures > 0 && entry.count >= lockout.max_failures {
+        entry.locked_until = Some(Instant::now() + lockout.duration);
+        entry.count = 0;
+        eprintln!("Locked user '{}' for {:?} after {} failed logins", username, lockout.duration, lockout.max_failures);
+        return LoginResult::Locked(lockout.duration);
+    }
+    LoginResult::Invalid
+}
+
+// Time left on a lockout, None when logins are allowed
+pub fn locked_for(username: &str) -> Option<Duration> {
+    let failures = FAILED_LOGINS.lock().unwrap();
+    let locked_until = failures.as_ref()?.get(username)?.locked_until?;
+    locked_until.checked_duration_since(Instant::now())
+}
+
+pub fn failed_logins(username: &str) -> u32 {
+    FAILED_LOGINS.lock().unwrap().as_ref().and_then(|failures| failures.get(username)).map_or(0, |entry| entry.count)
+}
+
+pub fn unlock(username: &str) {
+    if let Some(failures) = FAILED_LOGINS.lock().unwrap().as_mut() {
+        failures.remove(username);
+    }
+}
+
+// Hash of a random password, verified against for unknown usernames
+const DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$9ZCUT6EyrRDMnfLIVqzJcA$5g0EMi7XDCD2/WKdu5Xx8YmS8/NHnIYusCBHuyMG2+Y";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn users_file_is_replaced_privately() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("solara-users-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.toml");
+        // A file created by hand with the default umask
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        load_users(&path).unwrap();
+
+        add_user("alice", "correct horse").unwrap();
+        set_roles("alice", vec!["admin".to_string()]).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!dir.join("users.tmp").exists());
+
+        let stored = read_users(&path).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].roles, vec!["admin"]);
+        assert!(password::verify_password("correct horse", &stored[0].password_hash));
+
+        *USER_STORE.write().unwrap() = None;
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}